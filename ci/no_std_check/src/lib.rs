@@ -0,0 +1,18 @@
+#![no_std]
+
+extern crate codegen_runtime;
+
+use codegen_runtime::*;
+
+pub fn collections() -> u64 {
+    let pairs = cartesian_set!(set!{1, 2}, set!{'a', 'b'});
+    let squares = map!{1 => 1, 2 => 4};
+    let ordered = ord_map!{2 => seq!{1, 2}, 1 => seq!{}};
+
+    pairs.card() + squares.domain().card() + ordered.range().card() + ord_set!{mk_tuple!(1, true)}.card()
+}
+
+pub fn contracts(x: i64) -> Result<i64, VdmError> {
+    check_pre!(x > 0)?;
+    Ok(MATH::fac(x as u64) as i64)
+}
@@ -1,14 +1,340 @@
-use std::fmt::Debug;
+use lib::any::Any;
+use lib::{Box, String};
+use lib::sync::Mutex;
+use vdm_display::VdmDisplay;
+use Seq;
+use VdmError;
+#[cfg(feature = "std")]
+use std::fs::{self, OpenOptions};
+#[cfg(feature = "std")]
+use std::io::{self, Write};
+#[cfg(feature = "std")]
+use parse::FromVdmStr;
+#[cfg(feature = "std")]
+use Opt;
 
-pub fn writeval<T: Debug>(val: T) -> bool {
-    print!("{:?}", val);
+/// VDM `filedirective`: `<start>` overwrites the file, `<append>` appends to it.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub enum FileDirective {
+    Start,
+    Append,
+}
+
+/// Where `writeval`, `echo`, `print`, `println` and `printf` write to.
+/// Without `std` there is no stdout, so embedded targets register a
+/// writer for their UART or log buffer with `set_output`.
+pub trait Output: Send {
+    fn write_str(&mut self, text: &str);
+}
+
+static OUTPUT: Mutex<Option<Box<dyn Output>>> = Mutex::new(None);
+
+#[cfg(feature = "std")]
+static LAST_ERROR: Mutex<String> = Mutex::new(String::new());
+
+/// Redirects the console output of this module, which goes to stdout by
+/// default, or is discarded without `std`.
+pub fn set_output<O: Output + 'static>(output: O) {
+    *OUTPUT.lock() = Some(Box::new(output));
+}
+
+pub(crate) fn write_output(text: &str) {
+    match *OUTPUT.lock() {
+        Some(ref mut output) => output.write_str(text),
+        None => write_stdout(text),
+    }
+}
+
+/// The default output.
+#[cfg(feature = "std")]
+pub struct Stdout;
+
+#[cfg(feature = "std")]
+impl Output for Stdout {
+    fn write_str(&mut self, text: &str) {
+        print!("{}", text);
+    }
+}
+
+#[cfg(feature = "std")]
+fn write_stdout(text: &str) {
+    Stdout.write_str(text);
+}
+
+#[cfg(not(feature = "std"))]
+fn write_stdout(_text: &str) {}
+
+/// Prints `val` to stdout as the Overture interpreter does.
+pub fn writeval<T: VdmDisplay>(val: T) -> bool {
+    write_output(&val.to_vdm_string());
+    true
+}
+
+/// Writes `val` to `filename`, as `writeval` does to stdout.
+#[cfg(feature = "std")]
+pub fn fwriteval<F: Into<String>, T: VdmDisplay>(filename: F, val: T, fdir: FileDirective) -> bool {
+    write_file(&filename.into(), &val.to_vdm_string(), fdir)
+}
+
+/// Reads a value written by `fwriteval`. Yields `mk_(false, nil)` if the file
+/// cannot be read or does not hold a value of type `T`.
+#[cfg(feature = "std")]
+pub fn freadval<F: Into<String>, T: FromVdmStr>(filename: F) -> tuple_t!(bool, Opt<T>) {
+    let filename = filename.into();
+
+    let result = fs::read_to_string(&filename)
+                     .map_err(|err| format!("{}: {}", filename, err))
+                     .and_then(|text| T::from_vdm_str(&text).map_err(|err| err.to_string()));
+
+    match result {
+        Ok(val) => mk_tuple!(true, Opt::some(val)),
+        Err(msg) => {
+            set_error(msg);
+            mk_tuple!(false, Opt::nil())
+        }
+    }
+}
+
+/// Prints `text` to stdout without formatting.
+pub fn echo<S: Into<String>>(text: S) -> bool {
+    write_output(&text.into());
     true
 }
 
-pub fn print<T: Debug>(val: T) {
-    print!("{:?}", val);
+/// Writes `text` to `filename`, or to stdout if `filename` is empty.
+/// A `nil` directive overwrites the file.
+#[cfg(feature = "std")]
+pub fn fecho<F, S>(filename: F, text: S, fdir: Opt<FileDirective>) -> bool
+    where F: Into<String>,
+          S: Into<String>
+{
+    let filename = filename.into();
+
+    if filename.is_empty() {
+        echo(text)
+    } else {
+        write_file(&filename, &text.into(), fdir.unwrap_or(FileDirective::Start))
+    }
+}
+
+/// Returns the message of the last failed file operation.
+#[cfg(feature = "std")]
+pub fn ferror() -> Seq<char> {
+    let msg = LAST_ERROR.lock();
+    strseq!(msg)
+}
+
+/// Prints `val` like `writeval`, except that strings are printed without
+/// quotes.
+pub fn print<T: VdmDisplay + Any>(val: T) {
+    write_output(&string_of(&val));
+}
+
+pub fn println<T: VdmDisplay + Any>(val: T) {
+    let mut text = string_of(&val);
+    text.push('\n');
+    write_output(&text);
+}
+
+/// Prints `format` with each `%s` replaced by the next argument, and `%%`
+/// by `%`. Other conversions, or too few arguments, are a runtime error in
+/// Overture; they print nothing and give a `FormatError`.
+pub fn printf<S: Into<String>>(format: S, args: &[&dyn VdmDisplay]) -> Result<(), VdmError> {
+    write_output(&format_args(&format.into(), args)?);
+    Ok(())
+}
+
+fn string_of<T: VdmDisplay + Any>(val: &T) -> String {
+    let any = val as &dyn Any;
+
+    if let Some(text) = any.downcast_ref::<Seq<char>>() {
+        return text.iter().collect();
+    }
+
+    if let Some(text) = any.downcast_ref::<String>() {
+        text.clone()
+    } else {
+        val.to_vdm_string()
+    }
+}
+
+fn format_args(format: &str, args: &[&dyn VdmDisplay]) -> Result<String, VdmError> {
+    let mut result = String::new();
+    let mut args = args.iter();
+    let mut chars = format.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            result.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('s') => {
+                match args.next() {
+                    Some(arg) => result.push_str(&arg.to_vdm_string()),
+                    None => return Err(format_error("too few arguments", format)),
+                }
+            }
+            Some('%') => result.push('%'),
+            Some(other) => return Err(format_error(&format!("unsupported conversion %{}", other), format)),
+            None => return Err(format_error("incomplete conversion at the end", format)),
+        }
+    }
+
+    Ok(result)
+}
+
+fn format_error(problem: &str, format: &str) -> VdmError {
+    VdmError::FormatError(format!("{} in \"{}\"", problem, format))
 }
 
-pub fn println<T: Debug>(val: T) {
-    println!("{:?}", val);
+#[cfg(feature = "std")]
+fn write_file(filename: &str, text: &str, fdir: FileDirective) -> bool {
+    let result = OpenOptions::new()
+                     .write(true)
+                     .create(true)
+                     .append(fdir == FileDirective::Append)
+                     .truncate(fdir == FileDirective::Start)
+                     .open(filename)
+                     .and_then(|mut file| file.write_all(text.as_bytes()));
+
+    record(filename, result)
+}
+
+#[cfg(feature = "std")]
+fn record(filename: &str, result: io::Result<()>) -> bool {
+    match result {
+        Ok(()) => true,
+        Err(err) => {
+            set_error(format!("{}: {}", filename, err));
+            false
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn set_error(msg: String) {
+    *LAST_ERROR.lock() = msg;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::{ToOwned, ToString};
+    #[cfg(feature = "std")]
+    use std::{env, process};
+    use AsDebug;
+    #[cfg(feature = "std")]
+    use Opt;
+    use Set;
+
+    /// A file name of its own for each test and test process.
+    #[cfg(feature = "std")]
+    fn temp_file(name: &str) -> String {
+        let path = env::temp_dir().join(format!("codegen_runtime_io_{}_{}", process::id(), name));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn fwriteval_freadval() {
+        let file = temp_file("fwriteval");
+        let written = set!{1, 2, 3};
+
+        assert!(fwriteval(file.as_str(), written.clone(), FileDirective::Start));
+        assert_eq!(mk_tuple!(true, Opt::some(written)), freadval::<_, Set<i32>>(file.as_str()));
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn fecho_append() {
+        let file = temp_file("fecho");
+
+        assert!(fecho(file.as_str(), "a", Opt::nil()));
+        assert!(fecho(file.as_str(), strseq!("b"), Opt::some(FileDirective::Append)));
+        assert_eq!("ab", fs::read_to_string(&file).unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn freadval_missing_file() {
+        let (ok, val): (bool, Opt<i32>) = freadval(temp_file("missing")).into();
+
+        assert!(!ok);
+        assert!(val.is_nil());
+        assert!(String::from(ferror()).contains("missing"));
+    }
+
+    /// Runs `f` with the output redirected to a buffer, and returns what
+    /// it wrote. Tests writing output go through here, so that they do not
+    /// capture each other's output, and the previous output is restored
+    /// even if `f` panics.
+    fn capture_output<F: FnOnce()>(f: F) -> String {
+        static LOCK: Mutex<()> = Mutex::new(());
+        static CAPTURED: Mutex<String> = Mutex::new(String::new());
+
+        struct Capture;
+
+        impl Output for Capture {
+            fn write_str(&mut self, text: &str) {
+                CAPTURED.lock().push_str(text);
+            }
+        }
+
+        struct Restore(Option<Box<dyn Output>>);
+
+        impl Drop for Restore {
+            fn drop(&mut self) {
+                *OUTPUT.lock() = self.0.take();
+            }
+        }
+
+        let _lock = LOCK.lock();
+        CAPTURED.lock().clear();
+        let _restore = Restore(OUTPUT.lock().replace(Box::new(Capture)));
+        f();
+
+        CAPTURED.lock().clone()
+    }
+
+    #[test]
+    fn redirected_output() {
+        let captured = capture_output(|| {
+            println(strseq!("redirected"));
+            printf("%s;", &[&set!{7}]).unwrap();
+            assert!(printf("%s %s", &[&1]).is_err());
+        });
+
+        assert_eq!("redirected\n{7};", captured);
+        assert!(OUTPUT.lock().is_none());
+    }
+
+    #[test]
+    fn printf_substitution() {
+        assert_eq!(Ok("1 + 2 = 3%".to_owned()), format_args("%s + %s = %s%%", &[&1, &2, &3]));
+        assert_eq!(Ok("Some([1])".to_owned()), format_args("%s", &[&AsDebug(Some(vec![1]))]));
+    }
+
+    #[test]
+    fn printf_errors() {
+        assert_eq!(Err(VdmError::FormatError("too few arguments in \"%s, %s\"".to_owned())),
+                   format_args("%s, %s", &[&1]));
+        assert_eq!("Invalid format string: unsupported conversion %d in \"%d\"",
+                   format_args("%d", &[&1]).unwrap_err().to_string());
+        assert!(format_args("100%", &[]).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn fwriteval_strings() {
+        let file = temp_file("fwriteval_strings");
+        let written = seq![strseq!("a, b"), strseq!("c")];
+
+        assert!(fwriteval(file.as_str(), written.clone(), FileDirective::Start));
+        assert_eq!("[\"a, b\", \"c\"]", fs::read_to_string(&file).unwrap());
+        assert_eq!(mk_tuple!(true, Opt::some(written)),
+                   freadval::<_, Seq<Seq<char>>>(file.as_str()));
+        assert_eq!("abc", string_of(&strseq!("abc")));
+    }
 }
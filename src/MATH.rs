@@ -1,104 +1,422 @@
-use std::sync::Mutex;
-use rand::{Rng, SeedableRng, StdRng};
+#[cfg(feature = "std")]
+use lib::cell::RefCell;
+use lib::{Box, ToOwned};
+#[cfg(not(feature = "std"))]
+use lib::sync::Mutex;
 
 use F64;
+use BigInt;
+use real::{Real, Fixed, Decimal};
+use Rat;
+use random::{Generator, JavaRandom, RandomSource};
+use error::unwrap_vdm;
+use VdmError;
 
-use std::f64::consts::PI as math_pi;
+use lib::f64::consts::PI as math_pi;
 
 pub const pi: F64 = F64(math_pi);
 
-pub fn sin(v: F64) -> F64 {
-    F64(v.0.sin())
+/// Fails with the precondition `pre` unless `holds`.
+fn require(pre: &str, holds: bool) -> Result<(), VdmError> {
+    if holds {
+        Ok(())
+    } else {
+        Err(VdmError::PreconditionViolated(pre.to_owned()))
+    }
+}
+
+/// Errors of a `MATH` function whose precondition holds are those of its
+/// `Real` kernel; a result that is not a number breaks the precondition.
+fn checked<R: Real>(pre: &str, result: Result<R, VdmError>) -> Result<R, VdmError> {
+    match result {
+        Err(VdmError::NotANumber) => Err(VdmError::PreconditionViolated(pre.to_owned())),
+        result => result,
+    }
+}
+
+fn int<R: Real>(val: i64) -> R {
+    unwrap_vdm(R::try_from_int(val))
+}
+
+/// Whether `v` is zero to within the rounding of `R`.
+fn is_zero<R: Real>(v: R) -> bool {
+    v.max(-v) < R::epsilon()
+}
+
+pub fn sin<R: Real>(v: R) -> R {
+    unwrap_vdm(try_sin(v))
+}
+
+pub fn try_sin<R: Real>(v: R) -> Result<R, VdmError> {
+    checked("pre_sin", v.try_sin())
+}
+
+pub fn cos<R: Real>(v: R) -> R {
+    unwrap_vdm(try_cos(v))
+}
+
+pub fn try_cos<R: Real>(v: R) -> Result<R, VdmError> {
+    checked("pre_cos", v.try_cos())
+}
+
+pub fn tan<R: Real>(v: R) -> R {
+    unwrap_vdm(try_tan(v))
+}
+
+/// Requires `cos(v) <> 0`.
+pub fn try_tan<R: Real>(v: R) -> Result<R, VdmError> {
+    require("pre_tan", !is_zero(checked("pre_tan", v.try_cos())?))?;
+    checked("pre_tan", v.try_tan())
+}
+
+pub fn cot<R: Real>(v: R) -> R {
+    unwrap_vdm(try_cot(v))
+}
+
+/// Requires `sin(v) <> 0`.
+pub fn try_cot<R: Real>(v: R) -> Result<R, VdmError> {
+    require("pre_cot", !is_zero(checked("pre_cot", v.try_sin())?))?;
+    int::<R>(1).try_div(checked("pre_cot", v.try_tan())?)
+}
+
+pub fn asin<R: Real>(v: R) -> R {
+    unwrap_vdm(try_asin(v))
+}
+
+/// Requires `-1 <= v <= 1`.
+pub fn try_asin<R: Real>(v: R) -> Result<R, VdmError> {
+    require("pre_asin", int::<R>(-1) <= v && v <= int(1))?;
+    checked("pre_asin", v.try_asin())
+}
+
+pub fn acos<R: Real>(v: R) -> R {
+    unwrap_vdm(try_acos(v))
+}
+
+/// Requires `-1 <= v <= 1`.
+pub fn try_acos<R: Real>(v: R) -> Result<R, VdmError> {
+    require("pre_acos", int::<R>(-1) <= v && v <= int(1))?;
+    checked("pre_acos", v.try_acos())
+}
+
+pub fn atan<R: Real>(v: R) -> R {
+    unwrap_vdm(try_atan(v))
 }
 
-pub fn cos(v: F64) -> F64 {
-    F64(v.0.cos())
+pub fn try_atan<R: Real>(v: R) -> Result<R, VdmError> {
+    checked("pre_atan", v.try_atan())
 }
 
-pub fn cot(v: F64) -> F64 {
-    F64(1.0 / v.0.tan())
+pub fn acot<R: Real>(v: R) -> R {
+    unwrap_vdm(try_acot(v))
 }
 
-pub fn asin(v: F64) -> F64 {
-    F64(v.0.asin())
+/// Requires `v <> 0`.
+pub fn try_acot<R: Real>(v: R) -> Result<R, VdmError> {
+    require("pre_acot", v != int(0))?;
+    checked("pre_acot", int::<R>(1).try_div(v)?.try_atan())
 }
 
-pub fn atan(v: F64) -> F64 {
-    F64(v.0.atan())
+pub fn sqrt<R: Real>(v: R) -> R {
+    unwrap_vdm(try_sqrt(v))
 }
 
-pub fn acot(v: F64) -> F64 {
-    F64((1.0 / v.0).atan())
+/// Requires `v >= 0`.
+pub fn try_sqrt<R: Real>(v: R) -> Result<R, VdmError> {
+    require("pre_sqrt", v >= int(0))?;
+    checked("pre_sqrt", v.try_sqrt())
+}
+
+pub fn pi_f<R: Real>() -> R {
+    R::pi()
+}
+
+#[cfg(feature = "std")]
+thread_local! {
+    static GENERATOR: RefCell<Generator<Box<dyn RandomSource>>> =
+        RefCell::new(Generator::new(Box::new(JavaRandom::from_time())));
+}
+
+/// Without `std` there is neither a clock nor threads: one generator is
+/// shared, and it starts seeded with 0 until `srand` is called.
+#[cfg(not(feature = "std"))]
+static GENERATOR: Mutex<Option<Generator<Box<dyn RandomSource + Send>>>> = Mutex::new(None);
+
+#[cfg(feature = "std")]
+fn with_generator<T, F>(f: F) -> T
+    where F: FnOnce(&mut Generator<Box<dyn RandomSource>>) -> T
+{
+    GENERATOR.with(|g| f(&mut g.borrow_mut()))
 }
 
-pub fn sqrt(v: F64) -> F64 {
-    F64(v.0.sqrt())
+#[cfg(not(feature = "std"))]
+fn with_generator<T, F>(f: F) -> T
+    where F: FnOnce(&mut Generator<Box<dyn RandomSource + Send>>) -> T
+{
+    let mut generator = GENERATOR.lock();
+    f(generator.get_or_insert_with(|| Generator::new(Box::new(JavaRandom::new(0)))))
 }
 
-pub fn pi_f() -> F64 {
-    pi
+/// Replaces the random source of the current thread, e.g. to make
+/// `rand` deterministic without calling `srand`. Each thread starts with
+/// a `JavaRandom` seeded from the clock, as in Overture.
+#[cfg(feature = "std")]
+pub fn set_random_source<R: RandomSource + 'static>(source: R) {
+    with_generator(|g| *g = Generator::new(Box::new(source)));
 }
 
-lazy_static! {
-	static ref RND: Mutex<Option<StdRng>> = Mutex::new(None);
+#[cfg(not(feature = "std"))]
+pub fn set_random_source<R: RandomSource + Send + 'static>(source: R) {
+    with_generator(|g| *g = Generator::new(Box::new(source)));
 }
 
+/// Seeds the random generator of the current thread. With the same seed,
+/// `rand` yields the same numbers as in the Overture interpreter. A seed
+/// of -1 makes `rand` return its argument.
 pub fn srand(seed: i64) {
-    srand2(seed);
+    with_generator(|g| g.srand(seed))
 }
 
+/// Returns a random number in `[0, |top|)`.
 pub fn rand(top: i64) -> i64 {
-    if top == 0 {
-        return 0;
+    with_generator(|g| g.rand(top))
+}
+
+pub fn srand2(seed: i64) -> i64 {
+    with_generator(|g| g.srand2(seed))
+}
+
+pub fn exp<R: Real>(v: R) -> R {
+    unwrap_vdm(try_exp(v))
+}
+
+pub fn try_exp<R: Real>(v: R) -> Result<R, VdmError> {
+    checked("pre_exp", v.try_exp())
+}
+
+pub fn ln<R: Real>(v: R) -> R {
+    unwrap_vdm(try_ln(v))
+}
+
+/// Requires `v > 0`.
+pub fn try_ln<R: Real>(v: R) -> Result<R, VdmError> {
+    require("pre_ln", v > int(0))?;
+    checked("pre_ln", v.try_ln())
+}
+
+pub fn log<R: Real>(v: R) -> R {
+    unwrap_vdm(try_log(v))
+}
+
+/// Requires `v > 0`.
+pub fn try_log<R: Real>(v: R) -> Result<R, VdmError> {
+    require("pre_log", v > int(0))?;
+    checked("pre_log", v.try_log10())
+}
+
+/// Numeric types `fac` is defined for.
+pub trait Fac: Sized {
+    fn try_fac(&self) -> Result<Self, VdmError>;
+
+    fn fac(&self) -> Self {
+        unwrap_vdm(self.try_fac())
     }
+}
 
-    let rnd = match RND.lock() {
-        Ok(rnd) => rnd,
-        Err(poisoned) => poisoned.into_inner(),
-    };
-	
-    if rnd.is_some() {
-        rnd.unwrap().gen::<i64>().abs() % top
-    } else {
-        top
+/// `fac` of a `u64` requires `v < 21`, as larger results overflow;
+/// use `BigInt` for those.
+impl Fac for u64 {
+    fn try_fac(&self) -> Result<u64, VdmError> {
+        if *self >= 21 {
+            return Err(VdmError::PreconditionViolated("pre_fac".to_owned()));
+        }
+
+        Ok((1..*self + 1).product())
     }
 }
 
-pub fn srand2(seed: i64) -> i64 {
-    let seedSlice: &[_] = &[seed as usize];
+impl Fac for BigInt {
+    fn try_fac(&self) -> Result<BigInt, VdmError> {
+        if *self < BigInt::zero() {
+            return Err(VdmError::PreconditionViolated("pre_fac".to_owned()));
+        }
 
-    let mut rnd = match RND.lock() {
-        Ok(rnd) => rnd,
-        Err(poisoned) => poisoned.into_inner(),
-    };
+        let mut result = BigInt::one();
+        let mut i = BigInt::one();
+        while i <= *self {
+            result = result * i.clone();
+            i = i + BigInt::one();
+        }
+        Ok(result)
+    }
+}
 
-    if rnd.is_some() {
-        rnd.unwrap().reseed(seedSlice);
-    } else {
-        *rnd = Some(SeedableRng::from_seed(seedSlice));
+/// `fac` of a `u64`; use `Fac::fac` for a `BigInt`.
+pub fn fac(v: u64) -> u64 {
+    v.fac()
+}
+
+pub fn try_fac(v: u64) -> Result<u64, VdmError> {
+    v.try_fac()
+}
+
+/// Numeric types the VDM `floor` operator is defined for.
+pub trait Floor {
+    type Output;
+
+    fn floor(&self) -> Self::Output;
+}
+
+impl Floor for F64 {
+    type Output = i64;
+
+    fn floor(&self) -> i64 {
+        F64::floor(self)
     }
+}
 
-    seed
+impl Floor for BigInt {
+    type Output = BigInt;
+
+    fn floor(&self) -> BigInt {
+        BigInt::floor(self)
+    }
 }
 
-pub fn exp(v: F64) -> F64 {
-    F64(v.0.exp())
+impl Floor for Rat {
+    type Output = BigInt;
+
+    fn floor(&self) -> BigInt {
+        Rat::floor(self)
+    }
 }
 
-pub fn ln(v: F64) -> F64 {
-    F64(v.0.ln())
+impl<const FRAC: u32> Floor for Fixed<FRAC> {
+    type Output = i64;
+
+    fn floor(&self) -> i64 {
+        Real::floor(*self)
+    }
 }
 
-pub fn log(v: F64) -> F64 {
-    F64(v.0.log10())
+impl<const PLACES: u32> Floor for Decimal<PLACES> {
+    type Output = i64;
+
+    fn floor(&self) -> i64 {
+        Real::floor(*self)
+    }
 }
 
-pub fn fac(v: u64) -> u64 {
-    assert!(v < 21);
+pub fn floor<T: Floor>(v: T) -> T::Output {
+    v.floor()
+}
 
-    if v == 0 || v == 1 {
-        1
-    } else {
-        v * fac(v - 1)
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::{ToString, Vec};
+    use real::{Q32_32, Q48_16};
+    use F64;
+    use BigInt;
+    use Rat;
+
+    #[test]
+    fn fac_1() {
+        assert_eq!(1, fac(0));
+        assert_eq!(120, fac(5));
+    }
+
+    #[test]
+    fn fac_big() {
+        let f = BigInt::from(25i64).fac();
+        assert_eq!("15511210043330985984000000", f.to_string());
+    }
+
+    #[test]
+    fn rand_reproducible() {
+        srand(2024);
+        let first: Vec<i64> = (0..3).map(|_| rand(100)).collect();
+
+        assert_eq!(2024, srand2(2024));
+        assert_eq!(first, (0..3).map(|_| rand(100)).collect::<Vec<_>>());
+
+        srand(-1);
+        assert_eq!(100, rand(100));
+    }
+
+    #[test]
+    fn fac_domain() {
+        assert_eq!(2432902008176640000, fac(20u64));
+        assert!(try_fac(21u64).is_err());
+        assert_eq!("51090942171709440000", BigInt::from(21i64).fac().to_string());
+        assert!(BigInt::from(-1i64).try_fac().is_err());
+    }
+
+    #[test]
+    fn trigonometry() {
+        assert!((tan(pi / F64(4.0)).0 - 1.0).abs() < 1e-12);
+        assert_eq!(F64(0.0), acos(F64(1.0)));
+        assert!(try_asin(F64(1.5)).is_err());
+        assert!(try_acos(F64(-1.5)).is_err());
+        assert!(try_cot(F64(0.0)).is_err());
+        assert!(try_tan(pi / F64(2.0)).is_err());
+        assert!(try_tan(F64(-3.0) * pi / F64(2.0)).is_err());
+        assert!(try_cot(pi).is_err());
+        assert!(try_acot(F64(0.0)).is_err());
+        assert!(try_sin(F64(f64::INFINITY)).is_err());
+    }
+
+    #[test]
+    fn logarithms() {
+        assert_eq!(F64(2.0), log(F64(100.0)));
+        assert_eq!(F64(0.0), ln(F64(1.0)));
+        assert_eq!(Err(VdmError::PreconditionViolated("pre_ln".to_owned())), try_ln(F64(0.0)));
+        assert!(try_log(F64(-1.0)).is_err());
+        assert!(try_sqrt(F64(-1.0)).is_err());
+        assert_eq!(F64(3.0), sqrt(F64(9.0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Precondition violated: pre_sqrt")]
+    fn sqrt_panics() {
+        sqrt(F64(-4.0));
+    }
+
+    #[test]
+    fn generic_reals() {
+        let x = Decimal::<6>::from_f64(6.25);
+
+        assert_eq!(Decimal::from_f64(2.5), sqrt(x));
+        assert_eq!(Q32_32::from_f64(math_pi), pi_f());
+        assert!(try_ln(Fixed::<16>::from_int(0)).is_err());
+        assert_eq!(Err(VdmError::ArithmeticOverflow), try_exp(Fixed::<32>::from_int(30)));
+        assert_eq!(-2, floor(Q32_32::from_f64(-1.5)));
+    }
+
+    #[test]
+    fn scaled_reals() {
+        let half = Decimal::<9>::from_f64(0.5);
+
+        assert_eq!(Decimal::from_raw(479425539), sin(half));
+        assert_eq!(Decimal::from_raw(877582562), cos(half));
+        assert_eq!(Decimal::from_raw(523598776), asin(half));
+        assert_eq!(Decimal::from_raw(1047197551), acos(half));
+        assert_eq!(Decimal::from_raw(1648721271), exp(half));
+        assert_eq!(Decimal::from_raw(-693147181), ln(half));
+        assert_eq!(Decimal::from_f64(2.0), log(Decimal::<9>::from_int(100)));
+        assert_eq!(Decimal::from_raw(546302490), tan(half));
+        assert_eq!(Q48_16::from_f64(0.785400390625), atan(Q48_16::from_int(1)));
+        assert_eq!(Q48_16::from_f64(1.1071472167), acot(Q48_16::from_f64(0.5)));
+        assert!(try_asin(Q48_16::from_f64(1.5)).is_err());
+        assert!(try_cot(Q48_16::from_int(0)).is_err());
+        assert!(try_tan(pi_f::<Decimal<9>>() / Decimal::from_int(2)).is_err());
+        assert_eq!(Err(VdmError::PreconditionViolated("pre_log".to_owned())), try_log(Decimal::<9>::from_int(-1)));
+        assert_eq!(Err(VdmError::ArithmeticOverflow), Fixed::<62>::from_raw(1).try_ln());
+    }
+
+    #[test]
+    fn floor_1() {
+        assert_eq!(-2, floor(F64(-1.5)));
+        assert_eq!(BigInt::from(2i64), floor(Rat::from(5i64) / Rat::from(2i64)));
     }
 }
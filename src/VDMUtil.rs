@@ -1,12 +1,123 @@
 use Set;
 use Seq;
-use std::hash::Hash;
-use std::fmt::Debug;
+use Opt;
+use Class;
+use parse::FromVdmStr;
+use lib::cmp::Ordering;
+use lib::hash::Hash;
+use lib::{String, Vec};
+use vdm_display::VdmDisplay;
 
-pub fn set2seq<T: Hash + Eq + Clone>(set: Set<T>) -> Seq<T> {
-    set.iter().cloned().collect()
+/// Converts a set to a sequence in the order Overture prints the set, so
+/// that the result is the same in every run.
+pub fn set2seq<T: Hash + Eq + Clone + VdmDisplay>(set: Set<T>) -> Seq<T> {
+    set2seq_by(set, T::vdm_cmp)
 }
 
-pub fn val2seq_of_char<T: Debug>(val: T) -> Seq<char> {
-    strseq!(format!("{:?}", val))
+/// Converts a set to a sequence ordered by `compare`.
+pub fn set2seq_by<T, F>(set: Set<T>, compare: F) -> Seq<T>
+    where T: Hash + Eq + Clone,
+          F: FnMut(&T, &T) -> Ordering
+{
+    let mut elems: Vec<T> = set.into_iter().collect();
+    elems.sort_by(compare);
+    elems.into_iter().collect()
+}
+
+/// Prints `val` as the Overture interpreter does.
+pub fn val2seq_of_char<T: VdmDisplay>(val: T) -> Seq<char> {
+    strseq!(val.to_vdm_string())
+}
+
+/// Inverse of `val2seq_of_char`. Yields `mk_(false, nil)` if `text` is not
+/// a value of type `T`.
+pub fn seq_of_char2val<S: Into<String>, T: FromVdmStr>(text: S) -> tuple_t!(bool, Opt<T>) {
+    match T::from_vdm_str(&text.into()) {
+        Ok(val) => mk_tuple!(true, Opt::some(val)),
+        Err(_) => mk_tuple!(false, Opt::nil()),
+    }
+}
+
+/// Values that are not objects have no class name; the generated code
+/// uses `nil` for those directly.
+pub fn classname<T: Class + ?Sized>(val: &T) -> Opt<Seq<char>> {
+    Opt::some(strseq!(val.class().name))
+}
+
+/// Expands to the position of its call as the VDM tuple
+/// `mk_(file, line, column, module, function)`, wrapped in an `Opt`.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate codegen_runtime;
+/// fn main() {
+///     let pos = get_file_pos!().get();
+///
+///     assert_eq!("main", String::from(tuple_field!(pos, 5)));
+/// }
+/// ```
+#[macro_export]
+macro_rules! get_file_pos {
+    () => {{
+        fn f() {}
+        let name = $crate::VDMUtil::type_name_of(f);
+        let function = name.trim_end_matches("::f").rsplit("::").next().unwrap_or(name);
+
+        let seq = |s: &str| -> $crate::Seq<char> { s.chars().collect() };
+
+        $crate::Opt::some($crate::mk_tuple!(seq(file!()),
+                                            line!() as u64,
+                                            column!() as u64,
+                                            seq(module_path!()),
+                                            seq(function)))
+    }};
+}
+
+#[doc(hidden)]
+pub fn type_name_of<T>(_: T) -> &'static str {
+    ::lib::any::type_name::<T>()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Opt;
+    use Set;
+    use Seq;
+
+    struct Account;
+
+    impl_class! { Account {} }
+
+    #[test]
+    fn set2seq_orders() {
+        let s = set!{3, 1, 2};
+
+        assert_eq!(seq![1, 2, 3], set2seq(s.clone()));
+        assert_eq!(seq![set!{1, 2}, set!{1}, set!{3}], set2seq(set!{set!{3}, set!{1, 2}, set!{1}}));
+        assert_eq!(seq![3, 2, 1], set2seq_by(s, |a, b| b.cmp(a)));
+    }
+
+    #[test]
+    fn seq_of_char2val_round_trip() {
+        let text = val2seq_of_char(set!{1, 2});
+
+        assert_eq!(mk_tuple!(true, Opt::some(set!{1, 2})), seq_of_char2val::<_, Set<i32>>(text));
+        assert_eq!(mk_tuple!(false, Opt::nil()), seq_of_char2val::<_, Seq<i32>>("[1,"));
+    }
+
+    #[test]
+    fn classname_of_object() {
+        assert_eq!(Opt::some(strseq!("Account")), classname(&Account));
+    }
+
+    #[test]
+    fn file_pos() {
+        let pos = get_file_pos!().get();
+
+        assert!(String::from(tuple_field!(pos, 1)).ends_with("VDMUtil.rs"));
+        assert_eq!("codegen_runtime::VDMUtil::tests", String::from(tuple_field!(pos, 4)));
+        assert_eq!("file_pos", String::from(tuple_field!(pos, 5)));
+    }
 }
@@ -0,0 +1,240 @@
+use lib::ops::{Add, Sub, Mul, Div, Neg};
+use lib::convert::TryFrom;
+use lib::fmt;
+use num_traits::Float;
+use num_bigint;
+use num_traits::{Signed, ToPrimitive, Zero, One};
+use num_rational::BigRational;
+use error::unwrap_vdm;
+use F64;
+use Rat;
+use VdmError;
+
+/// Arbitrary-precision integer for VDM `int`, `nat` and `nat1` values
+/// that do not fit in 64 bits.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct BigInt(pub num_bigint::BigInt);
+
+impl BigInt {
+    pub fn zero() -> BigInt {
+        BigInt(num_bigint::BigInt::zero())
+    }
+
+    pub fn one() -> BigInt {
+        BigInt(num_bigint::BigInt::one())
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    pub fn floor(&self) -> BigInt {
+        self.clone()
+    }
+
+    pub fn abs(&self) -> BigInt {
+        BigInt(self.0.abs())
+    }
+
+    pub fn pow(&self, exp: u32) -> BigInt {
+        BigInt(self.0.pow(exp))
+    }
+
+    /// VDM `div`, truncating towards zero. Panics on division by zero.
+    /// Named apart from `Div::div`, which is VDM `/` yielding a `Rat`.
+    pub fn int_div(&self, other: BigInt) -> BigInt {
+        unwrap_vdm(self.try_int_div(other))
+    }
+
+    pub fn try_int_div(&self, other: BigInt) -> Result<BigInt, VdmError> {
+        if other.is_zero() {
+            return Err(VdmError::DivisionByZero);
+        }
+
+        Ok(BigInt(&self.0 / other.0))
+    }
+
+    /// VDM `rem`, the result has the sign of `self`.
+    pub fn rem(&self, other: BigInt) -> BigInt {
+        unwrap_vdm(self.try_rem(other))
+    }
+
+    pub fn try_rem(&self, other: BigInt) -> Result<BigInt, VdmError> {
+        if other.is_zero() {
+            return Err(VdmError::DivisionByZero);
+        }
+
+        Ok(BigInt(&self.0 % other.0))
+    }
+
+    /// VDM `mod`, the result has the sign of `other`.
+    pub fn modulo(&self, other: BigInt) -> BigInt {
+        unwrap_vdm(self.try_modulo(other))
+    }
+
+    pub fn try_modulo(&self, other: BigInt) -> Result<BigInt, VdmError> {
+        let r = self.try_rem(other.clone())?;
+
+        if !r.is_zero() && r.0.is_negative() != other.0.is_negative() {
+            Ok(r + other)
+        } else {
+            Ok(r)
+        }
+    }
+
+    /// VDM `/`, as `Div::div`.
+    pub fn try_div(&self, other: BigInt) -> Result<Rat, VdmError> {
+        Rat::from(self.clone()).try_div(Rat::from(other))
+    }
+
+    /// Converts to `i64`, if the value is in range.
+    pub fn to_i64(&self) -> Option<i64> {
+        self.0.to_i64()
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Debug for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+macro_rules! from_impl {
+    ($($t:ty)*) => ($(
+        impl From<$t> for BigInt {
+            fn from(val: $t) -> BigInt {
+                BigInt(num_bigint::BigInt::from(val))
+            }
+        }
+    )*)
+}
+
+from_impl! { u64 i32 i64 }
+
+impl From<num_bigint::BigInt> for BigInt {
+    fn from(val: num_bigint::BigInt) -> BigInt {
+        BigInt(val)
+    }
+}
+
+/// Truncates towards zero, like the `From<F64>` conversions for the
+/// primitive integers. NaN and the infinities are not integers.
+impl TryFrom<F64> for BigInt {
+    type Error = VdmError;
+
+    fn try_from(val: F64) -> Result<BigInt, VdmError> {
+        let int = BigRational::from_float(Float::trunc(val.0)).ok_or(VdmError::NotANumber)?;
+        Ok(BigInt(int.to_integer()))
+    }
+}
+
+impl From<BigInt> for F64 {
+    fn from(val: BigInt) -> F64 {
+        F64(val.0.to_f64().expect("BigInt is not representable as F64."))
+    }
+}
+
+impl Add for BigInt {
+    type Output = BigInt;
+
+    #[inline]
+    fn add(self, other: BigInt) -> BigInt {
+        BigInt(self.0 + other.0)
+    }
+}
+
+impl Sub for BigInt {
+    type Output = BigInt;
+
+    #[inline]
+    fn sub(self, other: BigInt) -> BigInt {
+        BigInt(self.0 - other.0)
+    }
+}
+
+impl Mul for BigInt {
+    type Output = BigInt;
+
+    #[inline]
+    fn mul(self, other: BigInt) -> BigInt {
+        BigInt(self.0 * other.0)
+    }
+}
+
+/// VDM `/` on integers yields a real, so the quotient is an exact `Rat`.
+impl Div for BigInt {
+    type Output = Rat;
+
+    #[inline]
+    fn div(self, other: BigInt) -> Rat {
+        unwrap_vdm(self.try_div(other))
+    }
+}
+
+impl Neg for BigInt {
+    type Output = BigInt;
+
+    #[inline]
+    fn neg(self) -> BigInt {
+        BigInt(-self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::ToString;
+    use F64;
+    use Rat;
+
+    #[test]
+    fn add_1() {
+        let i = BigInt::from(i64::MAX) + BigInt::from(1i64);
+        assert_eq!("9223372036854775808", i.to_string());
+    }
+
+    #[test]
+    fn div_1() {
+        let r = BigInt::from(1i64) / BigInt::from(3i64);
+        assert_eq!(Rat::new(BigInt::from(1i64), BigInt::from(3i64)), r);
+    }
+
+    #[test]
+    fn div_mod_rem() {
+        let x = BigInt::from(-14i64);
+        let y = BigInt::from(3i64);
+
+        assert_eq!(BigInt::from(-4i64), x.int_div(y.clone()));
+        assert_eq!(BigInt::from(-2i64), x.rem(y.clone()));
+        assert_eq!(BigInt::from(1i64), x.modulo(y));
+    }
+
+    #[test]
+    fn div_zero() {
+        let (x, zero) = (BigInt::from(7i64), BigInt::zero());
+
+        assert_eq!(Err(VdmError::DivisionByZero), x.try_int_div(zero.clone()));
+        assert_eq!(Err(VdmError::DivisionByZero), x.try_rem(zero.clone()));
+        assert_eq!(Err(VdmError::DivisionByZero), x.try_modulo(zero.clone()));
+        assert_eq!(Err(VdmError::DivisionByZero), x.try_div(zero));
+    }
+
+    #[test]
+    #[should_panic(expected = "Division by zero.")]
+    fn int_div_zero() {
+        let _ = BigInt::from(7i64).int_div(BigInt::zero());
+    }
+
+    #[test]
+    fn from_f64() {
+        assert_eq!(Ok(BigInt::from(-2i64)), BigInt::try_from(F64(-2.7)));
+        assert_eq!(Err(VdmError::NotANumber), BigInt::try_from(F64(f64::INFINITY)));
+        assert_eq!(F64(42.0), F64::from(BigInt::from(42i64)));
+    }
+}
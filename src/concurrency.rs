@@ -0,0 +1,533 @@
+//! VDM++ and VDM-RT concurrency: `sync` permission predicates, `mutex`
+//! constraints and the history counters they are written in, and
+//! procedural, periodic and sporadic threads.
+//!
+//! Each object with a `sync` clause owns a `Monitor`, and its generated
+//! operations run their bodies through `Monitor::call`.
+
+use lib::collections::{BTreeMap, HashMap};
+use lib::fmt;
+use lib::{Box, Vec};
+use lib::sync::Mutex;
+use random::{Generator, JavaRandom};
+use std::sync::{Arc, Condvar};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug)]
+struct Counters {
+    req: u64,
+    act: u64,
+    fin: u64,
+}
+
+/// The history of the operations of one object. Operations that were
+/// never called have all counters at 0.
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct History {
+    counters: BTreeMap<&'static str, Counters>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    /// `#req(op)`: the number of calls of `op`.
+    pub fn req(&self, op: &str) -> u64 {
+        self.get(op).req
+    }
+
+    /// `#act(op)`: the number of activations of `op`.
+    pub fn act(&self, op: &str) -> u64 {
+        self.get(op).act
+    }
+
+    /// `#fin(op)`: the number of completed activations of `op`.
+    pub fn fin(&self, op: &str) -> u64 {
+        self.get(op).fin
+    }
+
+    /// `#active(op)`, i.e. `#act(op) - #fin(op)`.
+    pub fn active(&self, op: &str) -> u64 {
+        let c = self.get(op);
+        c.act - c.fin
+    }
+
+    /// `#waiting(op)`, i.e. `#req(op) - #act(op)`.
+    pub fn waiting(&self, op: &str) -> u64 {
+        let c = self.get(op);
+        c.req - c.act
+    }
+
+    fn get(&self, op: &str) -> Counters {
+        self.counters.get(op).cloned().unwrap_or_default()
+    }
+
+    fn counters(&mut self, op: &'static str) -> &mut Counters {
+        self.counters.entry(op).or_default()
+    }
+}
+
+type Permission = Box<dyn Fn(&History) -> bool + Send + Sync>;
+
+/// The synchronisation of one object: its `History`, and the permission
+/// predicates of its `sync` clause. A call of an operation is blocked
+/// until all predicates of that operation hold.
+///
+/// # Examples
+///
+/// ```
+/// use codegen_runtime::concurrency::Monitor;
+///
+/// // sync
+/// //   per Get => #fin(Put) > #fin(Get);
+/// //   mutex(Put, Get)
+/// let monitor = Monitor::new()
+///     .per("Get", |h| h.fin("Put") > h.fin("Get"))
+///     .mutex(&["Put", "Get"]);
+///
+/// monitor.call("Put", || ());
+///
+/// assert_eq!(1, monitor.history().fin("Put"));
+/// assert_eq!(7, monitor.call("Get", || 7));
+/// ```
+pub struct Monitor {
+    history: Mutex<History>,
+    changed: Condvar,
+    permissions: HashMap<&'static str, Vec<Permission>>,
+}
+
+impl Monitor {
+    pub fn new() -> Monitor {
+        Monitor {
+            history: Mutex::new(History::new()),
+            changed: Condvar::new(),
+            permissions: HashMap::new(),
+        }
+    }
+
+    /// Adds `per op => pred`. Predicates added for the same operation must
+    /// all hold.
+    pub fn per<F>(mut self, op: &'static str, pred: F) -> Monitor
+        where F: Fn(&History) -> bool + Send + Sync + 'static
+    {
+        self.permissions.entry(op).or_default().push(Box::new(pred));
+        self
+    }
+
+    /// Adds `mutex(ops)`: none of `ops` is activated while one of them is
+    /// active. For `mutex(all)`, pass all public operations of the class.
+    pub fn mutex(self, ops: &[&'static str]) -> Monitor {
+        let ops: Vec<&'static str> = ops.to_vec();
+
+        ops.clone().into_iter().fold(self, |monitor, op| {
+            let ops = ops.clone();
+            monitor.per(op, move |h| ops.iter().all(|o| h.active(o) == 0))
+        })
+    }
+
+    /// Runs `body` as an activation of `op`, once its permission
+    /// predicates hold. The history counts the activation as finished
+    /// even if `body` panics.
+    pub fn call<T, F: FnOnce() -> T>(&self, op: &'static str, body: F) -> T {
+        let mut history = self.history.lock();
+        history.counters(op).req += 1;
+        self.changed.notify_all();
+
+        while !self.permitted(op, &history) {
+            history = self.changed.wait(history).unwrap_or_else(|poisoned| poisoned.into_inner());
+        }
+
+        history.counters(op).act += 1;
+        drop(history);
+
+        let _finish = Finish { monitor: self, op };
+        body()
+    }
+
+    /// Counts a call of `op` as requested, without waiting for it. With
+    /// `try_activate` and `finish`, this is `call` split in steps for
+    /// schedulers that cannot block a thread, such as `rt::Simulator`.
+    pub fn request(&self, op: &'static str) {
+        self.history.lock().counters(op).req += 1;
+        self.changed.notify_all();
+    }
+
+    /// Activates a requested call of `op` if its permission predicates
+    /// hold, and returns whether it did.
+    pub fn try_activate(&self, op: &'static str) -> bool {
+        let mut history = self.history.lock();
+
+        if !self.permitted(op, &history) {
+            return false;
+        }
+
+        history.counters(op).act += 1;
+        true
+    }
+
+    /// Counts an activation of `op` as finished.
+    pub fn finish(&self, op: &'static str) {
+        self.history.lock().counters(op).fin += 1;
+        self.changed.notify_all();
+    }
+
+    /// Re-evaluates the predicates of waiting calls. Needed after a change
+    /// to an instance variable that a predicate reads.
+    pub fn notify(&self) {
+        let _history = self.history.lock();
+        self.changed.notify_all();
+    }
+
+    /// A snapshot of the history counters.
+    pub fn history(&self) -> History {
+        self.history.lock().clone()
+    }
+
+    fn permitted(&self, op: &str, history: &History) -> bool {
+        match self.permissions.get(op) {
+            Some(preds) => preds.iter().all(|pred| pred(history)),
+            None => true,
+        }
+    }
+}
+
+impl Default for Monitor {
+    fn default() -> Monitor {
+        Monitor::new()
+    }
+}
+
+impl fmt::Debug for Monitor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Monitor").field("history", &self.history()).finish()
+    }
+}
+
+struct Finish<'a> {
+    monitor: &'a Monitor,
+    op: &'static str,
+}
+
+impl<'a> Drop for Finish<'a> {
+    fn drop(&mut self) {
+        self.monitor.finish(self.op);
+    }
+}
+
+/// The time threads are released at, in time units. Under `SystemClock`
+/// a unit is a nanosecond of wall-clock time, as in the Overture
+/// interpreter.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> u64;
+
+    /// Blocks the calling thread until `now() >= time`.
+    fn wait_until(&self, time: u64);
+}
+
+/// Wall-clock time since the creation of the clock.
+#[derive(Clone, Copy, Debug)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl SystemClock {
+    pub fn new() -> SystemClock {
+        SystemClock { start: Instant::now() }
+    }
+}
+
+impl Default for SystemClock {
+    fn default() -> SystemClock {
+        SystemClock::new()
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&self) -> u64 {
+        self.start.elapsed().as_nanos() as u64
+    }
+
+    fn wait_until(&self, time: u64) {
+        let now = self.now();
+
+        if time > now {
+            thread::sleep(Duration::from_nanos(time - now));
+        }
+    }
+}
+
+/// When a VDM-RT thread runs its operation.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Release {
+    /// `periodic(period, jitter, delay, offset)`: the `k`th release is at
+    /// `offset + k * period`, plus a random jitter of at most `jitter`,
+    /// and at least `delay` after the previous release.
+    Periodic {
+        period: u64,
+        jitter: u64,
+        delay: u64,
+        offset: u64,
+    },
+    /// `sporadic(min_delay, max_delay, offset)`: the first release is at
+    /// `offset`, and each next one a random delay in
+    /// `[min_delay, max_delay]` after the previous.
+    Sporadic {
+        min_delay: u64,
+        max_delay: u64,
+        offset: u64,
+    },
+}
+
+impl Release {
+    /// The release times, with the random part drawn from a `JavaRandom`
+    /// seeded with `seed`, so that they are the same on every run.
+    pub fn times(self, seed: i64) -> ReleaseTimes {
+        ReleaseTimes {
+            release: self,
+            rnd: Generator::new(JavaRandom::new(seed)),
+            count: 0,
+            last: None,
+        }
+    }
+}
+
+/// The infinite sequence of times of a `Release`.
+#[derive(Clone, Debug)]
+pub struct ReleaseTimes {
+    release: Release,
+    rnd: Generator<JavaRandom>,
+    count: u64,
+    last: Option<u64>,
+}
+
+impl ReleaseTimes {
+    /// A random number in `[0, max]`.
+    fn random(&mut self, max: u64) -> u64 {
+        if max == 0 {
+            0
+        } else {
+            self.rnd.rand(max.saturating_add(1) as i64) as u64
+        }
+    }
+}
+
+impl Iterator for ReleaseTimes {
+    type Item = u64;
+
+    fn next(&mut self) -> Option<u64> {
+        let time = match (self.release, self.last) {
+            (Release::Periodic { period, jitter, delay, offset }, last) => {
+                let nominal = offset.saturating_add(self.count.saturating_mul(period));
+                let time = nominal.saturating_add(self.random(jitter));
+
+                match last {
+                    Some(last) => time.max(last.saturating_add(delay)),
+                    None => time,
+                }
+            }
+            (Release::Sporadic { offset, .. }, None) => offset,
+            (Release::Sporadic { min_delay, max_delay, .. }, Some(last)) => {
+                let spread = max_delay.saturating_sub(min_delay);
+                last.saturating_add(min_delay).saturating_add(self.random(spread))
+            }
+        };
+
+        self.count += 1;
+        self.last = Some(time);
+        Some(time)
+    }
+}
+
+/// A running VDM thread.
+#[derive(Debug)]
+pub struct ThreadHandle {
+    stopped: Arc<AtomicBool>,
+    handle: JoinHandle<()>,
+}
+
+impl ThreadHandle {
+    /// VDM `stop`: a periodic or sporadic thread is not released again.
+    /// A procedural thread runs to the end of its body.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Waits for the thread to end, and resumes its panic if it panicked.
+    pub fn join(self) {
+        if let Err(panic) = self.handle.join() {
+            ::std::panic::resume_unwind(panic);
+        }
+    }
+}
+
+/// VDM `start` of an object with a procedural thread.
+pub fn start<F: FnOnce() + Send + 'static>(body: F) -> ThreadHandle {
+    ThreadHandle {
+        stopped: Arc::new(AtomicBool::new(false)),
+        handle: thread::spawn(body),
+    }
+}
+
+/// VDM `start` of an object with a periodic or sporadic thread: runs
+/// `body` at each of `releases` by `clock` until the thread is stopped or
+/// the releases end. A release that falls while `body` still runs is
+/// delayed until it returns.
+pub fn start_periodic<C, I, F>(clock: Arc<C>, releases: I, mut body: F) -> ThreadHandle
+    where C: Clock + ?Sized + 'static,
+          I: Iterator<Item = u64> + Send + 'static,
+          F: FnMut() + Send + 'static
+{
+    let stopped = Arc::new(AtomicBool::new(false));
+    let flag = stopped.clone();
+
+    let handle = thread::spawn(move || {
+        for time in releases {
+            if flag.load(Ordering::SeqCst) {
+                break;
+            }
+
+            clock.wait_until(time);
+
+            if flag.load(Ordering::SeqCst) {
+                break;
+            }
+
+            body();
+        }
+    });
+
+    ThreadHandle { stopped, handle }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    /// A clock that jumps to whatever time is waited for.
+    struct Jump(Mutex<u64>);
+
+    impl Clock for Jump {
+        fn now(&self) -> u64 {
+            *self.0.lock().unwrap()
+        }
+
+        fn wait_until(&self, time: u64) {
+            let mut now = self.0.lock().unwrap();
+            *now = (*now).max(time);
+        }
+    }
+
+    #[test]
+    fn history_counters() {
+        let monitor = Monitor::new();
+        monitor.call("Put", || ());
+        let nested = monitor.call("Put", || monitor.history());
+
+        assert_eq!(2, nested.req("Put"));
+        assert_eq!(1, nested.active("Put"));
+        assert_eq!(0, nested.waiting("Put"));
+        assert_eq!(2, monitor.history().fin("Put"));
+        assert_eq!(0, monitor.history().req("Get"));
+    }
+
+    #[test]
+    fn permission_blocks_until_it_holds() {
+        let monitor = Arc::new(Monitor::new().per("Get", |h| h.fin("Put") > h.fin("Get")));
+        let log = Arc::new(Mutex::new(Vec::new()));
+
+        let getter = {
+            let (monitor, log) = (monitor.clone(), log.clone());
+            start(move || monitor.call("Get", || log.lock().unwrap().push("Get")))
+        };
+
+        while monitor.history().waiting("Get") == 0 {
+            thread::yield_now();
+        }
+
+        monitor.call("Put", || log.lock().unwrap().push("Put"));
+        getter.join();
+
+        assert_eq!(vec!["Put", "Get"], *log.lock().unwrap());
+    }
+
+    #[test]
+    fn mutex_excludes() {
+        let monitor = Arc::new(Monitor::new().mutex(&["Inc", "Dec"]));
+        let overlaps = Arc::new(AtomicBool::new(false));
+
+        let threads: Vec<ThreadHandle> = (0..4).map(|i| {
+            let (monitor, overlaps) = (monitor.clone(), overlaps.clone());
+            let op = if i % 2 == 1 { "Inc" } else { "Dec" };
+
+            start(move || for _ in 0..50 {
+                monitor.call(op, || {
+                    let h = monitor.history();
+                    if h.active("Inc") + h.active("Dec") > 1 {
+                        overlaps.store(true, Ordering::SeqCst);
+                    }
+                });
+            })
+        }).collect();
+
+        threads.into_iter().for_each(ThreadHandle::join);
+
+        assert!(!overlaps.load(Ordering::SeqCst));
+        assert_eq!(100, monitor.history().fin("Inc"));
+    }
+
+    #[test]
+    fn periodic_release_times() {
+        let plain = Release::Periodic { period: 10, jitter: 0, delay: 0, offset: 5 };
+        assert_eq!(vec![5, 15, 25, 35], plain.times(0).take(4).collect::<Vec<u64>>());
+
+        let jittered = Release::Periodic { period: 10, jitter: 4, delay: 8, offset: 0 };
+        let times: Vec<u64> = jittered.times(42).take(100).collect();
+
+        assert_eq!(times, jittered.times(42).take(100).collect::<Vec<u64>>());
+        assert!(times.iter().enumerate().all(|(k, &t)| t >= 10 * k as u64));
+        assert!(times.windows(2).all(|w| w[1] - w[0] >= 8 && w[1] - w[0] <= 18));
+    }
+
+    #[test]
+    fn sporadic_release_times() {
+        let sporadic = Release::Sporadic { min_delay: 3, max_delay: 7, offset: 100 };
+        let times: Vec<u64> = sporadic.times(1).take(50).collect();
+
+        assert_eq!(100, times[0]);
+        assert!(times.windows(2).all(|w| (3..=7).contains(&(w[1] - w[0]))));
+    }
+
+    #[test]
+    fn periodic_thread() {
+        let clock = Arc::new(Jump(Mutex::new(0)));
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let release = Release::Periodic { period: 1000, jitter: 0, delay: 0, offset: 0 };
+
+        let thread = {
+            let (clock, seen) = (clock.clone(), seen.clone());
+            start_periodic(clock.clone(), release.times(0).take(3), move || {
+                seen.lock().unwrap().push(clock.now())
+            })
+        };
+        thread.join();
+
+        assert_eq!(vec![0, 1000, 2000], *seen.lock().unwrap());
+    }
+
+    #[test]
+    fn stopped_thread() {
+        let release = Release::Periodic { period: 1_000_000, jitter: 0, delay: 0, offset: 0 };
+        let thread = start_periodic(Arc::new(SystemClock::new()), release.times(0), || ());
+
+        thread.stop();
+        thread.join();
+    }
+}
@@ -0,0 +1,229 @@
+#[cfg(feature = "std")]
+use lib::cell::Cell;
+use lib::hash::Hash;
+use lib::sync::Mutex;
+use lib::ToOwned;
+use Seq;
+use Set;
+use Map;
+use OrdSet;
+use OrdMap;
+use VdmError;
+
+/// Types restricted by a VDM `inv` clause.
+pub trait Invariant {
+    fn inv(&self) -> bool;
+}
+
+/// Kind of condition being checked.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Condition {
+    Pre,
+    Post,
+    Inv,
+}
+
+/// What happens when a condition does not hold.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Policy {
+    /// The violation is silently accepted.
+    Ignore,
+    /// The violation is written to stderr, or to the `IO` output without
+    /// `std`, and execution continues.
+    Log,
+    /// The violation panics with the `VdmError` message.
+    Panic,
+    /// The violation is returned as a `VdmError`.
+    Error,
+}
+
+static POLICY: Mutex<Policy> = Mutex::new(Policy::Panic);
+
+#[cfg(feature = "std")]
+thread_local! {
+    static THREAD_POLICY: Cell<Option<Policy>> = const { Cell::new(None) };
+}
+
+/// Without `std` there are no thread-locals, so this is one global shared
+/// by every thread and interrupt handler.
+#[cfg(not(feature = "std"))]
+static THREAD_POLICY: Mutex<Option<Policy>> = Mutex::new(None);
+
+/// Sets the policy used by all threads without a thread policy.
+/// Defaults to `Policy::Panic`.
+pub fn set_policy(policy: Policy) {
+    *POLICY.lock() = policy;
+}
+
+/// Overrides the global policy for the current thread.
+/// `None` reverts the thread to the global policy.
+#[cfg(feature = "std")]
+pub fn set_thread_policy(policy: Option<Policy>) {
+    THREAD_POLICY.with(|p| p.set(policy));
+}
+
+/// Without `std` the override is global: it applies to every thread, not
+/// only the calling one, until it is reset with `None`.
+#[cfg(not(feature = "std"))]
+pub fn set_thread_policy(policy: Option<Policy>) {
+    *THREAD_POLICY.lock() = policy;
+}
+
+/// Returns the policy in effect for the current thread.
+pub fn policy() -> Policy {
+    if let Some(policy) = thread_policy() {
+        return policy;
+    }
+
+    *POLICY.lock()
+}
+
+/// Returns the current thread's override of the global policy, if any.
+#[cfg(feature = "std")]
+pub fn thread_policy() -> Option<Policy> {
+    THREAD_POLICY.with(|p| p.get())
+}
+
+#[cfg(not(feature = "std"))]
+pub fn thread_policy() -> Option<Policy> {
+    *THREAD_POLICY.lock()
+}
+
+/// Applies the current policy to the outcome of a condition.
+/// `desc` is used in the error message, typically the condition's source text.
+pub fn check(cond: Condition, holds: bool, desc: &str) -> Result<(), VdmError> {
+    if holds {
+        return Ok(());
+    }
+
+    let err = match cond {
+        Condition::Pre => VdmError::PreconditionViolated(desc.to_owned()),
+        Condition::Post => VdmError::PostconditionViolated(desc.to_owned()),
+        Condition::Inv => VdmError::InvariantViolated(desc.to_owned()),
+    };
+
+    match policy() {
+        Policy::Ignore => Ok(()),
+        Policy::Log => {
+            log(&err);
+            Ok(())
+        }
+        Policy::Panic => panic!("{}", err),
+        Policy::Error => Err(err),
+    }
+}
+
+#[cfg(feature = "std")]
+fn log(err: &VdmError) {
+    eprintln!("{}", err);
+}
+
+#[cfg(not(feature = "std"))]
+fn log(err: &VdmError) {
+    ::IO::write_output(&format!("{}\n", err));
+}
+
+/// Checks the invariant of `val` and passes it on, unless the policy
+/// turns a violation into an error.
+pub fn check_value<T: Invariant>(val: T, desc: &str) -> Result<T, VdmError> {
+    let holds = val.inv();
+    check(Condition::Inv, holds, desc)?;
+    Ok(val)
+}
+
+impl<T: Invariant + Hash + Clone> Invariant for Seq<T> {
+    fn inv(&self) -> bool {
+        self.iter().all(|e| e.inv())
+    }
+}
+
+impl<T: Invariant + Clone + Hash + Eq> Invariant for Set<T> {
+    fn inv(&self) -> bool {
+        self.iter().all(|e| e.inv())
+    }
+}
+
+impl<K, V> Invariant for Map<K, V>
+    where K: Invariant + Hash + Eq,
+          V: Invariant
+{
+    fn inv(&self) -> bool {
+        self.iter().all(|(k, v)| k.inv() && v.inv())
+    }
+}
+
+impl<T: Invariant + Ord + Clone> Invariant for OrdSet<T> {
+    fn inv(&self) -> bool {
+        self.iter().all(|e| e.inv())
+    }
+}
+
+impl<K, V> Invariant for OrdMap<K, V>
+    where K: Invariant + Ord,
+          V: Invariant
+{
+    fn inv(&self) -> bool {
+        self.iter().all(|(k, v)| k.inv() && v.inv())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use VdmError;
+
+    #[derive(Clone, PartialEq, Eq, Hash, Debug)]
+    struct Pos(i64);
+
+    impl Invariant for Pos {
+        fn inv(&self) -> bool {
+            self.0 > 0
+        }
+    }
+
+    #[test]
+    fn holds() {
+        set_thread_policy(Some(Policy::Error));
+
+        assert_eq!(Ok(()), check(Condition::Pre, true, "x > 0"));
+    }
+
+    #[test]
+    fn error_policy() {
+        set_thread_policy(Some(Policy::Error));
+
+        assert_eq!(Err(VdmError::PostconditionViolated("RESULT > 0".to_owned())),
+                   check(Condition::Post, false, "RESULT > 0"));
+    }
+
+    #[test]
+    fn ignore_policy() {
+        set_thread_policy(Some(Policy::Ignore));
+
+        assert_eq!(Ok(()), check(Condition::Inv, false, "inv_Pos"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Precondition violated: x > 0")]
+    fn panic_policy() {
+        set_thread_policy(Some(Policy::Panic));
+
+        let _ = check(Condition::Pre, false, "x > 0");
+    }
+
+    #[test]
+    fn check_value_error() {
+        set_thread_policy(Some(Policy::Error));
+
+        assert_eq!(Ok(Pos(1)), check_value(Pos(1), "inv_Pos"));
+        assert_eq!(Err(VdmError::InvariantViolated("inv_Pos".to_owned())),
+                   check_value(Pos(0), "inv_Pos"));
+    }
+
+    #[test]
+    fn collection_invariants() {
+        assert!(seq![Pos(1), Pos(2)].inv());
+        assert!(!set!{Pos(1), Pos(-2)}.inv());
+        assert!(!map!{Pos(1) => Pos(0)}.inv());
+    }
+}
@@ -0,0 +1,111 @@
+use lib::error::Error;
+use lib::fmt;
+use lib::String;
+
+/// Runtime errors raised when a VDM operator is applied outside of its
+/// precondition. Returned by the `try_*` variants of the collection operators;
+/// the panicking variants use the `Display` text as panic message.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum VdmError {
+    /// Sequence index outside of `inds s`. Holds the index and the length.
+    IndexOutOfRange(u64, u64),
+    /// `hd` or `tl` applied to the empty sequence.
+    EmptySequence,
+    /// Map application with a key outside of `dom m`.
+    KeyNotInDomain,
+    /// `munion` or `merge` of maps that disagree on a common key.
+    IncompatibleMerge,
+    /// Composition or iteration where the range is not a subset of the domain.
+    RangeNotInDomain,
+    /// `inverse` of a map that is not 1-to-1.
+    NotInjective,
+    /// `iota` where no element satisfies the predicate.
+    IotaNoMatch,
+    /// `iota` where more than one element satisfies the predicate.
+    IotaMultipleMatches,
+    /// `let ... be st` where no element satisfies the predicate.
+    BeSuchThatEmpty,
+    /// A `pre` clause did not hold. Holds a description of the condition.
+    PreconditionViolated(String),
+    /// A `post` clause did not hold. Holds a description of the condition.
+    PostconditionViolated(String),
+    /// An `inv` clause did not hold. Holds a description of the condition.
+    InvariantViolated(String),
+    /// An integer outside of the range of a numeric type. Holds the value and the type name.
+    NotInType(i64, &'static str),
+    /// `div`, `mod` or `rem` with a zero divisor.
+    DivisionByZero,
+    /// An integer operation overflowed 64 bits.
+    ArithmeticOverflow,
+    /// The value of an optional type was `nil`.
+    NilValue,
+    /// A real number operation yielded NaN, which is not a VDM value.
+    NotANumber,
+    /// Text that is not a VDM value of the expected type. Holds a description
+    /// of the problem and its position.
+    ParseError(String),
+    /// A `printf` format string with an unsupported conversion, or more
+    /// conversions than arguments. Holds a description of the problem.
+    FormatError(String),
+}
+
+impl fmt::Display for VdmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            VdmError::IndexOutOfRange(index, len) => {
+                write!(f, "Sequence index {} out of range 1..{}.", index, len)
+            }
+            VdmError::EmptySequence => write!(f, "Sequence is empty."),
+            VdmError::KeyNotInDomain => write!(f, "No such key in map."),
+            VdmError::IncompatibleMerge => write!(f, "Merging requires maps to be compatible"),
+            VdmError::RangeNotInDomain => write!(f, "Range is not a subset of the domain."),
+            VdmError::NotInjective => write!(f, "Map must be 1-to-1 to inverse."),
+            VdmError::IotaNoMatch => write!(f, "Iota does not select a result."),
+            VdmError::IotaMultipleMatches => write!(f, "Iota selects more than one result."),
+            VdmError::BeSuchThatEmpty => write!(f, "Let Be St found no applicable bindings"),
+            VdmError::PreconditionViolated(ref cond) => write!(f, "Precondition violated: {}", cond),
+            VdmError::PostconditionViolated(ref cond) => {
+                write!(f, "Postcondition violated: {}", cond)
+            }
+            VdmError::InvariantViolated(ref cond) => write!(f, "Invariant violated: {}", cond),
+            VdmError::NotInType(val, ty) => write!(f, "Value {} is not a {}.", val, ty),
+            VdmError::DivisionByZero => write!(f, "Division by zero."),
+            VdmError::ArithmeticOverflow => write!(f, "Arithmetic overflow."),
+            VdmError::NilValue => write!(f, "Value is nil."),
+            VdmError::NotANumber => write!(f, "Result is not a number."),
+            VdmError::ParseError(ref msg) => write!(f, "Cannot parse value: {}", msg),
+            VdmError::FormatError(ref msg) => write!(f, "Invalid format string: {}", msg),
+        }
+    }
+}
+
+impl Error for VdmError {}
+
+/// Unwraps the result of a `try_*` operator, panicking with the
+/// error message on failure.
+pub fn unwrap_vdm<T>(result: Result<T, VdmError>) -> T {
+    match result {
+        Ok(val) => val,
+        Err(err) => panic!("{}", err),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::ToString;
+
+    #[test]
+    fn display_formatting() {
+        let err = VdmError::IndexOutOfRange(4, 3);
+
+        assert_eq!("Sequence index 4 out of range 1..3.", err.to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "No such key in map.")]
+    fn unwrap_vdm_panics() {
+        let res: Result<i32, VdmError> = Err(VdmError::KeyNotInDomain);
+        unwrap_vdm(res);
+    }
+}
@@ -1,42 +1,103 @@
-use std::hash::{Hash, Hasher};
-use std::ops::{Add, Sub, Mul, Div, Neg};
-use std::fmt;
-
-/// Wrapper for `f64`. Is needed to implement `Hash` + `Eq`
-#[derive(Copy, Clone, PartialOrd)]
-pub struct F64(pub f64);
+use lib::cmp::Ordering;
+use lib::hash::{Hash, Hasher};
+use lib::ops::{Add, Sub, Mul, Div, Neg};
+use lib::fmt;
+use num_traits::Float;
+use error::unwrap_vdm;
+use VdmError;
+
+/// Wrapper for `f64`, implementing `Eq`, `Ord` and `Hash`, so reals can
+/// be set elements and map keys. `0.0` and `-0.0` are equal and hash
+/// alike.
+///
+/// NaN is not a VDM value. The value is private, so that every `F64`
+/// comes from `new` or `From<f64>`, which panic rather than hold NaN, or
+/// from `try_new`, which returns `VdmError::NotANumber`; the arithmetic
+/// operators and their `try_*` variants likewise. Division by zero is
+/// `VdmError::DivisionByZero`. Read the value with `f64::from`.
+#[derive(Copy, Clone)]
+pub struct F64(pub(crate) f64);
 
 impl F64 {
+    /// Panics if `val` is NaN.
+    pub fn new(val: f64) -> F64 {
+        unwrap_vdm(F64::try_new(val))
+    }
+
+    pub fn try_new(val: f64) -> Result<F64, VdmError> {
+        if val.is_nan() {
+            Err(VdmError::NotANumber)
+        } else {
+            Ok(F64(val))
+        }
+    }
+
     pub fn floor(&self) -> i64 {
-        self.0.floor() as i64
+        Float::floor(self.0) as i64
     }
 
     pub fn abs(&self) -> F64 {
-        F64(self.0.abs())
+        F64(Float::abs(self.0))
     }
 
     pub fn pow(&self, other: F64) -> F64 {
-        F64(self.0.powf(other.0))
+        unwrap_vdm(self.try_pow(other))
+    }
+
+    pub fn try_pow(&self, other: F64) -> Result<F64, VdmError> {
+        F64::try_new(Float::powf(self.0, other.0))
+    }
+
+    pub fn try_add(&self, other: F64) -> Result<F64, VdmError> {
+        F64::try_new(self.0 + other.0)
+    }
+
+    pub fn try_sub(&self, other: F64) -> Result<F64, VdmError> {
+        F64::try_new(self.0 - other.0)
+    }
+
+    pub fn try_mul(&self, other: F64) -> Result<F64, VdmError> {
+        F64::try_new(self.0 * other.0)
+    }
+
+    pub fn try_div(&self, other: F64) -> Result<F64, VdmError> {
+        if other.0 == 0.0 {
+            return Err(VdmError::DivisionByZero);
+        }
+
+        F64::try_new(self.0 / other.0)
+    }
+}
+
+/// Numeric order, which is total as an `F64` is never NaN.
+impl Ord for F64 {
+    fn cmp(&self, other: &F64) -> Ordering {
+        self.0.partial_cmp(&other.0).expect("F64 holds NaN")
+    }
+}
+
+impl PartialOrd for F64 {
+    fn partial_cmp(&self, other: &F64) -> Option<Ordering> {
+        Some(self.cmp(other))
     }
 }
 
 impl PartialEq for F64 {
     fn eq(&self, other: &F64) -> bool {
-        self.0 == other.0
+        self.cmp(other) == Ordering::Equal
     }
 }
 
-/// Total order only holds for non-NaN values. NaN values are illegal in VDM.
 impl Eq for F64 {}
 
 impl Hash for F64 {
     fn hash<S>(&self, state: &mut S)
         where S: Hasher
     {
-        use std::mem::transmute;
-        // bit cast (similar to reinterpret_cast in C++) to u64 and perform hash.
-        let val = unsafe { transmute::<f64, u64>(self.0) };
-        val.hash(state)
+        // Equal values must hash alike, so both zeros are hashed as one.
+        let canonical = if self.0 == 0.0 { 0.0 } else { self.0 };
+
+        canonical.to_bits().hash(state)
     }
 }
 
@@ -71,9 +132,10 @@ macro_rules! from_impl {
 
 from_impl! { u64 i32 i64 }
 
+/// Panics if `val` is NaN.
 impl From<f64> for F64 {
     fn from(val: f64) -> F64 {
-        F64(val)
+        F64::new(val)
     }
 }
 
@@ -88,7 +150,7 @@ impl Add for F64 {
 
     #[inline]
     fn add(self, other: F64) -> F64 {
-        F64(self.0 + other.0)
+        unwrap_vdm(self.try_add(other))
     }
 }
 
@@ -97,7 +159,7 @@ impl Sub for F64 {
 
     #[inline]
     fn sub(self, other: F64) -> F64 {
-        F64(self.0 - other.0)
+        unwrap_vdm(self.try_sub(other))
     }
 }
 
@@ -106,7 +168,7 @@ impl Mul for F64 {
 
     #[inline]
     fn mul(self, other: F64) -> F64 {
-        F64(self.0 * other.0)
+        unwrap_vdm(self.try_mul(other))
     }
 }
 
@@ -115,7 +177,7 @@ impl Div for F64 {
 
     #[inline]
     fn div(self, other: F64) -> F64 {
-        F64(self.0 / other.0)
+        unwrap_vdm(self.try_div(other))
     }
 }
 
@@ -131,6 +193,9 @@ impl Neg for F64 {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use lib::collections::HashSet;
+    use Set;
+    use Seq;
 
     #[test]
     fn add_1() {
@@ -138,6 +203,41 @@ mod tests {
         assert_eq!(F64(0.3), f);
     }
 
+    #[test]
+    fn signed_zero() {
+        let mut s = HashSet::new();
+        s.insert(F64(0.0));
+        s.insert(F64(-0.0));
+
+        assert_eq!(F64(0.0), F64(-0.0));
+        assert_eq!(1, s.len());
+    }
+
+    #[test]
+    fn total_order() {
+        let mut v = vec![F64(2.5), F64(-1.0), F64(f64::INFINITY), F64(0.0)];
+        v.sort();
+
+        assert_eq!(vec![F64(-1.0), F64(0.0), F64(2.5), F64(f64::INFINITY)], v);
+
+        let s: Set<F64> = vec![F64(2.0), F64(-0.5), F64(1.0)].into_iter().collect();
+        assert_eq!(seq![F64(-1.0), F64(4.0)], s.seq_compr(|x| x != F64(1.0), |x| x * F64(2.0)));
+    }
+
+    #[test]
+    fn nan_rejected() {
+        assert_eq!(Err(VdmError::NotANumber), F64::try_new(f64::NAN));
+        assert_eq!(Err(VdmError::NotANumber), F64(f64::INFINITY).try_sub(F64(f64::INFINITY)));
+        assert_eq!(Err(VdmError::DivisionByZero), F64(1.0).try_div(F64(0.0)));
+        assert_eq!(Err(VdmError::NotANumber), F64(-8.0).try_pow(F64(0.5)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Result is not a number.")]
+    fn nan_panics() {
+        let _ = F64(0.0) * F64(f64::INFINITY);
+    }
+
     #[test]
     fn floor_1() {
         let f = F64(1.23).floor();
@@ -0,0 +1,313 @@
+//! Elementary functions in integer arithmetic, for the `Fixed` and
+//! `Decimal` reals on targets without an FPU.
+//!
+//! Values are Q60 fixed-point numbers in an `i128`, i.e. `x / 2^60`. Each
+//! `Real` value is converted to Q60, the function is computed with range
+//! reduction and a Taylor series, and the result is rounded back to the
+//! scale of the type. The series stop once a term truncates to zero, so
+//! results are accurate to a few units of 2^-60 before that rounding.
+
+use VdmError;
+
+const FRAC_BITS: u32 = 60;
+
+const ONE: i128 = 1 << FRAC_BITS;
+
+/// π, ln 2, ln 10 and √3 in Q60, rounded to nearest.
+pub const PI: i128 = 3622009729038561421;
+const LN_2: i128 = 799144290325165979;
+const LN_10: i128 = 2654699869899991814;
+const SQRT_3: i128 = 1996918623117814388;
+
+/// Converts `raw / scale` to Q60, truncating.
+pub fn from_raw(raw: i64, scale: i64) -> i128 {
+    ((raw as i128) << FRAC_BITS) / scale as i128
+}
+
+/// Converts `x` to the raw value of a real with the given scale, rounding
+/// to nearest.
+pub fn to_raw(x: i128, scale: i64) -> Result<i64, VdmError> {
+    let scaled = x.checked_mul(scale as i128).ok_or(VdmError::ArithmeticOverflow)?;
+    let raw = (scaled + (ONE >> 1)) >> FRAC_BITS;
+
+    if raw < i64::MIN as i128 || raw > i64::MAX as i128 {
+        Err(VdmError::ArithmeticOverflow)
+    } else {
+        Ok(raw as i64)
+    }
+}
+
+/// Callers keep `a * b` within `i128`. Truncates towards zero, so series
+/// terms reach zero.
+fn mul(a: i128, b: i128) -> i128 {
+    a * b / ONE
+}
+
+fn div(a: i128, b: i128) -> i128 {
+    (a << FRAC_BITS) / b
+}
+
+/// The largest `r` with `r * r <= n`.
+pub fn isqrt(n: u128) -> u128 {
+    if n < 2 {
+        return n;
+    }
+
+    let mut x = 1u128 << (128 - n.leading_zeros()).div_ceil(2);
+    loop {
+        let y = (x + n / x) / 2;
+        if y >= x {
+            return x;
+        }
+        x = y;
+    }
+}
+
+/// Requires `0 <= x < 2^67`.
+pub fn sqrt(x: i128) -> i128 {
+    isqrt((x as u128) << FRAC_BITS) as i128
+}
+
+/// Splits `x` into `k * π/2 + r` with `|r| <= π/4`, giving `k mod 4`
+/// and `r`.
+fn reduce_quadrant(x: i128) -> (i128, i128) {
+    let half_pi = PI / 2;
+    let k = (x + half_pi / 2).div_euclid(half_pi);
+
+    (k.rem_euclid(4), x - k * half_pi)
+}
+
+/// `sin r` for `|r| <= π/4`.
+fn sin_series(r: i128) -> i128 {
+    let r2 = mul(r, r);
+    let mut term = r;
+    let mut sum = r;
+    let mut n = 1;
+
+    while term != 0 {
+        term = -mul(term, r2) / ((2 * n) * (2 * n + 1));
+        sum += term;
+        n += 1;
+    }
+    sum
+}
+
+/// `cos r` for `|r| <= π/4`.
+fn cos_series(r: i128) -> i128 {
+    let r2 = mul(r, r);
+    let mut term = ONE;
+    let mut sum = ONE;
+    let mut n = 1;
+
+    while term != 0 {
+        term = -mul(term, r2) / ((2 * n - 1) * (2 * n));
+        sum += term;
+        n += 1;
+    }
+    sum
+}
+
+pub fn sin(x: i128) -> i128 {
+    let (quadrant, r) = reduce_quadrant(x);
+
+    match quadrant {
+        0 => sin_series(r),
+        1 => cos_series(r),
+        2 => -sin_series(r),
+        _ => -cos_series(r),
+    }
+}
+
+pub fn cos(x: i128) -> i128 {
+    let (quadrant, r) = reduce_quadrant(x);
+
+    match quadrant {
+        0 => cos_series(r),
+        1 => -sin_series(r),
+        2 => -cos_series(r),
+        _ => sin_series(r),
+    }
+}
+
+/// Fails where `cos x` is zero.
+pub fn tan(x: i128) -> Result<i128, VdmError> {
+    let cos = cos(x);
+
+    if cos == 0 {
+        Err(VdmError::ArithmeticOverflow)
+    } else {
+        Ok(div(sin(x), cos))
+    }
+}
+
+/// `atan t` for `|t| <= 2 - √3`.
+fn atan_series(t: i128) -> i128 {
+    let t2 = mul(t, t);
+    let mut power = t;
+    let mut sum = t;
+    let mut n = 1;
+
+    loop {
+        power = -mul(power, t2);
+        let term = power / (2 * n + 1);
+        if term == 0 {
+            return sum;
+        }
+        sum += term;
+        n += 1;
+    }
+}
+
+pub fn atan(x: i128) -> i128 {
+    if x < 0 {
+        -atan(-x)
+    } else if x > ONE {
+        PI / 2 - atan(div(ONE, x))
+    } else if x > 2 * ONE - SQRT_3 {
+        // atan x = π/6 + atan((√3 x - 1) / (√3 + x)), and 2 - √3 = tan π/12.
+        PI / 6 + atan_series(div(mul(SQRT_3, x) - ONE, SQRT_3 + x))
+    } else {
+        atan_series(x)
+    }
+}
+
+/// Requires `-1 <= x <= 1`.
+pub fn asin(x: i128) -> i128 {
+    if x >= ONE {
+        PI / 2
+    } else if x <= -ONE {
+        -PI / 2
+    } else {
+        atan(div(x, sqrt(ONE - mul(x, x))))
+    }
+}
+
+/// Requires `-1 <= x <= 1`.
+pub fn acos(x: i128) -> i128 {
+    PI / 2 - asin(x)
+}
+
+/// Fails if the result does not fit in Q60 with 67 integer bits, which
+/// no `Real` can represent either.
+pub fn exp(x: i128) -> Result<i128, VdmError> {
+    let k = (x + LN_2 / 2).div_euclid(LN_2);
+
+    if k > 66 {
+        return Err(VdmError::ArithmeticOverflow);
+    } else if k < -(FRAC_BITS as i128) - 1 {
+        return Ok(0);
+    }
+
+    // e^x = 2^k e^r with |r| <= ln 2 / 2.
+    let r = x - k * LN_2;
+    let mut term = ONE;
+    let mut sum = ONE;
+    let mut n = 1;
+
+    loop {
+        term = mul(term, r) / n;
+        if term == 0 {
+            break;
+        }
+        sum += term;
+        n += 1;
+    }
+
+    Ok(if k >= 0 { sum << k } else { sum >> -k })
+}
+
+/// Requires `x > 0`.
+pub fn ln(x: i128) -> i128 {
+    // x = 2^k m with 1 <= m < 2.
+    let k = (127 - x.leading_zeros() as i128) - FRAC_BITS as i128;
+    let m = if k >= 0 { x >> k } else { x << -k };
+
+    // ln m = 2 atanh z with z = (m - 1) / (m + 1) < 1/3.
+    let z = div(m - ONE, m + ONE);
+    let z2 = mul(z, z);
+    let mut power = z;
+    let mut sum = z;
+    let mut n = 1;
+
+    loop {
+        power = mul(power, z2);
+        let term = power / (2 * n + 1);
+        if term == 0 {
+            break;
+        }
+        sum += term;
+        n += 1;
+    }
+
+    k * LN_2 + 2 * sum
+}
+
+/// Requires `x > 0`.
+pub fn log10(x: i128) -> i128 {
+    div(ln(x), LN_10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn q(v: f64) -> i128 {
+        (v * ONE as f64) as i128
+    }
+
+    fn assert_close(expected: f64, actual: i128) {
+        let actual = actual as f64 / ONE as f64;
+        assert!((expected - actual).abs() < 1e-15, "expected {}, got {}", expected, actual);
+    }
+
+    #[test]
+    fn trigonometry() {
+        for &v in &[-7.0, -3.0, -1.5, -0.5, 0.0, 0.25, 1.0, 2.0, 3.1, 4.5, 6.0, 100.0] {
+            assert_close(f64::sin(v), sin(q(v)));
+            assert_close(f64::cos(v), cos(q(v)));
+            assert_close(f64::atan(v), atan(q(v)));
+
+            if v.cos().abs() > 0.1 {
+                assert_close(f64::tan(v), tan(q(v)).unwrap());
+            }
+        }
+
+        for &v in &[-1.0, -0.9, -0.3, 0.0, 0.5, 0.99, 1.0] {
+            assert_close(f64::asin(v), asin(q(v)));
+            assert_close(f64::acos(v), acos(q(v)));
+        }
+    }
+
+    #[test]
+    fn exponentials() {
+        for &v in &[-20.0, -1.0, -0.3, 0.0, 0.5, 1.0, 10.0] {
+            assert!((f64::exp(v) - exp(q(v)).unwrap() as f64 / ONE as f64).abs() < 1e-15 * f64::exp(v).max(1.0));
+        }
+
+        for &v in &[0.0009765625, 0.1, 0.5, 1.0, 2.0, 10.0, 12345.678] {
+            assert_close(f64::ln(v), ln(q(v)));
+            assert_close(f64::log10(v), log10(q(v)));
+        }
+
+        assert_eq!(Err(VdmError::ArithmeticOverflow), exp(q(50.0)));
+        assert_eq!(Ok(0), exp(q(-50.0)));
+    }
+
+    #[test]
+    fn roots() {
+        assert_eq!(0, isqrt(0));
+        assert_eq!(3, isqrt(15));
+        assert_eq!(4, isqrt(16));
+        assert_eq!(1 << 62, isqrt(1 << 124));
+        assert_close(2f64.sqrt(), sqrt(2 * ONE));
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(ONE / 2, from_raw(5, 10));
+        assert_eq!(Ok(3), to_raw(PI, 1));
+        assert_eq!(Ok(31416), to_raw(PI, 10000));
+        assert_eq!(Ok(-2), to_raw(-3 * ONE / 2 - 1, 1));
+        assert_eq!(Err(VdmError::ArithmeticOverflow), to_raw(ONE << 10, 1 << 62));
+    }
+}
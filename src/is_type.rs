@@ -0,0 +1,28 @@
+/// Runtime type test behind VDM `is_` expressions. Implemented by
+/// `def_record!` records for themselves, and by `impl_union!` unions
+/// for each of their variant types.
+pub trait IsType<T> {
+    fn is_type(&self) -> bool;
+}
+
+#[cfg(test)]
+mod tests {
+    def_record! { Point { x: i64, y: i64 } }
+    def_record! { Tagged { val: i64, tag :- &'static str } }
+
+    enum Shape {
+        P(Point),
+        T(Tagged),
+    }
+
+    impl_union!{ Shape: Point as Shape::P, Tagged as Shape::T }
+
+    #[test]
+    fn is_record() {
+        let s = Shape::from(Point::new(1, 2));
+
+        assert!(is_Point(&s));
+        assert!(!is_Tagged(&s));
+        assert!(is_Point(&Point::new(1, 2)));
+    }
+}
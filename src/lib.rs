@@ -1,29 +1,196 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![allow(non_snake_case, non_upper_case_globals)]
 
-extern crate rand;
-
+#[cfg(not(feature = "std"))]
 #[macro_use]
-extern crate lazy_static;
+extern crate alloc;
 
-#[macro_use]
-extern crate itertools;
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+compile_error!("either the `std` or the `alloc` feature must be enabled");
+
+extern crate num_bigint;
+extern crate num_rational;
+extern crate num_traits;
+#[cfg(feature = "persistent")]
+extern crate im;
+#[cfg(not(feature = "std"))]
+extern crate hashbrown;
+#[cfg(not(feature = "std"))]
+extern crate spin;
+#[doc(hidden)]
+pub extern crate paste;
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+pub extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+#[cfg(all(test, feature = "serde"))]
+extern crate bincode;
+
+/// What the runtime uses of `std`, taken from `core` and `alloc` when the
+/// `std` feature is off. Modules import from here rather than from `std`.
+#[allow(unused_imports)]
+mod lib {
+    mod core {
+        #[cfg(feature = "std")]
+        pub use std::*;
+        #[cfg(not(feature = "std"))]
+        pub use core::*;
+    }
+
+    pub use self::core::{any, cell, cmp, convert, error, f64, fmt, hash, iter, marker, mem, ops, slice, str};
+
+    #[cfg(feature = "std")]
+    pub use std::rc;
+    #[cfg(feature = "std")]
+    pub use std::borrow::ToOwned;
+    #[cfg(feature = "std")]
+    pub use std::boxed::Box;
+    #[cfg(feature = "std")]
+    pub use std::string::{String, ToString};
+    #[cfg(feature = "std")]
+    pub use std::vec::Vec;
+
+    #[cfg(not(feature = "std"))]
+    pub use alloc::rc;
+    #[cfg(not(feature = "std"))]
+    pub use alloc::borrow::ToOwned;
+    #[cfg(not(feature = "std"))]
+    pub use alloc::boxed::Box;
+    #[cfg(not(feature = "std"))]
+    pub use alloc::string::{String, ToString};
+    #[cfg(not(feature = "std"))]
+    pub use alloc::vec::Vec;
+
+    /// `Set` and `Map` are backed by `hashbrown` without `std`.
+    pub mod collections {
+        #[cfg(feature = "std")]
+        pub use std::collections::{HashMap, HashSet, BTreeMap, BTreeSet, BinaryHeap};
+        #[cfg(feature = "std")]
+        pub use std::collections::{hash_map, hash_set, btree_map, btree_set};
+
+        #[cfg(not(feature = "std"))]
+        pub use hashbrown::{HashMap, HashSet, hash_map, hash_set};
+        #[cfg(not(feature = "std"))]
+        pub use alloc::collections::{BTreeMap, BTreeSet, BinaryHeap, btree_map, btree_set};
+    }
+
+    pub mod sync {
+        /// A mutex that can be used in a `static`: `std::sync::Mutex`,
+        /// ignoring poisoning, or a spin lock without `std`.
+        #[cfg(feature = "std")]
+        pub struct Mutex<T: ?Sized>(::std::sync::Mutex<T>);
+
+        #[cfg(feature = "std")]
+        impl<T> Mutex<T> {
+            pub const fn new(val: T) -> Mutex<T> {
+                Mutex(::std::sync::Mutex::new(val))
+            }
+        }
+
+        #[cfg(feature = "std")]
+        impl<T: Default> Default for Mutex<T> {
+            fn default() -> Mutex<T> {
+                Mutex::new(T::default())
+            }
+        }
 
+        #[cfg(feature = "std")]
+        impl<T: ?Sized + ::std::fmt::Debug> ::std::fmt::Debug for Mutex<T> {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        #[cfg(feature = "std")]
+        impl<T: ?Sized> Mutex<T> {
+            pub fn lock(&self) -> ::std::sync::MutexGuard<'_, T> {
+                self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+            }
+        }
+
+        #[cfg(not(feature = "std"))]
+        pub use spin::Mutex;
+
+        pub use super::core::sync::atomic;
+    }
+}
+
+/// Paths used by the exported macros, which cannot name `std` since the
+/// crate using them may be `no_std`.
+#[doc(hidden)]
+pub mod __private {
+    pub use lib::{cmp, fmt, hash, mem, ops, Box, String, ToString, Vec};
+    #[cfg(feature = "std")]
+    pub use std::{format, vec};
+    #[cfg(not(feature = "std"))]
+    pub use alloc::{format, vec};
+}
+
+mod error;
 mod token;
 #[macro_use]
 mod macros;
+#[macro_use]
+mod util;
 mod seq;
+#[macro_use]
 mod set;
+#[macro_use]
 mod map;
-mod util;
+mod ord_set;
+mod ord_map;
 mod f64_wrapper;
+mod big_int;
+mod rat;
+mod numeric;
+mod opt;
+mod tuple;
+mod random;
+mod real;
+mod fixed_math;
+mod is_type;
+mod parse;
+mod vdm_display;
+mod object;
+#[cfg(feature = "serde")]
+mod serialization;
+
+pub mod contracts;
+#[cfg(feature = "std")]
+pub mod concurrency;
+#[cfg(feature = "std")]
+pub mod rt;
+#[cfg(feature = "std")]
+pub mod rt_log;
+#[cfg(feature = "std")]
+pub mod traces;
 
 // std libs
 pub mod IO;
 pub mod MATH;
 pub mod VDMUtil;
 
+pub use self::error::VdmError;
 pub use self::token::Token;
 pub use self::seq::Seq;
 pub use self::set::Set;
 pub use self::map::Map;
+pub use self::ord_set::OrdSet;
+pub use self::ord_map::OrdMap;
 pub use self::f64_wrapper::F64;
+pub use self::big_int::BigInt;
+pub use self::rat::Rat;
+pub use self::numeric::{Int, Nat, Nat1};
+pub use self::opt::Opt;
+pub use self::tuple::{Tuple, DisplayFields, DebugFields};
+pub use self::random::{RandomSource, JavaRandom, Generator};
+pub use self::real::{Real, Fixed, Decimal, Q32_32, Q48_16, VdmReal};
+pub use self::is_type::IsType;
+pub use self::parse::{FromVdmStr, FromVdmFields, Parser};
+pub use self::vdm_display::{VdmDisplay, VdmDisplayFields, Vdm, AsDebug};
+pub use self::object::{ObjRef, Class, ClassDesc};
+#[cfg(feature = "std")]
+pub use self::object::SyncObjRef;
+#[cfg(feature = "serde")]
+pub use self::serialization::{SerializeFields, DeserializeFields};
@@ -37,8 +37,40 @@ macro_rules! strseq {
     }}
 }
 
+#[macro_export]
+macro_rules! ord_map {
+    () => ( $crate::OrdMap::new() );
+    ($( $key: expr => $val: expr ),*) => {{
+         let mut map = $crate::OrdMap::new();
+         $( map.insert($key, $val); )*
+         map
+    }}
+}
+
+#[macro_export]
+macro_rules! ord_set {
+    () => ( $crate::OrdSet::new() );
+    ($( $val: expr ),*) => {{
+         let mut set = $crate::OrdSet::new();
+         $( set.insert($val); )*
+         set
+    }}
+}
+
 /// Implements `From<EnumT> for T` and `From<T> for EnumT` for variants of an enum.
-/// Implicitly implements corresponding `Into` traits. Also implements Debug trait.
+/// Implicitly implements corresponding `Into` traits. Also implements Debug trait,
+/// and `IsType<T>` so records' `is_` functions can test the variant.
+///
+/// `Display` is delegated to the variant values if they all implement it.
+/// Likewise for `FromVdmStr`, trying the variants in order, and `VdmDisplay`.
+///
+/// When each variant is written `Enum::Variant`, also generates an
+/// `is_Variant()` predicate and a non-panicking `try_into_Variant()`
+/// conversion. Longer variant paths are accepted without them.
+///
+/// A trailing `; derive(...)` clause opts into any of `Clone`, `PartialEq`,
+/// `Eq`, `Hash` and `PartialOrd`, delegated to the variant values. Values
+/// of different variants are then unequal and incomparable, as in VDM.
 ///
 /// # Examples
 ///
@@ -56,9 +88,23 @@ macro_rules! strseq {
 /// let i: Vec<i8> = ai.into();
 /// # }
 /// ```
+///
+/// ```
+/// # #[macro_use] extern crate codegen_runtime; fn main() {
+/// enum A {I(i64), C(char)}
+/// impl_union!{ A: i64 as A::I, char as A::C; derive(Clone, PartialEq, Eq, Hash) }
+///
+/// let ai = A::from(-12i64);
+///
+/// // type tests.
+/// assert!(ai.is_I());
+/// assert_eq!(None, ai.clone().try_into_C());
+/// assert!(ai != A::from('c'));
+/// # }
+/// ```
 #[macro_export]
 macro_rules! impl_union {
-    ($enum_t:ty: $( $t:ty as $v:path),*) => {
+    (@core $enum_t:ty: $( $t:ty as $v:path),*) => {
         $(
             impl From<$enum_t> for $t {
                 fn from(val: $enum_t) -> $t {
@@ -74,10 +120,62 @@ macro_rules! impl_union {
                     $v(val)
                 }
             }
+
+            impl $crate::IsType<$t> for $enum_t {
+                #[allow(unreachable_patterns)]
+                fn is_type(&self) -> bool {
+                    match *self {
+                        $v(_) => true,
+                        _ => false,
+                    }
+                }
+            }
         )*
+
+        impl $crate::FromVdmStr for $enum_t where $(for<'a> $t: $crate::FromVdmStr),* {
+            fn parse_vdm(p: &mut $crate::Parser) -> Result<$enum_t, $crate::VdmError> {
+                $(
+                    if let Ok(val) = p.try_parse(<$t as $crate::FromVdmStr>::parse_vdm) {
+                        return Ok($v(val));
+                    }
+                )*
+                Err(p.error(concat!("expected ", stringify!($enum_t))))
+            }
+        }
+
+        impl $crate::VdmDisplay for $enum_t where $(for<'a> $t: $crate::VdmDisplay),* {
+            fn fmt_vdm(&self, f: &mut $crate::__private::fmt::Formatter) -> $crate::__private::fmt::Result {
+                match *self {
+                    $(
+                        $v(ref val) => $crate::VdmDisplay::fmt_vdm(val, f),
+                    )*
+                }
+            }
+
+            #[allow(unreachable_patterns)]
+            fn vdm_cmp(&self, other: &$enum_t) -> $crate::__private::cmp::Ordering {
+                match (self, other) {
+                    $(
+                        (&$v(ref a), &$v(ref b)) => $crate::VdmDisplay::vdm_cmp(a, b),
+                    )*
+                    _ => $crate::VdmDisplay::to_vdm_string(self)
+                             .cmp(&$crate::VdmDisplay::to_vdm_string(other)),
+                }
+            }
+        }
+
+        impl $crate::__private::fmt::Display for $enum_t where $(for<'a> $t: $crate::__private::fmt::Display),* {
+			fn fmt(&self, f: &mut $crate::__private::fmt::Formatter) -> $crate::__private::fmt::Result {
+				match *self {
+					$(
+						$v(ref val) => write!(f, "{}", val),
+					)*
+				}
+    		}
+		}
         
-        impl ::std::fmt::Debug for $enum_t {
-			fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        impl $crate::__private::fmt::Debug for $enum_t {
+			fn fmt(&self, f: &mut $crate::__private::fmt::Formatter) -> $crate::__private::fmt::Result {
 				match *self {
 					$(
 						$v(ref val) => write!(f, "{:?}", val),
@@ -86,11 +184,105 @@ macro_rules! impl_union {
     		}
 		}
     };
+    (@named $enum_t:ty: $( $t:ty as $e:ident :: $v:ident),*) => {
+        $crate::paste::paste! {
+            #[allow(non_snake_case, dead_code)]
+            impl $enum_t {
+                $(
+                    pub fn [<is_ $v>](&self) -> bool {
+                        $crate::IsType::<$t>::is_type(self)
+                    }
+
+                    #[allow(unreachable_patterns)]
+                    pub fn [<try_into_ $v>](self) -> Option<$t> {
+                        match self {
+                            $e::$v(val) => Some(val),
+                            _ => None,
+                        }
+                    }
+                )*
+            }
+        }
+    };
+    (@derives $enum_t:ty, $variants:tt, $($d:ident),*) => {
+        $( $crate::impl_union!(@derive $d $enum_t, $variants); )*
+    };
+    (@derive Clone $enum_t:ty, [$($v:path),*]) => {
+        impl Clone for $enum_t {
+            fn clone(&self) -> $enum_t {
+                match *self {
+                    $(
+                        $v(ref val) => $v(val.clone()),
+                    )*
+                }
+            }
+        }
+    };
+    (@derive PartialEq $enum_t:ty, [$($v:path),*]) => {
+        impl PartialEq for $enum_t {
+            #[allow(unreachable_patterns)]
+            fn eq(&self, other: &$enum_t) -> bool {
+                match (self, other) {
+                    $(
+                        (&$v(ref a), &$v(ref b)) => a == b,
+                    )*
+                    _ => false,
+                }
+            }
+        }
+    };
+    (@derive Eq $enum_t:ty, [$($v:path),*]) => {
+        impl Eq for $enum_t {}
+    };
+    (@derive Hash $enum_t:ty, [$($v:path),*]) => {
+        impl $crate::__private::hash::Hash for $enum_t {
+            fn hash<H: $crate::__private::hash::Hasher>(&self, state: &mut H) {
+                $crate::__private::hash::Hash::hash(&$crate::__private::mem::discriminant(self), state);
+
+                match *self {
+                    $(
+                        $v(ref val) => $crate::__private::hash::Hash::hash(val, state),
+                    )*
+                }
+            }
+        }
+    };
+    (@derive PartialOrd $enum_t:ty, [$($v:path),*]) => {
+        impl PartialOrd for $enum_t {
+            #[allow(unreachable_patterns)]
+            fn partial_cmp(&self, other: &$enum_t) -> Option<$crate::__private::cmp::Ordering> {
+                match (self, other) {
+                    $(
+                        (&$v(ref a), &$v(ref b)) => a.partial_cmp(b),
+                    )*
+                    _ => None,
+                }
+            }
+        }
+    };
+    ($enum_t:ty: $( $t:ty as $e:ident :: $v:ident),*) => {
+        $crate::impl_union!(@core $enum_t: $( $t as $e::$v),*);
+        $crate::impl_union!(@named $enum_t: $( $t as $e::$v),*);
+    };
+    ($enum_t:ty: $( $t:ty as $e:ident :: $v:ident),*; derive($($d:ident),*)) => {
+        $crate::impl_union!(@core $enum_t: $( $t as $e::$v),*);
+        $crate::impl_union!(@named $enum_t: $( $t as $e::$v),*);
+        $crate::impl_union!(@derives $enum_t, [$($e::$v),*], $($d),*);
+    };
+    ($enum_t:ty: $( $t:ty as $v:path),*) => {
+        $crate::impl_union!(@core $enum_t: $( $t as $v),*);
+    };
+    ($enum_t:ty: $( $t:ty as $v:path),*; derive($($d:ident),*)) => {
+        $crate::impl_union!(@core $enum_t: $( $t as $v),*);
+        $crate::impl_union!(@derives $enum_t, [$($v),*], $($d),*);
+    };
 }
 
 /// Declares a unit-like `pub struct`, and implements
-/// `std::fmt::Display` and `std::fmt::Debug` traits.
-/// Also derives `PartialEq, Eq, Hash, Clone and Copy` 
+/// `std::fmt::Display` and `std::fmt::Debug` traits, `VdmDisplay`, and
+/// `FromVdmStr` reading `<Name>`. With the `serde` feature, the quote
+/// serializes as its name.
+/// Also derives `PartialEq, Eq, PartialOrd, Ord, Hash, Clone and Copy` 
 ///  automatically.
 ///
 /// # Examples
@@ -100,36 +292,59 @@ macro_rules! impl_union {
 /// impl_quote! { Bar }
 /// 
 /// fn main() {
-/// 	let b1 = Bar;
-/// 	let b2 = Bar;
+///     let b1 = Bar;
+///     let b2 = Bar;
 ///    
-/// 	println!("b1: {}", b1); // prints: "b1: <Bar>"
-/// 	assert!(b1 == b2);
+///     println!("b1: {}", b1); // prints: "b1: <Bar>"
+///     assert!(b1 == b2);
 /// }
 /// ```
 #[macro_export]
 macro_rules! impl_quote {
     ($qt:ident) => {
-    	#[derive(PartialEq, Eq, Hash, Clone, Copy)]
+    	#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
     	pub struct $qt;
     	
-    	impl ::std::fmt::Display for $qt {
-			fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+    	impl $crate::__private::fmt::Display for $qt {
+			fn fmt(&self, f: &mut $crate::__private::fmt::Formatter) -> $crate::__private::fmt::Result {
 		        write!(f, "<{}>", stringify!($qt))
 	    	}
 		}
     	
-    	impl ::std::fmt::Debug for $qt {
-			fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+    	impl $crate::__private::fmt::Debug for $qt {
+			fn fmt(&self, f: &mut $crate::__private::fmt::Formatter) -> $crate::__private::fmt::Result {
 		        write!(f, "<{}>", stringify!($qt))
 	    	}
 		}   	
+
+		impl $crate::FromVdmStr for $qt {
+			fn parse_vdm(p: &mut $crate::Parser) -> Result<$qt, $crate::VdmError> {
+			    p.expect(concat!("<", stringify!($qt), ">"))?;
+			    Ok($qt)
+			}
+		}
+
+		impl $crate::VdmDisplay for $qt {
+			fn fmt_vdm(&self, f: &mut $crate::__private::fmt::Formatter) -> $crate::__private::fmt::Result {
+		        write!(f, "<{}>", stringify!($qt))
+	    	}
+		}
+
+		$crate::__serde_quote!($qt);
     };
 }
 
 /// Implements a trivial new() function
 /// for a struct.
 ///
+/// An optional `inv` clause implements `contracts::Invariant`
+/// for the record. `new` then checks the invariant according to the
+/// current `contracts::Policy`, and `try_new` returns the violation
+/// as a `VdmError`.
+///
+/// If all field types implement `FromVdmStr`, so does the record,
+/// reading `mk_Point(1, 2)`. Likewise for `VdmDisplay`, printing that form.
+///
 /// # Examples
 ///
 /// ```
@@ -142,9 +357,19 @@ macro_rules! impl_quote {
 ///
 ///impl_record! { Point: x as i32, y as i32 }
 ///
+///#[derive(PartialEq, Debug)]
+///pub struct Interval {
+///    pub lo: i32,
+///    pub hi: i32,
+///}
+///
+///impl_record! { Interval: lo as i32, hi as i32; inv i => i.lo <= i.hi }
+///
 ///pub fn main() {
-///	let p: Point = Point::new(1,2);
+///    let p: Point = Point::new(1,2);
 ///    assert_eq!(Point{x: 1, y: 2}, p);
+///
+///    assert!(Interval::try_new(1, 2).is_ok());
 ///}
 /// ```
 #[macro_export]
@@ -159,31 +384,845 @@ macro_rules! impl_record {
 			    }
 	    	}
 		}
+
+		$crate::impl_record!(@parse $rec: $( $n as $t),* => |rec| Ok(rec));
+		$crate::impl_record!(@display $rec: $( $n as $t),*);
+    };
+    ($rec:ident: $( $n:ident as $t:ty),* ; inv $p:ident => $inv:expr ) => {
+    	impl $rec {
+			pub fn new($( $n : $t),*) -> $rec {
+			    match $rec::try_new($( $n ),*) {
+			        Ok(rec) => rec,
+			        Err(err) => panic!("{}", err),
+			    }
+	    	}
+
+			pub fn try_new($( $n : $t),*) -> Result<$rec, $crate::VdmError> {
+			    let rec = $rec {
+			        $(
+			            $n: $n,
+			        )*
+			    };
+			    $crate::contracts::check_value(rec, concat!("inv_", stringify!($rec)))
+	    	}
+		}
+
+		impl $crate::contracts::Invariant for $rec {
+			fn inv(&self) -> bool {
+			    let $p = self;
+			    $inv
+			}
+		}
+
+		$crate::impl_record!(@parse $rec: $( $n as $t),* => |rec| {
+		    $crate::contracts::check_value(rec, concat!("inv_", stringify!($rec)))
+		});
+		$crate::impl_record!(@display $rec: $( $n as $t),*);
+    };
+    (@parse $rec:ident: $( $n:ident as $t:ty),* => $check:expr) => {
+		impl $crate::FromVdmStr for $rec where $(for<'a> $t: $crate::FromVdmStr),* {
+			#[allow(unused_mut, unused_assignments)]
+			fn parse_vdm(p: &mut $crate::Parser) -> Result<$rec, $crate::VdmError> {
+			    p.expect(concat!("mk_", stringify!($rec)))?;
+			    p.expect("(")?;
+			    let mut first = true;
+			    $(
+			        if !first {
+			            p.expect(",")?;
+			        }
+			        first = false;
+			        let $n: $t = $crate::FromVdmStr::parse_vdm(p)?;
+			    )*
+			    p.expect(")")?;
+
+			    let check: fn($rec) -> Result<$rec, $crate::VdmError> = $check;
+			    check($rec { $( $n: $n, )* })
+			}
+		}
+    };
+    (@display $rec:ident: $( $n:ident as $t:ty),*) => {
+		impl $crate::VdmDisplay for $rec where $(for<'a> $t: $crate::VdmDisplay),* {
+			#[allow(unused_mut, unused_assignments)]
+			fn fmt_vdm(&self, f: &mut $crate::__private::fmt::Formatter) -> $crate::__private::fmt::Result {
+			    write!(f, "mk_{}(", stringify!($rec))?;
+			    let mut first = true;
+			    $(
+			        if !first {
+			            f.write_str(", ")?;
+			        }
+			        first = false;
+			        $crate::VdmDisplay::fmt_vdm(&self.$n, f)?;
+			    )*
+			    f.write_str(")")
+			}
+
+			fn vdm_cmp(&self, other: &$rec) -> $crate::__private::cmp::Ordering {
+			    $crate::__private::cmp::Ordering::Equal
+			        $(.then_with(|| $crate::VdmDisplay::vdm_cmp(&self.$n, &other.$n)))*
+			}
+		}
     };
 }
 
+/// Declares a record type `pub struct $rec`, the full VDM counterpart
+/// of `impl_record!`. Generates:
+///
+/// * `new` and `try_new`, checking the optional `inv` clause
+///   according to the current `contracts::Policy`.
+/// * `mu_<field>` functional updates, also checking the invariant, and
+///   `try_mu_<field>` returning the violation as a `VdmError`.
+/// * `PartialEq`, `Eq` and `Hash` over all fields, except those
+///   declared with the equality abstraction marker `:-`.
+/// * `PartialOrd` from the optional `ord` clause. Without a clause,
+///   only equal records are comparable.
+/// * `Debug` in VDM form, e.g. `mk_Point(1, 2)`.
+/// * `FromVdmStr` reading the same form, and `VdmDisplay` and `Display`
+///   printing it, if all field types implement them.
+/// * With the `serde` feature, `Serialize` and `Deserialize` as a struct,
+///   if all field types implement them. Deserializing checks the invariant.
+/// * An `is_<rec>` function, testing records and `impl_union!` unions.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate codegen_runtime;
+/// def_record! {
+///     Point { x: i32, y: i32, label :- String };
+///     ord a < b => a.x < b.x;
+///     inv p => p.x >= 0
+/// }
+///
+/// fn main() {
+///     let p = Point::new(1, 2, "a".to_owned());
+///     let q = p.mu_x(5).mu_label("b".to_owned());
+///
+///     assert_eq!("mk_Point(5, 2, \"b\")", q.to_string());
+///     assert_eq!(Point::new(1, 2, "c".to_owned()), p);
+///     assert!(p < q);
+///     assert!(is_Point(&q));
+/// }
+/// ```
+#[macro_export]
+macro_rules! def_record {
+    ($(#[$attr:meta])* $rec:ident { $($fields:tt)* } $($opts:tt)*) => {
+        $crate::def_record!(@fields [$(#[$attr])* $rec] [] [] [$($fields)*] $($opts)*);
+    };
+
+    (@fields $head:tt [$($acc:tt)*] [$($eq:tt)*] [$f:ident :- $t:ty, $($rest:tt)*] $($opts:tt)*) => {
+        $crate::def_record!(@fields $head [$($acc)* $f: $t,] [$($eq)*] [$($rest)*] $($opts)*);
+    };
+    (@fields $head:tt [$($acc:tt)*] [$($eq:tt)*] [$f:ident :- $t:ty] $($opts:tt)*) => {
+        $crate::def_record!(@fields $head [$($acc)* $f: $t,] [$($eq)*] [] $($opts)*);
+    };
+    (@fields $head:tt [$($acc:tt)*] [$($eq:tt)*] [$f:ident : $t:ty, $($rest:tt)*] $($opts:tt)*) => {
+        $crate::def_record!(@fields $head [$($acc)* $f: $t,] [$($eq)* $f,] [$($rest)*] $($opts)*);
+    };
+    (@fields $head:tt [$($acc:tt)*] [$($eq:tt)*] [$f:ident : $t:ty] $($opts:tt)*) => {
+        $crate::def_record!(@fields $head [$($acc)* $f: $t,] [$($eq)* $f,] [] $($opts)*);
+    };
+
+    (@fields $head:tt $acc:tt $eq:tt []) => {
+        $crate::def_record!(@gen $head $acc $eq [a b => false] [p => true]);
+    };
+    (@fields $head:tt $acc:tt $eq:tt []; ord $a:ident < $b:ident => $ord:expr) => {
+        $crate::def_record!(@gen $head $acc $eq [$a $b => $ord] [p => true]);
+    };
+    (@fields $head:tt $acc:tt $eq:tt []; inv $p:ident => $inv:expr) => {
+        $crate::def_record!(@gen $head $acc $eq [a b => false] [$p => $inv]);
+    };
+    (@fields $head:tt $acc:tt $eq:tt [];
+     ord $a:ident < $b:ident => $ord:expr; inv $p:ident => $inv:expr) => {
+        $crate::def_record!(@gen $head $acc $eq [$a $b => $ord] [$p => $inv]);
+    };
+
+    (@gen [$(#[$attr:meta])* $rec:ident] [$($af:ident: $at:ty,)*] [$($ef:ident,)*]
+     [$a:ident $b:ident => $ord:expr] [$p:ident => $inv:expr]) => {
+        $crate::paste::paste! {
+            $(#[$attr])*
+            #[derive(Clone)]
+            pub struct $rec {
+                $(pub $af: $at,)*
+            }
+
+            #[allow(dead_code)]
+            impl $rec {
+                pub fn new($($af: $at),*) -> $rec {
+                    match $rec::try_new($($af),*) {
+                        Ok(rec) => rec,
+                        Err(err) => panic!("{}", err),
+                    }
+                }
+
+                pub fn try_new($($af: $at),*) -> Result<$rec, $crate::VdmError> {
+                    $crate::contracts::check_value($rec { $($af: $af,)* },
+                                                   concat!("inv_", stringify!($rec)))
+                }
+
+                $(
+                    pub fn [<mu_ $af>](&self, val: $at) -> $rec {
+                        match self.[<try_mu_ $af>](val) {
+                            Ok(rec) => rec,
+                            Err(err) => panic!("{}", err),
+                        }
+                    }
+
+                    pub fn [<try_mu_ $af>](&self, val: $at) -> Result<$rec, $crate::VdmError> {
+                        let mut rec = self.clone();
+                        rec.$af = val;
+                        $crate::contracts::check_value(rec, concat!("inv_", stringify!($rec)))
+                    }
+                )*
+            }
+
+            impl $crate::contracts::Invariant for $rec {
+                #[allow(unused_variables)]
+                fn inv(&self) -> bool {
+                    let $p = self;
+                    $inv
+                }
+            }
+
+            impl PartialEq for $rec {
+                fn eq(&self, other: &$rec) -> bool {
+                    true $(&& self.$ef == other.$ef)*
+                }
+            }
+
+            impl Eq for $rec {}
+
+            impl $crate::__private::hash::Hash for $rec {
+                #[allow(unused_variables)]
+                fn hash<H: $crate::__private::hash::Hasher>(&self, state: &mut H) {
+                    $( $crate::__private::hash::Hash::hash(&self.$ef, state); )*
+                }
+            }
+
+            impl PartialOrd for $rec {
+                #[allow(unused_variables)]
+                fn partial_cmp(&self, other: &$rec) -> Option<$crate::__private::cmp::Ordering> {
+                    let lt = |$a: &$rec, $b: &$rec| -> bool { $ord };
+
+                    if self == other {
+                        Some($crate::__private::cmp::Ordering::Equal)
+                    } else if lt(self, other) {
+                        Some($crate::__private::cmp::Ordering::Less)
+                    } else if lt(other, self) {
+                        Some($crate::__private::cmp::Ordering::Greater)
+                    } else {
+                        None
+                    }
+                }
+            }
+
+            impl $crate::__private::fmt::Display for $rec where $(for<'a> $at: $crate::VdmDisplay),* {
+                fn fmt(&self, f: &mut $crate::__private::fmt::Formatter) -> $crate::__private::fmt::Result {
+                    $crate::VdmDisplay::fmt_vdm(self, f)
+                }
+            }
+
+            impl $crate::__private::fmt::Debug for $rec {
+                fn fmt(&self, f: &mut $crate::__private::fmt::Formatter) -> $crate::__private::fmt::Result {
+                    let fields: $crate::__private::Vec<$crate::__private::String> = $crate::__private::vec![$($crate::__private::format!("{:?}", self.$af)),*];
+                    write!(f, "mk_{}({})", stringify!($rec), fields.join(", "))
+                }
+            }
+
+            impl $crate::IsType<$rec> for $rec {
+                fn is_type(&self) -> bool {
+                    true
+                }
+            }
+
+            $crate::impl_record!(@parse $rec: $($af as $at),* => |rec| {
+                $crate::contracts::check_value(rec, concat!("inv_", stringify!($rec)))
+            });
+            $crate::impl_record!(@display $rec: $($af as $at),*);
+            $crate::__serde_record!($rec: $($af: $at),*);
+
+            /// VDM type test, for records and unions containing the record.
+            #[allow(non_snake_case, dead_code)]
+            pub fn [<is_ $rec>]<V: $crate::IsType<$rec>>(val: &V) -> bool {
+                val.is_type()
+            }
+        }
+    };
+}
 
-/// Creates a set of tuples, with the cartesian product of 
-/// the input sets.
+/// Declares a named subtype `pub struct $name($t)` restricted by an
+/// invariant. The invariant is checked according to the current
+/// `contracts::Policy` on construction and on assignment. Also derives
+/// `PartialEq, Eq, PartialOrd, Hash and Clone`, and delegates `Display`, `Debug`,
+/// `VdmDisplay`, `Deref` and, with the `serde` feature, serialization to the
+/// underlying value.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate codegen_runtime;
+/// use codegen_runtime::contracts::{self, Policy};
+///
+/// impl_subtype! { Even: u64; inv n => n % 2 == 0 }
+///
+/// fn main() {
+///     contracts::set_thread_policy(Some(Policy::Error));
+///
+///     let mut e = Even::new(4);
+///     assert_eq!(4, *e);
+///
+///     assert!(Even::try_new(3).is_err());
+///     assert!(e.assign(5).is_err());
+///     assert_eq!(4, *e);
+/// }
+/// ```
+#[macro_export]
+macro_rules! impl_subtype {
+    ($name:ident: $t:ty; inv $p:ident => $inv:expr) => {
+    	#[derive(PartialEq, Eq, PartialOrd, Hash, Clone)]
+    	pub struct $name($t);
+
+    	#[allow(dead_code)]
+    	impl $name {
+			pub fn new(val: $t) -> $name {
+			    match $name::try_new(val) {
+			        Ok(v) => v,
+			        Err(err) => panic!("{}", err),
+			    }
+			}
+
+			pub fn try_new(val: $t) -> Result<$name, $crate::VdmError> {
+			    $crate::contracts::check_value($name(val), concat!("inv_", stringify!($name)))
+			}
+
+			/// Assigns a new value, unless it violates the invariant.
+			pub fn assign(&mut self, val: $t) -> Result<(), $crate::VdmError> {
+			    *self = $name::try_new(val)?;
+			    Ok(())
+			}
+
+			pub fn into_inner(self) -> $t {
+			    self.0
+			}
+		}
+
+		impl $crate::contracts::Invariant for $name {
+			fn inv(&self) -> bool {
+			    let $p = self.0.clone();
+			    $inv
+			}
+		}
+
+		impl $crate::__private::ops::Deref for $name {
+			type Target = $t;
+
+			fn deref(&self) -> &$t {
+			    &self.0
+			}
+		}
+
+		impl $crate::__private::fmt::Display for $name {
+			fn fmt(&self, f: &mut $crate::__private::fmt::Formatter) -> $crate::__private::fmt::Result {
+		        write!(f, "{}", self.0)
+	    	}
+		}
+
+		impl $crate::__private::fmt::Debug for $name {
+			fn fmt(&self, f: &mut $crate::__private::fmt::Formatter) -> $crate::__private::fmt::Result {
+		        write!(f, "{:?}", self.0)
+	    	}
+		}
+
+		impl $crate::VdmDisplay for $name where for<'a> $t: $crate::VdmDisplay {
+			fn fmt_vdm(&self, f: &mut $crate::__private::fmt::Formatter) -> $crate::__private::fmt::Result {
+		        $crate::VdmDisplay::fmt_vdm(&self.0, f)
+	    	}
+
+			fn vdm_cmp(&self, other: &$name) -> $crate::__private::cmp::Ordering {
+		        $crate::VdmDisplay::vdm_cmp(&self.0, &other.0)
+	    	}
+		}
+
+		$crate::__serde_subtype!($name: $t);
+    };
+}
+
+/// Implements `Class` for the type of the objects of a VDM++ class, and
+/// gives it the associated constant `CLASS`, the `ClassDesc` to pass to
+/// `ObjRef::isofclass`. Superclasses follow the name; the listed instance
+/// variables are printed by `ObjRef`, and must implement `VdmDisplay`.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate codegen_runtime;
+/// use codegen_runtime::ObjRef;
+///
+/// struct Account {
+///     balance: u64,
+/// }
+///
+/// struct Savings {
+///     balance: u64,
+///     rate: u64,
+/// }
+///
+/// impl_class! { Account { balance } }
+/// impl_class! { Savings: Account { balance, rate } }
+///
+/// fn main() {
+///     let s = ObjRef::new(Savings { balance: 100, rate: 2 });
+///
+///     assert!(s.isofclass(Account::CLASS));
+///     assert!(s.isofbaseclass(Account::CLASS));
+///     assert!(s.to_string().ends_with(", balance := 100, rate := 2}"));
+/// }
+/// ```
+#[macro_export]
+macro_rules! impl_class {
+    ($class:ident { $($f:ident),* }) => {
+        $crate::impl_class!($class: { $($f),* });
+    };
+    ($class:ident: $($sup:ident),* { $($f:ident),* }) => {
+		impl $class {
+			#[allow(dead_code)]
+			pub const CLASS: &'static $crate::ClassDesc = &$crate::ClassDesc {
+			    name: stringify!($class),
+			    supers: &[$($sup::CLASS),*],
+			};
+		}
+
+		impl $crate::Class for $class {
+			fn class(&self) -> &'static $crate::ClassDesc {
+			    $class::CLASS
+			}
+
+			fn fields(&self) -> $crate::__private::Vec<(&'static str, $crate::__private::String)> {
+			    $crate::__private::vec![$((stringify!($f), $crate::VdmDisplay::to_vdm_string(&self.$f))),*]
+			}
+		}
+    };
+}
+
+// The serde impls of the generated types. These are selected by the `serde`
+// feature of this crate, which a `#[cfg]` in the expansion would not see.
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __serde_quote {
+    ($qt:ident) => {
+		impl $crate::serde::Serialize for $qt {
+			fn serialize<S: $crate::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+			    serializer.serialize_str(stringify!($qt))
+			}
+		}
+
+		impl<'de> $crate::serde::Deserialize<'de> for $qt {
+			fn deserialize<D: $crate::serde::Deserializer<'de>>(deserializer: D) -> Result<$qt, D::Error> {
+			    let name = <$crate::__private::String as $crate::serde::Deserialize>::deserialize(deserializer)?;
+
+			    if name == stringify!($qt) {
+			        Ok($qt)
+			    } else {
+			        Err($crate::serde::de::Error::invalid_value($crate::serde::de::Unexpected::Str(&name),
+			                                                    &stringify!($qt)))
+			    }
+			}
+		}
+    };
+}
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __serde_record {
+    ($rec:ident: $( $n:ident: $t:ty),*) => {
+		impl $crate::serde::Serialize for $rec where $(for<'a> $t: $crate::serde::Serialize),* {
+			fn serialize<S: $crate::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+			    use $crate::serde::ser::SerializeStruct;
+
+			    let len = [$(stringify!($n)),*].len();
+			    let mut s = serializer.serialize_struct(stringify!($rec), len)?;
+			    $( s.serialize_field(stringify!($n), &self.$n)?; )*
+			    s.end()
+			}
+		}
+
+		impl<'de> $crate::serde::Deserialize<'de> for $rec
+		    where $(for<'a> $t: $crate::serde::de::DeserializeOwned),*
+		{
+			fn deserialize<D: $crate::serde::Deserializer<'de>>(deserializer: D) -> Result<$rec, D::Error> {
+			    struct RecordVisitor;
+
+			    impl<'de> $crate::serde::de::Visitor<'de> for RecordVisitor
+			        where $(for<'a> $t: $crate::serde::de::DeserializeOwned),*
+			    {
+			        type Value = $rec;
+
+			        fn expecting(&self, f: &mut $crate::__private::fmt::Formatter) -> $crate::__private::fmt::Result {
+			            write!(f, concat!("a ", stringify!($rec), " record"))
+			        }
+
+			        fn visit_seq<A>(self, mut seq: A) -> Result<$rec, A::Error>
+			            where A: $crate::serde::de::SeqAccess<'de>
+			        {
+			            let mut index = 0;
+			            $(
+			                let $n: $t = match seq.next_element()? {
+			                    Some(val) => val,
+			                    None => return Err($crate::serde::de::Error::invalid_length(index, &self)),
+			                };
+			                index += 1;
+			            )*
+			            let _ = index;
+
+			            $crate::contracts::check_value($rec { $( $n: $n, )* },
+			                                           concat!("inv_", stringify!($rec)))
+			                .map_err($crate::serde::de::Error::custom)
+			        }
+
+			        fn visit_map<A>(self, mut map: A) -> Result<$rec, A::Error>
+			            where A: $crate::serde::de::MapAccess<'de>
+			        {
+			            $( let mut $n: Option<$t> = None; )*
+
+			            while let Some(key) = map.next_key::<$crate::__private::String>()? {
+			                match key.as_str() {
+			                    $( stringify!($n) => $n = Some(map.next_value()?), )*
+			                    _ => {
+			                        map.next_value::<$crate::serde::de::IgnoredAny>()?;
+			                    }
+			                }
+			            }
+
+			            $(
+			                let $n = match $n {
+			                    Some(val) => val,
+			                    None => return Err($crate::serde::de::Error::missing_field(stringify!($n))),
+			                };
+			            )*
+
+			            $crate::contracts::check_value($rec { $( $n: $n, )* },
+			                                           concat!("inv_", stringify!($rec)))
+			                .map_err($crate::serde::de::Error::custom)
+			        }
+			    }
+
+			    const FIELDS: &[&str] = &[$(stringify!($n)),*];
+			    deserializer.deserialize_struct(stringify!($rec), FIELDS, RecordVisitor)
+			}
+		}
+    };
+}
+
+#[cfg(feature = "serde")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __serde_subtype {
+    ($name:ident: $t:ty) => {
+		impl $crate::serde::Serialize for $name where for<'a> $t: $crate::serde::Serialize {
+			fn serialize<S: $crate::serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+			    $crate::serde::Serialize::serialize(&self.0, serializer)
+			}
+		}
+
+		impl<'de> $crate::serde::Deserialize<'de> for $name
+		    where for<'a> $t: $crate::serde::Deserialize<'de>
+		{
+			fn deserialize<D: $crate::serde::Deserializer<'de>>(deserializer: D) -> Result<$name, D::Error> {
+			    let val = <$t as $crate::serde::Deserialize>::deserialize(deserializer)?;
+			    $name::try_new(val).map_err($crate::serde::de::Error::custom)
+			}
+		}
+    };
+}
+
+#[cfg(not(feature = "serde"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __serde_quote {
+    ($($any:tt)*) => {};
+}
+
+#[cfg(not(feature = "serde"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __serde_record {
+    ($($any:tt)*) => {};
+}
+
+#[cfg(not(feature = "serde"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __serde_subtype {
+    ($($any:tt)*) => {};
+}
+
+/// Checks a precondition according to the current `contracts::Policy`.
+/// Evaluates to `Result<(), VdmError>`.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate codegen_runtime;
+/// use codegen_runtime::VdmError;
+///
+/// fn half(n: u64) -> Result<u64, VdmError> {
+///     check_pre!(n % 2 == 0)?;
+///     Ok(n / 2)
+/// }
+///
+/// fn main() {
+///     assert_eq!(Ok(2), half(4));
+/// }
+/// ```
+#[macro_export]
+macro_rules! check_pre {
+    ($cond:expr) => {
+    	$crate::contracts::check($crate::contracts::Condition::Pre, $cond, stringify!($cond))
+    };
+}
+
+/// Checks a postcondition according to the current `contracts::Policy`.
+/// Evaluates to `Result<(), VdmError>`.
+#[macro_export]
+macro_rules! check_post {
+    ($cond:expr) => {
+    	$crate::contracts::check($crate::contracts::Condition::Post, $cond, stringify!($cond))
+    };
+}
+
+/// Checks the invariant of a value implementing `contracts::Invariant`
+/// according to the current `contracts::Policy`.
+/// Evaluates to `Result<(), VdmError>`.
+#[macro_export]
+macro_rules! check_inv {
+    ($val:expr) => {
+    	$crate::contracts::check($crate::contracts::Condition::Inv,
+    	                         $crate::contracts::Invariant::inv(&$val),
+    	                         stringify!($val))
+    };
+}
+
+/// Creates a set of VDM tuples, with the cartesian product of 
+/// the input sets. A single set yields the 1-tuples `mk_(x)`.
 ///
 /// # Examples
 ///
 /// ```
 /// # #[macro_use] extern crate codegen_runtime; 
-/// # #[macro_use] extern crate itertools;
 /// use codegen_runtime::*;
 /// 
 /// fn main() {
-///		let s1 = set!{1,2};
-///		let s2 = set!{3,4};
-///		let cart = cartesian_set!(s1, s2);
-/// 	let result = set!{(1,3), (1,4), (2,3), (2,4)};
-/// 	assert_eq!(result, cart);
+///     let s1 = set!{1,2};
+///     let s2 = set!{3,4};
+///     let cart = cartesian_set!(s1, s2);
+///     let result = set!{mk_tuple!(1,3), mk_tuple!(1,4), mk_tuple!(2,3), mk_tuple!(2,4)};
+///     assert_eq!(result, cart);
 /// }
 /// ```
 #[macro_export]
 macro_rules! cartesian_set {
+    (@tuples $S:expr) => {
+        $S.iter().map(|x| $crate::Tuple(x.clone(), ())).collect::<$crate::Set<_>>()
+    };
+    (@tuples $S:expr, $($rest:expr),+) => {{
+        let rest = $crate::cartesian_set!(@tuples $($rest),+);
+        $S.iter()
+          .flat_map(|x| rest.iter().map(move |r| $crate::Tuple(x.clone(), r.clone())))
+          .collect::<$crate::Set<_>>()
+    }};
     ( $($S:expr),+ ) => {
-    	iproduct!($($S.iter().cloned()),*).collect::<Set<_>>()    	
+        $crate::cartesian_set!(@tuples $($S),+)
     };
 }
+
+/// Creates a VDM tuple, `mk_(...)` in VDM, of any arity.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate codegen_runtime;
+/// fn main() {
+///     let t = mk_tuple!(1, 'a', true);
+///
+///     assert_eq!("mk_(1, a, true)", t.to_string());
+/// }
+/// ```
+#[macro_export]
+macro_rules! mk_tuple {
+    () => ( () );
+    ($head:expr $(, $tail:expr)*) => (
+        $crate::Tuple($head, $crate::mk_tuple!($($tail),*))
+    );
+}
+
+/// Names the type of a tuple created by `mk_tuple!`.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate codegen_runtime;
+/// fn main() {
+///     let t: tuple_t!(i32, char) = mk_tuple!(1, 'a');
+/// #   let _ = t;
+/// }
+/// ```
+#[macro_export]
+macro_rules! tuple_t {
+    () => ( () );
+    ($head:ty $(, $tail:ty)*) => (
+        $crate::Tuple<$head, $crate::tuple_t!($($tail),*)>
+    );
+}
+
+/// Selects field `n` of a tuple, 1-based like the VDM `t.#n` expression.
+///
+/// `n` must be a literal from 1 to 20. Fields past the 20th are reached
+/// by hand: `t.#n` is `t` followed by `n - 1` times `.1` and then `.0`.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate codegen_runtime;
+/// fn main() {
+///     let t = mk_tuple!(1, 'a', true);
+///
+///     assert_eq!('a', tuple_field!(t, 2));
+/// }
+/// ```
+#[macro_export]
+macro_rules! tuple_field {
+    ($t:expr, 1) => ( ($t).0 );
+    ($t:expr, 2) => ( $crate::tuple_field!(($t).1, 1) );
+    ($t:expr, 3) => ( $crate::tuple_field!(($t).1, 2) );
+    ($t:expr, 4) => ( $crate::tuple_field!(($t).1, 3) );
+    ($t:expr, 5) => ( $crate::tuple_field!(($t).1, 4) );
+    ($t:expr, 6) => ( $crate::tuple_field!(($t).1, 5) );
+    ($t:expr, 7) => ( $crate::tuple_field!(($t).1, 6) );
+    ($t:expr, 8) => ( $crate::tuple_field!(($t).1, 7) );
+    ($t:expr, 9) => ( $crate::tuple_field!(($t).1, 8) );
+    ($t:expr, 10) => ( $crate::tuple_field!(($t).1, 9) );
+    ($t:expr, 11) => ( $crate::tuple_field!(($t).1, 10) );
+    ($t:expr, 12) => ( $crate::tuple_field!(($t).1, 11) );
+    ($t:expr, 13) => ( $crate::tuple_field!(($t).1, 12) );
+    ($t:expr, 14) => ( $crate::tuple_field!(($t).1, 13) );
+    ($t:expr, 15) => ( $crate::tuple_field!(($t).1, 14) );
+    ($t:expr, 16) => ( $crate::tuple_field!(($t).1, 15) );
+    ($t:expr, 17) => ( $crate::tuple_field!(($t).1, 16) );
+    ($t:expr, 18) => ( $crate::tuple_field!(($t).1, 17) );
+    ($t:expr, 19) => ( $crate::tuple_field!(($t).1, 18) );
+    ($t:expr, 20) => ( $crate::tuple_field!(($t).1, 19) );
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::{ToOwned, ToString};
+    use contracts::{self, Policy};
+    use VdmError;
+    use Set;
+
+    def_record! {
+        Point { x: i64, y: i64 };
+        ord a < b => a.x < b.x
+    }
+
+    def_record! {
+        Tagged { val: i64, tag :- &'static str };
+        inv t => t.val > 0
+    }
+
+    enum Shape {
+        P(Point),
+        T(Tagged),
+    }
+
+    impl_union!{ Shape: Point as Shape::P, Tagged as Shape::T; derive(Clone, PartialEq, Eq, Hash, PartialOrd) }
+
+    mod nested {
+        pub enum Num {
+            R(f64),
+            C(char),
+        }
+    }
+
+    impl_union!{ nested::Num: f64 as nested::Num::R, char as nested::Num::C }
+
+    #[test]
+    fn union_variants() {
+        let s = Shape::from(Tagged::new(1, "a"));
+
+        assert!(s.is_T());
+        assert!(!s.is_P());
+        assert_eq!(None, s.clone().try_into_P());
+        assert_eq!(Some(Tagged::new(1, "a")), s.try_into_T());
+    }
+
+    #[test]
+    fn union_equality() {
+        let s = set!{Shape::from(Point::new(1, 2)),
+                     Shape::from(Point::new(1, 2)),
+                     Shape::from(Tagged::new(1, "a"))};
+
+        assert_eq!(2, s.card());
+        assert!(Shape::from(Point::new(1, 2)) < Shape::from(Point::new(2, 0)));
+        assert_eq!(None, Shape::from(Point::new(1, 2)).partial_cmp(&Shape::from(Tagged::new(1, "a"))));
+    }
+
+    #[test]
+    fn union_display() {
+        assert_eq!("mk_Point(1, 2)", Shape::from(Point::new(1, 2)).to_string());
+    }
+
+    #[test]
+    fn union_variant_paths() {
+        let n = nested::Num::from(2.5);
+
+        assert!(::IsType::<f64>::is_type(&n));
+        assert_eq!("2.5", n.to_string());
+        assert_eq!('c', char::from(nested::Num::from('c')));
+    }
+
+    #[test]
+    fn mu() {
+        let p = Point::new(1, 2);
+
+        assert_eq!(Point::new(1, 5), p.mu_y(5));
+        assert_eq!(Point::new(1, 2), p);
+    }
+
+    #[test]
+    fn equality_abstraction() {
+        let s = set!{Tagged::new(1, "a"), Tagged::new(1, "b")};
+
+        assert_eq!(1, s.card());
+    }
+
+    #[test]
+    fn ordering() {
+        assert!(Point::new(1, 9) < Point::new(2, 0));
+        assert_eq!(None, Tagged::new(1, "a").partial_cmp(&Tagged::new(2, "a")));
+    }
+
+    #[test]
+    fn invariant() {
+        contracts::set_thread_policy(Some(Policy::Error));
+
+        assert_eq!(Err(VdmError::InvariantViolated("inv_Tagged".to_owned())),
+                   Tagged::try_new(0, "a"));
+        assert_eq!(Err(VdmError::InvariantViolated("inv_Tagged".to_owned())),
+                   Tagged::new(1, "a").try_mu_val(-1));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invariant violated: inv_Tagged")]
+    fn mu_invariant() {
+        let _ = Tagged::new(1, "a").mu_val(-1);
+    }
+
+    #[test]
+    fn display_formatting() {
+        assert_eq!("mk_Point(1, 2)", Point::new(1, 2).to_string());
+        assert_eq!("mk_Tagged(1, \"a\")", Tagged::new(1, "a").to_string());
+        assert_eq!("mk_Tagged(1, \"a\")", format!("{:?}", Tagged::new(1, "a")));
+    }
+}
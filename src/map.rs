@@ -1,220 +1,372 @@
-use std::collections::HashMap;
-use std::collections::hash_map::Iter;
-use std::hash::{Hash, Hasher};
-use std::fmt;
-use std::ops::{Deref, DerefMut};
-use std::iter::{Iterator, FromIterator, IntoIterator};
+use lib::collections::HashMap;
+#[cfg(not(feature = "persistent"))]
+use lib::collections::hash_map;
+#[cfg(feature = "persistent")]
+use im::hashmap;
+use lib::hash::{Hash, Hasher};
+use lib::fmt;
+use lib::ops::Index;
+use lib::iter::{Iterator, Extend, FromIterator, IntoIterator};
+use lib::Vec;
 use util::*;
 use Set;
+use VdmError;
+use error::unwrap_vdm;
+
+/// Implements the VDM map operators for `$map`, a wrapper around the
+/// backing map `$inner` whose keys are bounded by `$bound`. Shared by `Map`
+/// and `OrdMap`; `Iter` and `IntoIter`, wrapping the iterators of the
+/// backing map, must be in scope, and domains and ranges are returned as
+/// `$set`. Only uses operations that std, `hashbrown` and `im` maps have
+/// in common.
+macro_rules! map_type {
+    ($map:ident, $inner:ident, $set:ident, $($bound:tt)+) => {
+        impl<K: $($bound)+, V> $map<K, V> {
+            pub fn len(&self) -> usize {
+                self.inner.len()
+            }
 
-#[derive(Clone, PartialEq, Eq)]
-pub struct Map<K: Hash + Eq, V> {
-    inner: HashMap<K, V>,
-}
+            pub fn is_empty(&self) -> bool {
+                self.inner.is_empty()
+            }
 
-impl<K, V> Map<K, V>
-    where K: Hash + Eq + Clone,
-          V: Hash + Eq + Clone
-{
-    pub fn new() -> Map<K, V> {
-        Map { inner: HashMap::new() }
-    }
+            pub fn iter(&self) -> Iter<'_, K, V> {
+                Iter(self.inner.iter())
+            }
 
-    pub fn domain(&self) -> Set<K> {
-        self.keys().cloned().collect()
-    }
+            pub fn keys(&self) -> impl ExactSizeIterator<Item = &K> {
+                self.iter().map(|(k, _)| k)
+            }
 
-    pub fn range(&self) -> Set<V> {
-        self.values().cloned().collect()
-    }
+            pub fn values(&self) -> impl ExactSizeIterator<Item = &V> {
+                self.iter().map(|(_, v)| v)
+            }
 
-    pub fn get(&self, key: K) -> V {
-        self.inner.get(&key).expect("No such key in map.").clone()
-    }
+            pub fn contains_key(&self, key: &K) -> bool {
+                self.inner.contains_key(key)
+            }
+        }
 
-    pub fn get_ref(&mut self, key: K) -> &mut V {
-        self.inner.get_mut(&key).expect("No such key in map.")
-    }
+        impl<K, V> $map<K, V>
+            where K: $($bound)+ + Clone,
+                  V: Clone
+        {
+            pub fn new() -> $map<K, V> {
+                $map { inner: $inner::new() }
+            }
 
-    pub fn merge(&self, other: Map<K, V>) -> Map<K, V> {
-        if !self.is_compatible(&other) {
-            panic!("Merging requires maps to be compatible");
-        } else {
-            self.ovrride(other)
-        }
-    }
+            /// Adds the maplet `key |-> value`, and returns the value `key`
+            /// mapped to before, if any.
+            pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+                self.inner.insert(key, value)
+            }
 
-    pub fn ovrride(&self, other: Map<K, V>) -> Map<K, V> {
-        let i1 = self.iter();
-        let i2 = other.iter();
-        i1.chain(i2).map(|(k, v)| (k.clone(), v.clone())).collect()
-    }
+            /// Removes the maplet of `key`, and returns its value.
+            pub fn remove(&mut self, key: &K) -> Option<V> {
+                self.inner.remove(key)
+            }
 
-    pub fn dom_restrict_to(&self, allowed: Set<K>) -> Map<K, V> {
-        self.iter()
-            .filter(|&(k, _)| allowed.contains(k))
-            .map(|(k, v)| (k.clone(), v.clone()))
-            .collect()
-    }
+            pub fn domain(&self) -> $set<K> {
+                self.keys().cloned().collect()
+            }
 
-    pub fn dom_restrict_by(&self, restricted: Set<K>) -> Map<K, V> {
-        self.iter()
-            .filter(|&(k, _)| !restricted.contains(k))
-            .map(|(k, v)| (k.clone(), v.clone()))
-            .collect()
-    }
+            pub fn range(&self) -> $set<V>
+                where V: $($bound)+
+            {
+                self.values().cloned().collect()
+            }
 
-    pub fn rng_restrict_to(&self, allowed: Set<V>) -> Map<K, V> {
-        self.iter()
-            .filter(|&(_, v)| allowed.contains(v))
-            .map(|(k, v)| (k.clone(), v.clone()))
-            .collect()
-    }
+            pub fn get(&self, key: K) -> V {
+                unwrap_vdm(self.try_get(key))
+            }
 
-    pub fn rng_restrict_by(&self, restricted: Set<V>) -> Map<K, V> {
-        self.iter()
-            .filter(|&(_, v)| !restricted.contains(v))
-            .map(|(k, v)| (k.clone(), v.clone()))
-            .collect()
-    }
+            pub fn try_get(&self, key: K) -> Result<V, VdmError> {
+                self.inner.get(&key).cloned().ok_or(VdmError::KeyNotInDomain)
+            }
+
+            pub fn get_ref(&mut self, key: K) -> &mut V {
+                unwrap_vdm(self.try_get_ref(key))
+            }
+
+            pub fn try_get_ref(&mut self, key: K) -> Result<&mut V, VdmError> {
+                self.inner.get_mut(&key).ok_or(VdmError::KeyNotInDomain)
+            }
+
+            pub fn merge(&self, other: $map<K, V>) -> $map<K, V>
+                where V: PartialEq
+            {
+                unwrap_vdm(self.try_merge(other))
+            }
+
+            pub fn try_merge(&self, other: $map<K, V>) -> Result<$map<K, V>, VdmError>
+                where V: PartialEq
+            {
+                if !self.is_compatible(&other) {
+                    Err(VdmError::IncompatibleMerge)
+                } else {
+                    Ok(self.ovrride(other))
+                }
+            }
+
+            pub fn ovrride(&self, other: $map<K, V>) -> $map<K, V> {
+                let mut result = self.clone();
+                result.inner.extend(other.inner);
+                result
+            }
+
+            pub fn dom_restrict_to(&self, allowed: $set<K>) -> $map<K, V> {
+                self.iter()
+                    .filter(|&(k, _)| allowed.contains(k))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect()
+            }
 
-    pub fn compose<A: Hash + Eq + Clone>(&self, m2: Map<A, K>) -> Map<A, V> {
-        if !m2.range().is_subset(self.domain()) {
-            panic!("Range is not a subset of the domain.");
+            pub fn dom_restrict_by(&self, restricted: $set<K>) -> $map<K, V> {
+                self.iter()
+                    .filter(|&(k, _)| !restricted.contains(k))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect()
+            }
+
+            pub fn rng_restrict_to(&self, allowed: $set<V>) -> $map<K, V>
+                where V: $($bound)+
+            {
+                self.iter()
+                    .filter(|&(_, v)| allowed.contains(v))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect()
+            }
+
+            pub fn rng_restrict_by(&self, restricted: $set<V>) -> $map<K, V>
+                where V: $($bound)+
+            {
+                self.iter()
+                    .filter(|&(_, v)| !restricted.contains(v))
+                    .map(|(k, v)| (k.clone(), v.clone()))
+                    .collect()
+            }
+
+            pub fn compose<A: $($bound)+ + Clone>(&self, m2: $map<A, K>) -> $map<A, V> {
+                unwrap_vdm(self.try_compose(m2))
+            }
+
+            pub fn try_compose<A: $($bound)+ + Clone>(&self, m2: $map<A, K>) -> Result<$map<A, V>, VdmError> {
+                if !m2.range().is_subset(self.domain()) {
+                    return Err(VdmError::RangeNotInDomain);
+                }
+
+                Ok(m2.iter().map(|(a, b)| (a.clone(), self[b].clone())).collect())
+            }
+
+            pub fn inverse(&self) -> $map<V, K>
+                where V: $($bound)+
+            {
+                unwrap_vdm(self.try_inverse())
+            }
+
+            pub fn try_inverse(&self) -> Result<$map<V, K>, VdmError>
+                where V: $($bound)+
+            {
+                let dom = self.domain();
+                let rng = self.range();
+
+                if dom.len() != rng.len() {
+                    Err(VdmError::NotInjective)
+                } else {
+                    Ok(self.iter().map(|(k, v)| (v.clone(), k.clone())).collect())
+                }
+            }
+
+            fn is_compatible(&self, other: &$map<K, V>) -> bool
+                where V: PartialEq
+            {
+                !self.iter().any(|(k, v)| {
+                    match other.inner.get(k) {
+                        None => false,
+                        Some(other_v) => other_v != v,
+                    }
+                })
+            }
         }
 
-        m2.iter().map(|(a, b)| (a.clone(), self[b].clone())).collect()
-    }
+        impl<A: $($bound)+ + Clone> $map<A, A> {
+            pub fn iterate(&self, n: u64) -> $map<A, A> {
+                unwrap_vdm(self.try_iterate(n))
+            }
 
-    pub fn inverse(&self) -> Map<V, K> {
-        let dom = self.domain();
-        let rng = self.range();
+            pub fn try_iterate(&self, n: u64) -> Result<$map<A, A>, VdmError> {
+                if n == 0 {
+                    Ok(self.keys().map(|k| (k.clone(), k.clone())).collect())
+                } else if n == 1 {
+                    Ok(self.clone())
+                } else if self.range().is_subset(self.domain()) {
+                    let mut result = self.try_compose(self.clone())?;
+
+                    for _ in 2..n {
+                        result = result.try_compose(self.clone())?;
+                    }
+
+                    Ok(result)
+                } else {
+                    Err(VdmError::RangeNotInDomain)
+                }
+            }
+        }
 
-        if dom.len() != rng.len() {
-            panic!("Map must be 1-to-1 to inverse.");
-        } else {
-            self.iter().map(|(k, v)| (v.clone(), k.clone())).collect()
+        impl<K, V> $set<$map<K, V>>
+            where K: $($bound)+ + Clone,
+                  V: PartialEq + Clone,
+                  $map<K, V>: $($bound)+
+        {
+            pub fn merge(&self) -> $map<K, V> {
+                unwrap_vdm(self.try_merge())
+            }
+
+            pub fn try_merge(&self) -> Result<$map<K, V>, VdmError> {
+                let mut result = $map::new();
+
+                for map in self {
+                    if !result.is_compatible(map) {
+                        return Err(VdmError::IncompatibleMerge);
+                    } else {
+                        let cloned_iter = map.iter().map(|(k, v)| (k.clone(), v.clone()));
+                        result.extend(cloned_iter);
+                    }
+                }
+                Ok(result)
+            }
         }
-    }
 
-    fn is_compatible(&self, other: &Map<K, V>) -> bool {
-        !self.iter().any(|(k, v)| {
-            match other.inner.get(k) {
-                None => false,
-                Some(other_v) => other_v != v,
+        impl<K: $($bound)+, V> Default for $map<K, V> {
+            fn default() -> $map<K, V> {
+                $map { inner: Default::default() }
             }
-        })
-    }
-}
+        }
 
-impl<A: Hash + Eq + Clone> Map<A, A> {
-    pub fn iterate(&self, n: u64) -> Map<A, A> {
-        if n == 0 {
-            self.iter().map(|(k, _)| (k.clone(), k.clone())).collect()
-        } else if n == 1 {
-            self.clone()
-        } else if self.range().is_subset(self.domain()) {
-            let mut result = self.compose(self.clone());
+        impl<K, V> FromIterator<(K, V)> for $map<K, V>
+            where K: $($bound)+ + Clone,
+                  V: Clone
+        {
+            fn from_iter<I: IntoIterator<Item = (K, V)>>(iterable: I) -> $map<K, V> {
+                $map { inner: iterable.into_iter().collect() }
+            }
+        }
 
-            for i in 2..n {
-                println!("{}", i);
-                result = result.compose(self.clone());
+        impl<K, V> Extend<(K, V)> for $map<K, V>
+            where K: $($bound)+ + Clone,
+                  V: Clone
+        {
+            fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iterable: I) {
+                self.inner.extend(iterable)
             }
+        }
+
+        /// `m(key)`; panics with `KeyNotInDomain` if `key` is not in the
+        /// domain.
+        impl<'a, K, V> Index<&'a K> for $map<K, V>
+            where K: $($bound)+ + Clone,
+                  V: Clone
+        {
+            type Output = V;
 
-            result
-        } else {
-            panic!("Range is not a subset of the domain.");
+            fn index(&self, key: &K) -> &V {
+                unwrap_vdm(self.inner.get(key).ok_or(VdmError::KeyNotInDomain))
+            }
         }
-    }
-}
 
+        impl<'a, K: $($bound)+, V> IntoIterator for &'a $map<K, V> {
+            type Item = (&'a K, &'a V);
+            type IntoIter = Iter<'a, K, V>;
 
-impl<K, V> Set<Map<K, V>>
-    where K: Hash + Eq + Clone,
-          V: Hash + Eq + Clone
-{
-    pub fn merge(&self) -> Map<K, V> {
-        let mut result = Map::new();
+            fn into_iter(self) -> Iter<'a, K, V> {
+                self.iter()
+            }
+        }
+
+        impl<K, V> IntoIterator for $map<K, V>
+            where K: $($bound)+ + Clone,
+                  V: Clone
+        {
+            type Item = (K, V);
+            type IntoIter = IntoIter<K, V>;
 
-        for map in self {
-            if !result.is_compatible(map) {
-                panic!("Merging requires maps to be compatible");
-            } else {
-                let cloned_iter = map.iter().map(|(k, v)| (k.clone(), v.clone()));
-                result.extend(cloned_iter);
+            fn into_iter(self) -> IntoIter<K, V> {
+                IntoIter(self.inner.into_iter())
             }
         }
-        result
-    }
-}
 
-impl<K, V> Hash for Map<K, V>
-    where K: Eq + Hash + Clone,
-          V: Eq + Hash + Clone
-{
-    /// https://github.com/rust-lang/rust/issues/21182
-    fn hash<H>(&self, state: &mut H)
-        where H: Hasher
-    {
-        let set_hash = self.into_iter().fold(0, |sum, (k, v)| sum ^ get_hash(k) ^ get_hash(v));
-        set_hash.hash(state);
-    }
-}
+        impl<K: $($bound)+ + fmt::Display, V: fmt::Display> fmt::Display for $map<K, V> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let item_string = self.iter()
+                                      .map(|(k, v)| format!("{} |-> {}", k, v))
+                                      .collect::<Vec<_>>()
+                                      .join(", ");
 
-impl<K, V> Default for Map<K, V> where K: Eq + Hash
-{
-    fn default() -> Map<K, V> {
-        Map { inner: Default::default() }
-    }
+                write!(f, "{{{}}}", item_string)
+            }
+        }
+    };
 }
 
-impl<K, V> FromIterator<(K, V)> for Map<K, V>
-    where K: Eq + Hash + Clone,
-          V: Eq + Hash + Clone
-{
-    fn from_iter<I: IntoIterator<Item = (K, V)>>(iterable: I) -> Map<K, V> {
-        Map { inner: iterable.into_iter().collect() }
-    }
+/// Map backed by a `HashMap`, or by the persistent `im::HashMap` with the
+/// `persistent` feature. The backing is private, so the API, including
+/// the conversions from and to `HashMap`, is the same with either.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Map<K: Hash + Eq, V> {
+    inner: Backing<K, V>,
 }
 
-impl<'a, K, V> IntoIterator for &'a Map<K, V> where K: Eq + Hash
-{
-    type Item = (&'a K, &'a V);
-    type IntoIter = Iter<'a, K, V>;
+#[cfg(not(feature = "persistent"))]
+type Backing<K, V> = HashMap<K, V>;
+#[cfg(feature = "persistent")]
+type Backing<K, V> = im::HashMap<K, V>;
 
-    fn into_iter(self) -> Iter<'a, K, V> {
-        self.iter()
-    }
+#[cfg(not(feature = "persistent"))]
+wrap_iter! {
+    /// Iterator over the maplets of a `Map`.
+    Iter<'a, K, V>(hash_map::Iter<'a, K, V>) -> (&'a K, &'a V) where K: Hash + Eq
+}
+#[cfg(feature = "persistent")]
+wrap_iter! {
+    /// Iterator over the maplets of a `Map`.
+    Iter<'a, K, V>(hashmap::Iter<'a, K, V>) -> (&'a K, &'a V) where K: Hash + Eq
 }
 
-impl<K: Hash + Eq, V> Deref for Map<K, V> {
-    type Target = HashMap<K,V>;
-
-    fn deref(&self) -> &HashMap<K, V> {
-        &self.inner
-    }
+#[cfg(not(feature = "persistent"))]
+wrap_iter! {
+    /// Consuming iterator over the maplets of a `Map`.
+    IntoIter<K, V>(hash_map::IntoIter<K, V>) -> (K, V) where K: Hash + Eq + Clone, V: Clone
+}
+#[cfg(feature = "persistent")]
+wrap_iter! {
+    /// Consuming iterator over the maplets of a `Map`.
+    IntoIter<K, V>(hashmap::ConsumingIter<(K, V)>) -> (K, V) where K: Hash + Eq + Clone, V: Clone
 }
 
-impl<K: Hash + Eq, V> DerefMut for Map<K, V> {
-    fn deref_mut(&mut self) -> &mut HashMap<K, V> {
-        &mut self.inner
+map_type!(Map, Backing, Set, Hash + Eq);
+
+impl<K: Hash + Eq + Clone, V: Clone> From<HashMap<K, V>> for Map<K, V> {
+    fn from(m: HashMap<K, V>) -> Map<K, V> {
+        Map { inner: Backing::from(m) }
     }
 }
 
-impl<K: Hash + Eq, V> Into<HashMap<K, V>> for Map<K, V> {
-    fn into(self) -> HashMap<K, V> {
-        self.inner
+impl<K: Hash + Eq + Clone, V: Clone> From<Map<K, V>> for HashMap<K, V> {
+    fn from(m: Map<K, V>) -> HashMap<K, V> {
+        m.into_iter().collect()
     }
 }
 
-impl<K: Hash + Eq + fmt::Display, V: fmt::Display> fmt::Display for Map<K, V> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let item_string = self.iter()
-                              .map(|(k, v)| format!("{} |-> {}", k, v))
-                              .collect::<Vec<_>>()
-                              .join(", ");
-
-        write!(f, "{{{}}}", item_string)
+impl<K, V> Hash for Map<K, V>
+    where K: Eq + Hash + Clone,
+          V: Eq + Hash + Clone
+{
+    /// https://github.com/rust-lang/rust/issues/21182
+    fn hash<H>(&self, state: &mut H)
+        where H: Hasher
+    {
+        let set_hash = self.into_iter().fold(0, |sum, (k, v)| sum ^ get_hash(k) ^ get_hash(v));
+        set_hash.hash(state);
     }
 }
 
@@ -232,7 +384,10 @@ impl<K: Hash + Eq + fmt::Debug, V: fmt::Debug> fmt::Debug for Map<K, V> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use lib::ToString;
     use Set;
+    use Opt;
+    use VdmError;
 
     #[test]
     fn equality() {
@@ -258,12 +413,36 @@ mod tests {
         assert_eq!("bar", e2);
     }
 
+    #[test]
+    fn try_apply_read() {
+        let m1 = map!{1 => "foo", 2 => "bar"};
+
+        assert_eq!(Ok("foo"), m1.try_get(1));
+        assert_eq!(Err(VdmError::KeyNotInDomain), m1.try_get(3));
+    }
+
+    #[test]
+    fn optional_values() {
+        let m1 = map!{1 => Opt::some("foo"), 2 => Opt::nil()};
+
+        assert_eq!(Opt::nil(), m1.get(2));
+        assert_eq!(Ok(Opt::some("foo")), m1.try_get(1));
+        assert_eq!(Err(VdmError::KeyNotInDomain), m1.try_get(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "No such key in map.")]
+    fn apply_read_missing() {
+        let m1 = map!{1 => "foo", 2 => "bar"};
+        let _ = m1.get(3);
+    }
+
     #[test]
     fn apply_write() {
         let mut m1 = map!{1 => "foo", 2 => "bar"};
         m1.insert(2, "baz");
 
-        let e2 = m1[&2].clone();
+        let e2 = m1[&2];
 
         assert_eq!("baz", e2);
     }
@@ -310,7 +489,21 @@ mod tests {
         let m2 = map!{1 => "bar"};
 
         let _ = m1.merge(m2);
-        assert!(true);
+    }
+
+    #[test]
+    fn try_merge() {
+        let m1 = map!{1 => "foo"};
+
+        assert_eq!(Ok(map!{1 => "foo", 2 => "bar"}), m1.try_merge(map!{2 => "bar"}));
+        assert_eq!(Err(VdmError::IncompatibleMerge), m1.try_merge(map!{1 => "bar"}));
+    }
+
+    #[test]
+    fn try_dmerge() {
+        let maps = set!{map!{1 => "foo"}, map!{1 => "bar"}};
+
+        assert_eq!(Err(VdmError::IncompatibleMerge), maps.try_merge());
     }
 
     #[test]
@@ -374,6 +567,14 @@ mod tests {
         m1.compose(m2);
     }
 
+    #[test]
+    fn try_composition() {
+        let m1 = map!{"foo" => "bar", "bar" => "kek"};
+        let m2 = map!{1 => "foo", 2 => "bar", 3 => "w00t"};
+
+        assert_eq!(Err(VdmError::RangeNotInDomain), m1.try_compose(m2));
+    }
+
     #[test]
     fn iteration0() {
         let m = map!{ 1 => 2, 2 => 3, 3 => 4, 4 => 1 };
@@ -405,6 +606,14 @@ mod tests {
         assert_eq!(exp_res10, i10);
     }
 
+    #[test]
+    fn try_iteration() {
+        let m = map!{ 1 => 2, 2 => 3 };
+
+        assert_eq!(Ok(m.clone()), m.try_iterate(1));
+        assert_eq!(Err(VdmError::RangeNotInDomain), m.try_iterate(2));
+    }
+
     #[test]
     fn inverse() {
         let m = map!{1 => "foo", 2 => "bar"};
@@ -422,6 +631,13 @@ mod tests {
         m.inverse();
     }
 
+    #[test]
+    fn try_inverse() {
+        let m = map!{1 => "foo", 2 => "foo"};
+
+        assert_eq!(Err(VdmError::NotInjective), m.try_inverse());
+    }
+
     #[test]
     fn display_formatting() {
         let m1 = map!{1 => "foo"};
@@ -430,4 +646,15 @@ mod tests {
         assert_eq!("{1 |-> foo}", m1_string);
     }
 
+    #[test]
+    fn hash_map_conversions() {
+        let mut m = map!{1 => "foo"};
+        assert_eq!(None, m.insert(2, "bar"));
+        assert_eq!("bar", m[&2]);
+
+        let h: HashMap<i32, &str> = m.clone().into();
+        assert_eq!(Some(&"foo"), h.get(&1));
+        assert_eq!(m, Map::from(h));
+    }
+
 }
@@ -0,0 +1,285 @@
+use lib::ops::{Add, Sub, Mul, Neg};
+use lib::convert::TryFrom;
+use lib::fmt;
+use error::unwrap_vdm;
+use VdmError;
+use F64;
+
+/// VDM `div`: integer division, truncating towards zero.
+fn vdm_div(x: i64, y: i64) -> Result<i64, VdmError> {
+    if y == 0 {
+        return Err(VdmError::DivisionByZero);
+    }
+
+    x.checked_div(y).ok_or(VdmError::ArithmeticOverflow)
+}
+
+/// VDM `rem`: `x - y * (x div y)`, the result has the sign of `x`.
+fn vdm_rem(x: i64, y: i64) -> Result<i64, VdmError> {
+    if y == 0 {
+        return Err(VdmError::DivisionByZero);
+    }
+
+    x.checked_rem(y).ok_or(VdmError::ArithmeticOverflow)
+}
+
+/// VDM `mod`: `x - y * floor(x / y)`, the result has the sign of `y`.
+fn vdm_mod(x: i64, y: i64) -> Result<i64, VdmError> {
+    let r = vdm_rem(x, y)?;
+
+    if r != 0 && (r < 0) != (y < 0) {
+        Ok(r + y)
+    } else {
+        Ok(r)
+    }
+}
+
+macro_rules! int_type {
+    ($(#[$attr:meta])* $name:ident, $vdm:expr, $in_range:expr, $div_t:ident) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+        pub struct $name(i64);
+
+        impl $name {
+            /// Panics if `val` is outside the range of the type.
+            pub fn new(val: i64) -> $name {
+                unwrap_vdm($name::try_new(val))
+            }
+
+            pub fn try_new(val: i64) -> Result<$name, VdmError> {
+                let in_range: fn(i64) -> bool = $in_range;
+
+                if in_range(val) {
+                    Ok($name(val))
+                } else {
+                    Err(VdmError::NotInType(val, $vdm))
+                }
+            }
+
+            pub fn value(&self) -> i64 {
+                self.0
+            }
+
+            /// VDM `div`, named like `BigInt::int_div`.
+            pub fn int_div(&self, other: $name) -> $div_t {
+                unwrap_vdm(self.try_int_div(other))
+            }
+
+            pub fn try_int_div(&self, other: $name) -> Result<$div_t, VdmError> {
+                $div_t::try_new(vdm_div(self.0, other.0)?)
+            }
+
+            pub fn modulo(&self, other: $name) -> $div_t {
+                unwrap_vdm(self.try_modulo(other))
+            }
+
+            pub fn try_modulo(&self, other: $name) -> Result<$div_t, VdmError> {
+                $div_t::try_new(vdm_mod(self.0, other.0)?)
+            }
+
+            pub fn rem(&self, other: $name) -> $div_t {
+                unwrap_vdm(self.try_rem(other))
+            }
+
+            pub fn try_rem(&self, other: $name) -> Result<$div_t, VdmError> {
+                $div_t::try_new(vdm_rem(self.0, other.0)?)
+            }
+
+            pub fn pow(&self, exp: u32) -> $name {
+                match self.0.checked_pow(exp) {
+                    Some(val) => $name::new(val),
+                    None => panic!("{}", VdmError::ArithmeticOverflow),
+                }
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self.0)
+            }
+        }
+
+        impl From<$name> for i64 {
+            fn from(val: $name) -> i64 {
+                val.0
+            }
+        }
+
+        impl From<$name> for F64 {
+            fn from(val: $name) -> F64 {
+                F64(val.0 as f64)
+            }
+        }
+
+        /// Truncates towards zero. NaN, the infinities and values outside
+        /// the range of the type are rejected.
+        impl TryFrom<F64> for $name {
+            type Error = VdmError;
+
+            fn try_from(val: F64) -> Result<$name, VdmError> {
+                let val = val.0;
+
+                if val.is_nan() || val.is_infinite() {
+                    Err(VdmError::NotANumber)
+                } else if val < i64::MIN as f64 || val >= i64::MAX as f64 {
+                    Err(VdmError::ArithmeticOverflow)
+                } else {
+                    $name::try_new(val as i64)
+                }
+            }
+        }
+
+        impl Add for $name {
+            type Output = $name;
+
+            #[inline]
+            fn add(self, other: $name) -> $name {
+                match self.0.checked_add(other.0) {
+                    Some(val) => $name::new(val),
+                    None => panic!("{}", VdmError::ArithmeticOverflow),
+                }
+            }
+        }
+
+        /// The difference is an `int`, whatever the operand types.
+        impl Sub for $name {
+            type Output = Int;
+
+            #[inline]
+            fn sub(self, other: $name) -> Int {
+                match self.0.checked_sub(other.0) {
+                    Some(val) => Int(val),
+                    None => panic!("{}", VdmError::ArithmeticOverflow),
+                }
+            }
+        }
+
+        impl Mul for $name {
+            type Output = $name;
+
+            #[inline]
+            fn mul(self, other: $name) -> $name {
+                match self.0.checked_mul(other.0) {
+                    Some(val) => $name::new(val),
+                    None => panic!("{}", VdmError::ArithmeticOverflow),
+                }
+            }
+        }
+    };
+}
+
+int_type! {
+    /// VDM `int`. Arithmetic panics on 64-bit overflow.
+    #[derive(Default)]
+    Int, "int", |_| true, Int
+}
+
+int_type! {
+    /// VDM `nat`. Arithmetic panics if the result is negative; subtraction
+    /// yields an `Int`.
+    #[derive(Default)]
+    Nat, "nat", |v| v >= 0, Nat
+}
+
+int_type! {
+    /// VDM `nat1`. Arithmetic panics if the result is not positive;
+    /// subtraction yields an `Int`.
+    /// `div`, `mod` and `rem` yield a `Nat`, as zero is a valid result.
+    Nat1, "nat1", |v| v >= 1, Nat
+}
+
+impl Neg for Int {
+    type Output = Int;
+
+    #[inline]
+    fn neg(self) -> Int {
+        match self.0.checked_neg() {
+            Some(val) => Int(val),
+            None => panic!("{}", VdmError::ArithmeticOverflow),
+        }
+    }
+}
+
+impl From<Nat> for Int {
+    fn from(val: Nat) -> Int {
+        Int(val.0)
+    }
+}
+
+impl From<Nat1> for Int {
+    fn from(val: Nat1) -> Int {
+        Int(val.0)
+    }
+}
+
+impl From<Nat1> for Nat {
+    fn from(val: Nat1) -> Nat {
+        Nat(val.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use VdmError;
+    use F64;
+
+    #[test]
+    fn div() {
+        assert_eq!(Int::new(-4), Int::new(-14).int_div(Int::new(3)));
+        assert_eq!(Int::new(-4), Int::new(14).int_div(Int::new(-3)));
+        assert_eq!(Int::new(4), Int::new(-14).int_div(Int::new(-3)));
+    }
+
+    #[test]
+    fn rem() {
+        assert_eq!(Int::new(2), Int::new(14).rem(Int::new(-3)));
+        assert_eq!(Int::new(-2), Int::new(-14).rem(Int::new(3)));
+        assert_eq!(Int::new(-2), Int::new(-14).rem(Int::new(-3)));
+    }
+
+    #[test]
+    fn modulo() {
+        assert_eq!(Int::new(-1), Int::new(14).modulo(Int::new(-3)));
+        assert_eq!(Int::new(1), Int::new(-14).modulo(Int::new(3)));
+        assert_eq!(Int::new(-2), Int::new(-14).modulo(Int::new(-3)));
+        assert_eq!(Int::new(2), Int::new(14).modulo(Int::new(3)));
+    }
+
+    #[test]
+    fn division_by_zero() {
+        assert_eq!(Err(VdmError::DivisionByZero), Nat::new(1).try_int_div(Nat::new(0)));
+    }
+
+    #[test]
+    fn nat_range() {
+        assert_eq!(Err(VdmError::NotInType(-1, "nat")), Nat::try_new(-1));
+        assert_eq!(Err(VdmError::NotInType(0, "nat1")), Nat1::try_new(0));
+    }
+
+    #[test]
+    fn nat_sub() {
+        assert_eq!(Int::new(-1), Nat::new(1) - Nat::new(2));
+        assert_eq!(Int::new(0), Nat1::new(3) - Nat1::new(3));
+    }
+
+    #[test]
+    fn nat1_modulo() {
+        assert_eq!(Nat::new(0), Nat1::new(4).modulo(Nat1::new(2)));
+    }
+
+    #[test]
+    fn f64_conversion() {
+        assert_eq!(F64(3.0), F64::from(Nat1::new(3)));
+        assert_eq!(Ok(Int::new(-2)), Int::try_from(F64(-2.7)));
+        assert_eq!(Err(VdmError::NotInType(-2, "nat")), Nat::try_from(F64(-2.7)));
+        assert_eq!(Err(VdmError::NotANumber), F64::try_new(f64::NAN).and_then(Int::try_from));
+        assert_eq!(Err(VdmError::ArithmeticOverflow), Int::try_from(F64(1e19)));
+    }
+}
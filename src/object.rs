@@ -0,0 +1,518 @@
+use lib::cell::{Ref, RefCell, RefMut};
+use lib::cmp::Ordering;
+use lib::fmt;
+use lib::hash::{Hash, Hasher};
+use lib::rc::Rc;
+use lib::sync::Mutex;
+use lib::sync::atomic::{self, AtomicBool};
+use lib::{String, Vec};
+#[cfg(feature = "std")]
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use vdm_display::VdmDisplay;
+
+/// The name and direct superclasses of a VDM++ class. Classes are told
+/// apart by name, as class names are unique in a model.
+#[derive(Debug)]
+pub struct ClassDesc {
+    pub name: &'static str,
+    pub supers: &'static [&'static ClassDesc],
+}
+
+impl ClassDesc {
+    /// Whether this is `class` or one of its subclasses.
+    pub fn is_subclass_of(&self, class: &ClassDesc) -> bool {
+        self.name == class.name || self.supers.iter().any(|s| s.is_subclass_of(class))
+    }
+
+    /// Whether this class has no superclass.
+    pub fn is_base(&self) -> bool {
+        self.supers.is_empty()
+    }
+
+    /// The base classes this class derives from, itself if it is one.
+    pub fn bases(&self) -> Vec<&ClassDesc> {
+        if self.is_base() {
+            return vec![self];
+        }
+
+        let mut bases: Vec<&ClassDesc> = self.supers.iter().flat_map(|s| s.bases()).collect();
+        bases.sort_by_key(|c| c.name);
+        bases.dedup_by_key(|c| c.name);
+        bases
+    }
+}
+
+/// Implemented by the types of VDM++ objects, normally with `impl_class!`.
+pub trait Class {
+    fn class(&self) -> &'static ClassDesc;
+
+    /// The instance variables shown when printing the object, with their
+    /// values in VDM form.
+    fn fields(&self) -> Vec<(&'static str, String)> {
+        Vec::new()
+    }
+}
+
+/// The shared part of an object: `value` is the cell holding the
+/// instance variables.
+struct Object<C: ?Sized> {
+    id: u64,
+    class: &'static ClassDesc,
+    printing: AtomicBool,
+    value: C,
+}
+
+static NEXT_ID: Mutex<u64> = Mutex::new(1);
+
+impl<C> Object<C> {
+    fn new(class: &'static ClassDesc, value: C) -> Object<C> {
+        let id = {
+            let mut next = NEXT_ID.lock();
+            let id = *next;
+            *next += 1;
+            id
+        };
+
+        Object {
+            id,
+            class,
+            printing: AtomicBool::new(false),
+            value,
+        }
+    }
+}
+
+impl<C: ?Sized> Object<C> {
+    /// Prints the object with the instance variables `fields` returns, or
+    /// `None` if they cannot be read now.
+    fn fmt_vdm<F>(&self, f: &mut fmt::Formatter, fields: F) -> fmt::Result
+        where F: FnOnce() -> Option<Vec<(&'static str, String)>>
+    {
+        let fields = if self.printing.swap(true, atomic::Ordering::Acquire) {
+            None
+        } else {
+            let fields = fields();
+            self.printing.store(false, atomic::Ordering::Release);
+            fields
+        };
+
+        write!(f, "{}{{#{}", self.class.name, self.id)?;
+
+        match fields {
+            Some(fields) => {
+                for (name, val) in fields {
+                    write!(f, ", {} := {}", name, val)?;
+                }
+            }
+            None => write!(f, ", ...")?,
+        }
+
+        write!(f, "}}")
+    }
+}
+
+/// Declares a reference to VDM++ objects held by the pointer `$ptr` in
+/// the cell `$cell`, with identity semantics and the class tests.
+macro_rules! obj_ref {
+    ($(#[$attr:meta])* $name:ident, $ptr:ident, $cell:ident) => {
+        $(#[$attr])*
+        pub struct $name<T: ?Sized> {
+            obj: $ptr<Object<$cell<T>>>,
+        }
+
+        impl<T: Class> $name<T> {
+            pub fn new(val: T) -> $name<T> {
+                $name { obj: $ptr::new(Object::new(val.class(), $cell::new(val))) }
+            }
+        }
+
+        impl<T: ?Sized> $name<T> {
+            /// The number of the object, which Overture prints as `#id`.
+            pub fn id(&self) -> u64 {
+                self.obj.id
+            }
+
+            pub fn class(&self) -> &'static ClassDesc {
+                self.obj.class
+            }
+
+            /// `isofclass(class, self)`.
+            pub fn isofclass(&self, class: &ClassDesc) -> bool {
+                self.class().is_subclass_of(class)
+            }
+
+            /// `isofbaseclass(class, self)`: `class` is a base class, and
+            /// this object is of that class.
+            pub fn isofbaseclass(&self, class: &ClassDesc) -> bool {
+                class.is_base() && self.isofclass(class)
+            }
+
+            /// `sameclass(self, other)`.
+            pub fn sameclass<U: ?Sized>(&self, other: &$name<U>) -> bool {
+                self.class().name == other.class().name
+            }
+
+            /// `samebaseclass(self, other)`: both objects derive from a
+            /// common base class.
+            pub fn samebaseclass<U: ?Sized>(&self, other: &$name<U>) -> bool {
+                let theirs = other.class().bases();
+                self.class().bases().iter().any(|b| theirs.iter().any(|t| t.name == b.name))
+            }
+        }
+
+        impl<T: ?Sized> Clone for $name<T> {
+            fn clone(&self) -> $name<T> {
+                $name { obj: self.obj.clone() }
+            }
+        }
+
+        impl<T: ?Sized> PartialEq for $name<T> {
+            fn eq(&self, other: &$name<T>) -> bool {
+                self.obj.id == other.obj.id
+            }
+        }
+
+        impl<T: ?Sized> Eq for $name<T> {}
+
+        impl<T: ?Sized> Hash for $name<T> {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                self.obj.id.hash(state);
+            }
+        }
+
+        impl<T: ?Sized> PartialOrd for $name<T> {
+            fn partial_cmp(&self, other: &$name<T>) -> Option<Ordering> {
+                Some(self.cmp(other))
+            }
+        }
+
+        impl<T: ?Sized> Ord for $name<T> {
+            fn cmp(&self, other: &$name<T>) -> Ordering {
+                self.obj.id.cmp(&other.obj.id)
+            }
+        }
+
+        /// A reference is of the class of its object, so that `classname`
+        /// takes objects and references alike.
+        impl<T: Class + ?Sized> Class for $name<T> {
+            fn class(&self) -> &'static ClassDesc {
+                self.obj.class
+            }
+
+            fn fields(&self) -> Vec<(&'static str, String)> {
+                self.try_fields().unwrap_or_default()
+            }
+        }
+
+        /// Prints `Name{#id, field := value, ...}`. An object reached again
+        /// while it is printed, or that is being changed, prints as
+        /// `Name{#id, ...}`.
+        impl<T: Class + ?Sized> VdmDisplay for $name<T> {
+            fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.obj.fmt_vdm(f, || self.try_fields())
+            }
+
+            fn vdm_cmp(&self, other: &$name<T>) -> Ordering {
+                self.cmp(other)
+            }
+        }
+
+        impl<T: Class + ?Sized> fmt::Display for $name<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.fmt_vdm(f)
+            }
+        }
+
+        impl<T: ?Sized> fmt::Debug for $name<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}{{#{}}}", self.class().name, self.id())
+            }
+        }
+    };
+}
+
+obj_ref! {
+    /// A reference to a VDM++ object. Clones refer to the same object,
+    /// which they share and can all change.
+    ///
+    /// Equality and hashing are by identity, as in VDM, so that objects
+    /// can be kept in `Set`s and `Map`s; two objects with the same
+    /// instance variables are still different. Objects are numbered in
+    /// order of creation, and ordered by that number.
+    ///
+    /// An `ObjRef<dyn Class>`, converted with `into_dyn` or `From`, can
+    /// refer to an object of any class, e.g. in a set of objects of a
+    /// class hierarchy. `ObjRef` cannot be sent to another thread; use
+    /// `SyncObjRef` with the `concurrency` module.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate codegen_runtime;
+    /// use codegen_runtime::ObjRef;
+    ///
+    /// struct Account {
+    ///     balance: u64,
+    /// }
+    ///
+    /// impl_class! { Account { balance } }
+    ///
+    /// fn main() {
+    ///     let a = ObjRef::new(Account { balance: 10 });
+    ///     let b = a.clone();
+    ///     b.borrow_mut().balance += 5;
+    ///
+    ///     assert_eq!(a, b);
+    ///     assert_ne!(a, ObjRef::new(Account { balance: 15 }));
+    ///     assert_eq!(format!("Account{{#{}, balance := 15}}", a.id()), a.to_string());
+    /// }
+    /// ```
+    ObjRef, Rc, RefCell
+}
+
+impl<T: ?Sized> ObjRef<T> {
+    /// Panics if the object is being changed.
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.obj.value.borrow()
+    }
+
+    /// Panics if the object is in use.
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.obj.value.borrow_mut()
+    }
+
+    fn try_fields(&self) -> Option<Vec<(&'static str, String)>>
+        where T: Class
+    {
+        self.obj.value.try_borrow().ok().map(|value| value.fields())
+    }
+}
+
+impl<T: Class + 'static> ObjRef<T> {
+    pub fn into_dyn(self) -> ObjRef<dyn Class> {
+        ObjRef { obj: self.obj }
+    }
+}
+
+impl<T: Class + 'static> From<ObjRef<T>> for ObjRef<dyn Class> {
+    fn from(obj: ObjRef<T>) -> ObjRef<dyn Class> {
+        obj.into_dyn()
+    }
+}
+
+#[cfg(feature = "std")]
+obj_ref! {
+    /// An `ObjRef` that can be shared between threads, for models with
+    /// `thread`s run by the `concurrency` module. The object is behind a
+    /// read-write lock, so `borrow` and `borrow_mut` wait for a thread
+    /// changing it instead of panicking; guard whole operations with the
+    /// object's `Monitor`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #[macro_use] extern crate codegen_runtime;
+    /// use codegen_runtime::SyncObjRef;
+    /// use codegen_runtime::concurrency;
+    ///
+    /// struct Counter {
+    ///     n: u64,
+    /// }
+    ///
+    /// impl_class! { Counter { n } }
+    ///
+    /// fn main() {
+    ///     let c = SyncObjRef::new(Counter { n: 0 });
+    ///     let shared = c.clone();
+    ///
+    ///     concurrency::start(move || shared.borrow_mut().n += 1).join();
+    ///
+    ///     assert_eq!(1, c.borrow().n);
+    /// }
+    /// ```
+    SyncObjRef, Arc, RwLock
+}
+
+#[cfg(feature = "std")]
+impl<T: ?Sized> SyncObjRef<T> {
+    /// Waits while another thread changes the object.
+    pub fn borrow(&self) -> RwLockReadGuard<'_, T> {
+        self.obj.value.read().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Waits while the object is in use by another thread.
+    pub fn borrow_mut(&self) -> RwLockWriteGuard<'_, T> {
+        self.obj.value.write().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn try_fields(&self) -> Option<Vec<(&'static str, String)>>
+        where T: Class
+    {
+        match self.obj.value.try_read() {
+            Ok(value) => Some(value.fields()),
+            Err(::std::sync::TryLockError::Poisoned(poisoned)) => Some(poisoned.into_inner().fields()),
+            Err(::std::sync::TryLockError::WouldBlock) => None,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Class + Send + Sync + 'static> SyncObjRef<T> {
+    pub fn into_dyn(self) -> SyncObjRef<dyn Class + Send + Sync> {
+        SyncObjRef { obj: self.obj }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Class + Send + Sync + 'static> From<SyncObjRef<T>> for SyncObjRef<dyn Class + Send + Sync> {
+    fn from(obj: SyncObjRef<T>) -> SyncObjRef<dyn Class + Send + Sync> {
+        obj.into_dyn()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::{ToOwned, ToString};
+    use Set;
+    use VDMUtil;
+
+    struct Shape;
+    struct Named;
+    struct Circle {
+        r: u64,
+        next: Option<ObjRef<Circle>>,
+    }
+    struct Square;
+
+    impl_class! { Shape {} }
+    impl_class! { Named {} }
+    impl_class! { Square: Shape {} }
+
+    impl Circle {
+        const CLASS: &'static ClassDesc = &ClassDesc { name: "Circle", supers: &[Shape::CLASS, Named::CLASS] };
+    }
+
+    impl Class for Circle {
+        fn class(&self) -> &'static ClassDesc {
+            Circle::CLASS
+        }
+
+        fn fields(&self) -> Vec<(&'static str, String)> {
+            let next = match self.next {
+                Some(ref obj) => obj.to_vdm_string(),
+                None => "nil".to_owned(),
+            };
+
+            vec![("r", self.r.to_vdm_string()), ("next", next)]
+        }
+    }
+
+    fn circle(r: u64) -> ObjRef<Circle> {
+        ObjRef::new(Circle { r, next: None })
+    }
+
+    #[test]
+    fn identity() {
+        let (a, b) = (circle(1), circle(1));
+        let objs = set!{a.clone(), b.clone(), a.clone()};
+
+        assert_eq!(2, objs.card());
+        assert!(a < b);
+        assert!(objs.contains(&b));
+    }
+
+    #[test]
+    fn class_tests() {
+        let (c, s) = (circle(1), ObjRef::new(Square));
+
+        assert!(c.isofclass(Shape::CLASS));
+        assert!(c.isofclass(Circle::CLASS));
+        assert!(!c.isofclass(Square::CLASS));
+        assert!(c.isofbaseclass(Named::CLASS));
+        assert!(!c.isofbaseclass(Circle::CLASS));
+        assert!(c.sameclass(&circle(2)));
+        assert!(!c.sameclass(&s));
+        assert!(c.samebaseclass(&s));
+        assert!(!s.samebaseclass(&ObjRef::new(Named)));
+        assert_eq!("Square", VDMUtil::classname(&s).get().iter().collect::<String>());
+    }
+
+    #[test]
+    fn display() {
+        let a = circle(1);
+        let b = circle(2);
+        b.borrow_mut().next = Some(a.clone());
+
+        assert_eq!(format!("Circle{{#{}, r := 2, next := Circle{{#{}, r := 1, next := nil}}}}", b.id(), a.id()),
+                   b.to_string());
+
+        a.borrow_mut().next = Some(b.clone());
+        assert_eq!(format!("Circle{{#{}, r := 1, next := Circle{{#{}, r := 2, next := Circle{{#{}, ...}}}}}}",
+                           a.id(), b.id(), a.id()),
+                   a.to_string());
+
+        let s = ObjRef::new(Square);
+        assert_eq!(format!("Square{{#{}}}", s.id()), format!("{:?}", s));
+    }
+
+    #[test]
+    fn class_hierarchy() {
+        let c = circle(3);
+        let shapes: Set<ObjRef<dyn Class>> = set!{c.clone().into_dyn(), ObjRef::new(Square).into(), ObjRef::new(Named).into()};
+        let circles: Vec<_> = shapes.iter().filter(|s| s.isofclass(Circle::CLASS)).collect();
+
+        assert_eq!(2, shapes.iter().filter(|s| s.isofbaseclass(Shape::CLASS)).count());
+        assert_eq!(vec![&c.clone().into_dyn()], circles);
+        assert_eq!(c.to_string(), circles[0].to_string());
+        assert_eq!("Named", circles[0].borrow().class().supers[1].name);
+    }
+
+    #[cfg(feature = "std")]
+    struct Node {
+        next: Option<SyncObjRef<Node>>,
+    }
+
+    #[cfg(feature = "std")]
+    impl Class for Node {
+        fn class(&self) -> &'static ClassDesc {
+            &ClassDesc { name: "Node", supers: &[] }
+        }
+
+        fn fields(&self) -> Vec<(&'static str, String)> {
+            let next = match self.next {
+                Some(ref obj) => obj.to_vdm_string(),
+                None => "nil".to_owned(),
+            };
+
+            vec![("next", next)]
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn shared_between_threads() {
+        use concurrency;
+
+        let node = SyncObjRef::new(Node { next: None });
+        let handles: Vec<_> = (0..4).map(|_| {
+            let node = node.clone();
+            concurrency::start(move || {
+                let next = SyncObjRef::new(Node { next: None });
+                node.borrow_mut().next = Some(next);
+            })
+        }).collect();
+
+        for handle in handles {
+            handle.join();
+        }
+
+        let next = node.borrow().next.clone().unwrap();
+        next.borrow_mut().next = Some(node.clone());
+        assert_eq!(format!("Node{{#{}, next := Node{{#{}, next := Node{{#{}, ...}}}}}}", node.id(), next.id(), node.id()),
+                   node.to_string());
+
+        let objs: Set<SyncObjRef<dyn Class + Send + Sync>> = set!{node.clone().into(), next.into()};
+        assert_eq!(2, objs.card());
+    }
+}
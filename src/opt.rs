@@ -0,0 +1,132 @@
+use lib::fmt;
+use lib::ops::{Deref, DerefMut};
+use contracts::Invariant;
+use error::unwrap_vdm;
+use VdmError;
+
+/// VDM optional type `[T]`. `Opt(None)` is the `nil` value.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Opt<T>(pub Option<T>);
+
+impl<T> Opt<T> {
+    pub fn nil() -> Opt<T> {
+        Opt(None)
+    }
+
+    pub fn some(val: T) -> Opt<T> {
+        Opt(Some(val))
+    }
+
+    pub fn is_nil(&self) -> bool {
+        self.0.is_none()
+    }
+
+    /// Returns the value, panicking if it is `nil`.
+    pub fn get(&self) -> T
+        where T: Clone
+    {
+        unwrap_vdm(self.try_get())
+    }
+
+    pub fn try_get(&self) -> Result<T, VdmError>
+        where T: Clone
+    {
+        self.0.clone().ok_or(VdmError::NilValue)
+    }
+}
+
+impl<T> Default for Opt<T> {
+    fn default() -> Opt<T> {
+        Opt(None)
+    }
+}
+
+impl<T> Deref for Opt<T> {
+    type Target = Option<T>;
+
+    fn deref(&self) -> &Option<T> {
+        &self.0
+    }
+}
+
+impl<T> DerefMut for Opt<T> {
+    fn deref_mut(&mut self) -> &mut Option<T> {
+        &mut self.0
+    }
+}
+
+impl<T> From<Option<T>> for Opt<T> {
+    fn from(val: Option<T>) -> Opt<T> {
+        Opt(val)
+    }
+}
+
+impl<T> From<Opt<T>> for Option<T> {
+    fn from(val: Opt<T>) -> Option<T> {
+        val.0
+    }
+}
+
+/// `nil` satisfies any invariant.
+impl<T: Invariant> Invariant for Opt<T> {
+    fn inv(&self) -> bool {
+        match self.0 {
+            Some(ref val) => val.inv(),
+            None => true,
+        }
+    }
+}
+
+impl<T: fmt::Display> fmt::Display for Opt<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(ref val) => write!(f, "{}", val),
+            None => write!(f, "nil"),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for Opt<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(ref val) => write!(f, "{:?}", val),
+            None => write!(f, "nil"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::ToString;
+    use VdmError;
+    use Seq;
+    use Set;
+
+    #[test]
+    fn equality() {
+        assert_eq!(Opt::some(1), Opt::from(Some(1)));
+        assert!(Opt::some(1) != Opt::nil());
+    }
+
+    #[test]
+    fn set_element() {
+        let s = set!{Opt::some(1), Opt::nil(), Opt::nil()};
+
+        assert_eq!(2, s.card());
+        assert!(s.in_set(Opt::nil()));
+    }
+
+    #[test]
+    fn try_get() {
+        assert_eq!(Ok(1), Opt::some(1).try_get());
+        assert_eq!(Err(VdmError::NilValue), Opt::<i32>::nil().try_get());
+    }
+
+    #[test]
+    fn display_formatting() {
+        assert_eq!("nil", Opt::<i32>::nil().to_string());
+        assert_eq!("3", Opt::some(3).to_string());
+        assert_eq!("[nil, 2]", seq![Opt::nil(), Opt::some(2)].to_string());
+    }
+}
@@ -0,0 +1,190 @@
+use lib::collections::{btree_map, BTreeMap};
+use lib::hash::Hash;
+use lib::fmt;
+use lib::ops::Index;
+use lib::iter::{Iterator, DoubleEndedIterator, Extend, FromIterator, IntoIterator};
+use lib::Vec;
+use Map;
+use OrdSet;
+use VdmError;
+use error::unwrap_vdm;
+
+/// Map with the same VDM operators as `Map`, backed by a `BTreeMap`.
+/// Iteration and printing follow the key order, so they are deterministic
+/// across runs.
+///
+/// Ordered lexicographically by its maplets, so maps can be elements of
+/// an `OrdSet`.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OrdMap<K: Ord, V> {
+    inner: BTreeMap<K, V>,
+}
+
+wrap_iter! {
+    /// Iterator over the maplets of an `OrdMap`, in key order.
+    Iter<'a, K, V>(btree_map::Iter<'a, K, V>) -> (&'a K, &'a V) where K: Ord
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        self.0.next_back()
+    }
+}
+
+wrap_iter! {
+    /// Consuming iterator over the maplets of an `OrdMap`, in key order.
+    IntoIter<K, V>(btree_map::IntoIter<K, V>) -> (K, V) where K: Ord
+}
+
+map_type!(OrdMap, BTreeMap, OrdSet, Ord);
+
+impl<K: Ord + Clone, V: Clone> From<BTreeMap<K, V>> for OrdMap<K, V> {
+    fn from(m: BTreeMap<K, V>) -> OrdMap<K, V> {
+        OrdMap { inner: m }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> From<OrdMap<K, V>> for BTreeMap<K, V> {
+    fn from(m: OrdMap<K, V>) -> BTreeMap<K, V> {
+        m.inner
+    }
+}
+
+impl<K, V> From<Map<K, V>> for OrdMap<K, V>
+    where K: Ord + Hash + Clone,
+          V: Clone
+{
+    fn from(m: Map<K, V>) -> OrdMap<K, V> {
+        m.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+}
+
+impl<K, V> From<OrdMap<K, V>> for Map<K, V>
+    where K: Ord + Hash + Clone,
+          V: Clone
+{
+    fn from(m: OrdMap<K, V>) -> Map<K, V> {
+        m.into_iter().collect()
+    }
+}
+
+impl<K: Ord + fmt::Debug, V: fmt::Debug> fmt::Debug for OrdMap<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let item_string = self.iter()
+                              .map(|(k, v)| format!("{:?} |-> {:?}", k, v))
+                              .collect::<Vec<_>>()
+                              .join(", ");
+
+        write!(f, "{{{}}}", item_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::ToString;
+    use Map;
+    use VdmError;
+
+    #[test]
+    fn equality() {
+        let m1 = ord_map!{1 => "foo", 2 => "bar"};
+        let m2 = ord_map!{2 => "bar", 1 => "foo"};
+
+        assert_eq!(m1, m2);
+    }
+
+    #[test]
+    fn try_apply_read() {
+        let m1 = ord_map!{1 => "foo", 2 => "bar"};
+
+        assert_eq!(Ok("foo"), m1.try_get(1));
+        assert_eq!(Err(VdmError::KeyNotInDomain), m1.try_get(3));
+    }
+
+    #[test]
+    fn domain_and_range() {
+        let m1 = ord_map!{1 => "foo", 2 => "bar"};
+
+        assert_eq!(ord_set!{1, 2}, m1.domain());
+        assert_eq!(ord_set!{"bar", "foo"}, m1.range());
+    }
+
+    #[test]
+    fn try_merge() {
+        let m1 = ord_map!{1 => "foo"};
+
+        assert_eq!(Ok(ord_map!{1 => "foo", 2 => "bar"}), m1.try_merge(ord_map!{2 => "bar"}));
+        assert_eq!(Err(VdmError::IncompatibleMerge), m1.try_merge(ord_map!{1 => "bar"}));
+    }
+
+    #[test]
+    fn dmerge() {
+        let maps = ord_set!{ord_map!{1 => "foo"}, ord_map!{2 => "bar"}};
+
+        assert_eq!(ord_map!{1 => "foo", 2 => "bar"}, maps.merge());
+    }
+
+    #[test]
+    fn ovrride() {
+        let m1 = ord_map!{1 => "foo", 2 => "bar"};
+
+        assert_eq!(ord_map!{1 => "foo", 2 => "baz"}, m1.ovrride(ord_map!{2 => "baz"}));
+    }
+
+    #[test]
+    fn restrictions() {
+        let m = ord_map!{1 => "foo", 2 => "bar"};
+
+        assert_eq!(ord_map!{2 => "bar"}, m.dom_restrict_to(ord_set!{2}));
+        assert_eq!(ord_map!{1 => "foo"}, m.dom_restrict_by(ord_set!{2}));
+        assert_eq!(ord_map!{1 => "foo"}, m.rng_restrict_to(ord_set!{"foo"}));
+        assert_eq!(ord_map!{2 => "bar"}, m.rng_restrict_by(ord_set!{"foo"}));
+    }
+
+    #[test]
+    fn try_composition() {
+        let m1 = ord_map!{"foo" => "bar", "bar" => "kek"};
+
+        assert_eq!(Ok(ord_map!{1 => "bar", 2 => "kek"}), m1.try_compose(ord_map!{1 => "foo", 2 => "bar"}));
+        assert_eq!(Err(VdmError::RangeNotInDomain), m1.try_compose(ord_map!{3 => "w00t"}));
+    }
+
+    #[test]
+    fn iteration10() {
+        let m = ord_map!{ 1 => 2, 2 => 3, 3 => 4, 4 => 1 };
+
+        assert_eq!(ord_map!{ 1 => 3, 2 => 4, 3 => 1, 4 => 2 }, m.iterate(10));
+    }
+
+    #[test]
+    fn try_inverse() {
+        assert_eq!(Ok(ord_map!{"foo" => 1, "bar" => 2}), ord_map!{1 => "foo", 2 => "bar"}.try_inverse());
+        assert_eq!(Err(VdmError::NotInjective), ord_map!{1 => "foo", 2 => "foo"}.try_inverse());
+    }
+
+    #[test]
+    fn unordered_values() {
+        let m1 = ord_map!{2 => 0.5, 1 => 1.5};
+
+        assert_eq!(ord_set!{1, 2}, m1.domain());
+        assert_eq!(Ok(0.5), m1.try_get(2));
+        assert_eq!(ord_map!{1 => 1.5, 2 => 2.5}, m1.merge(ord_map!{2 => 0.5}).ovrride(ord_map!{2 => 2.5}));
+    }
+
+    #[test]
+    fn map_conversion() {
+        let m: OrdMap<i32, &str> = map!{2 => "bar", 1 => "foo"}.into();
+
+        assert_eq!(ord_map!{1 => "foo", 2 => "bar"}, m);
+        assert_eq!(map!{1 => "foo", 2 => "bar"}, Map::from(m));
+    }
+
+    #[test]
+    fn display_formatting() {
+        let m1 = ord_map!{3 => "baz", 1 => "foo", 2 => "bar"};
+
+        assert_eq!("{1 |-> foo, 2 |-> bar, 3 |-> baz}", m1.to_string());
+        assert_eq!("{1 |-> \"foo\"}", format!("{:?}", ord_map!{1 => "foo"}));
+    }
+}
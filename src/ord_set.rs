@@ -0,0 +1,191 @@
+use lib::collections::{btree_set, BTreeSet};
+use lib::hash::Hash;
+use lib::fmt;
+use lib::ops::{BitOr, BitAnd, Sub};
+use lib::iter::{Iterator, DoubleEndedIterator, Extend, FromIterator, IntoIterator};
+use lib::Vec;
+use num_traits::Float;
+use OrdMap;
+use Seq;
+use Set;
+use VdmError;
+use error::unwrap_vdm;
+
+/// Set with the same VDM operators as `Set`, backed by a `BTreeSet`.
+/// Iteration, printing and `be_such_that` follow the element order, so
+/// they are deterministic across runs.
+///
+/// Ordered lexicographically, so sets of sets (e.g. `powersets`) are
+/// ordered too. Use `is_psubset` for set inclusion.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct OrdSet<T: Ord> {
+    inner: BTreeSet<T>,
+}
+
+wrap_iter! {
+    /// Iterator over the elements of an `OrdSet`, in order.
+    Iter<'a, T>(btree_set::Iter<'a, T>) -> &'a T where T: Ord
+}
+
+wrap_iter! {
+    /// Consuming iterator over the elements of an `OrdSet`, in order.
+    IntoIter<T>(btree_set::IntoIter<T>) -> T where T: Ord
+}
+
+impl<'a, T: Ord> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.0.next_back()
+    }
+}
+
+set_type!(OrdSet, BTreeSet, OrdMap, Ord);
+
+impl<T: Ord + Clone> From<BTreeSet<T>> for OrdSet<T> {
+    fn from(s: BTreeSet<T>) -> OrdSet<T> {
+        OrdSet { inner: s }
+    }
+}
+
+impl<T: Ord + Clone> From<OrdSet<T>> for BTreeSet<T> {
+    fn from(s: OrdSet<T>) -> BTreeSet<T> {
+        s.inner
+    }
+}
+
+impl<T: Ord + Clone> OrdSet<T> {
+    pub fn seq_compr<P, E, O>(&self, pred: P, expr: E) -> Seq<O>
+        where P: FnMut(T) -> bool,
+              E: FnMut(T) -> O,
+              O: Hash + Clone
+    {
+        self.compr(pred, expr)
+    }
+}
+
+impl<T: Ord + Hash + Clone> From<Set<T>> for OrdSet<T> {
+    fn from(s: Set<T>) -> OrdSet<T> {
+        s.into_iter().collect()
+    }
+}
+
+impl<T: Ord + Hash + Clone> From<OrdSet<T>> for Set<T> {
+    fn from(s: OrdSet<T>) -> Set<T> {
+        s.into_iter().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::ToString;
+    use Seq;
+    use Set;
+    use VdmError;
+
+    #[test]
+    fn equality() {
+        let s1 = ord_set!{1,2,3,3};
+        let s2 = ord_set!{2,3,1};
+
+        assert_eq!(s1, s2);
+    }
+
+    #[test]
+    fn union() {
+        let s1 = ord_set!{1,2,3};
+        let s2 = ord_set!{4,5};
+
+        assert_eq!(ord_set!{1,2,3,4,5}, s1.union(s2));
+    }
+
+    #[test]
+    fn inter() {
+        let s1 = ord_set!{1,2,3,4};
+        let s2 = ord_set!{4,5};
+
+        assert_eq!(ord_set!{4}, s1.inter(s2));
+    }
+
+    #[test]
+    fn difference() {
+        let s1 = ord_set!{1,2,3,4};
+        let s2 = ord_set!{4,5};
+
+        assert_eq!(ord_set!{1,2,3}, s1.difference(s2));
+    }
+
+    #[test]
+    fn is_psubset() {
+        let s1 = ord_set!{1,2,3,4,5};
+        let s2 = ord_set!{4,5};
+
+        assert!(s2.is_psubset(s1.clone()));
+        assert!(!s2.is_psubset(s2.clone()));
+        assert!(!s1.is_psubset(s2));
+    }
+
+    #[test]
+    fn range() {
+        assert_eq!(ord_set!{2,3,4,5}, OrdSet::range(1.2f64, 5.7f64));
+    }
+
+    #[test]
+    fn dunion() {
+        let ss = ord_set!{ord_set!{1,2}, ord_set!{2,3,4}, ord_set!{2,5,6}};
+
+        assert_eq!(ord_set!{1,2,3,4,5,6}, ss.dunion());
+    }
+
+    #[test]
+    fn dinter() {
+        let ss = ord_set!{ord_set!{1,2}, ord_set!{2,3,4}, ord_set!{2,5,6}};
+
+        assert_eq!(ord_set!{2}, ss.dinter());
+    }
+
+    #[test]
+    fn powersets() {
+        let ps = ord_set!{1,2}.powersets();
+
+        assert_eq!("{{}, {1}, {1, 2}, {2}}", ps.to_string());
+    }
+
+    #[test]
+    fn try_iota() {
+        let s1 = ord_set!{1,2,3};
+
+        assert_eq!(Ok(2), s1.try_iota(|i| i == 2));
+        assert_eq!(Err(VdmError::IotaMultipleMatches), s1.try_iota(|i| i > 1));
+    }
+
+    #[test]
+    fn let_be_such_that() {
+        let result = ord_set!{4,3,2,1}.be_such_that(|i| i % 2 == 0);
+
+        assert_eq!(2, result);
+    }
+
+    #[test]
+    fn comprehensions() {
+        let s = ord_set!{2,3,1,4};
+
+        assert_eq!(ord_set!{4,8}, s.set_compr(|i| i % 2 == 0, |i| i * 2));
+        assert_eq!(seq![4, 8], s.seq_compr(|i| i % 2 == 0, |i| i * 2));
+        assert_eq!(ord_map!{2 => 4, 4 => 8}, s.map_compr(|i| i % 2 == 0, |i| (i, i * 2)));
+    }
+
+    #[test]
+    fn set_conversion() {
+        let s: OrdSet<i32> = set!{3,1,2}.into();
+
+        assert_eq!(ord_set!{1,2,3}, s);
+        assert_eq!(set!{1,2,3}, Set::from(s));
+    }
+
+    #[test]
+    fn display_formatting() {
+        let s1_string = ord_set!{3,10,1}.to_string();
+
+        assert_eq!("{1, 3, 10}", s1_string);
+    }
+}
@@ -0,0 +1,593 @@
+use lib::any::Any;
+use lib::hash::Hash;
+use lib::str::FromStr;
+use lib::{Box, String, Vec};
+use BigInt;
+use F64;
+use Int;
+use Nat;
+use Nat1;
+use Opt;
+use Token;
+use Tuple;
+use Fixed;
+use Decimal;
+use Seq;
+use Set;
+use Map;
+use OrdSet;
+use OrdMap;
+use VdmError;
+
+/// Types that can be read from VDM value syntax, e.g. `{1, 2}`,
+/// `[3, 4]`, `{1 |-> "a"}`, `mk_(1, <A>)` or `"text"`.
+///
+/// `impl_quote!`, `def_record!`, `impl_record!` and `impl_union!` implement
+/// it for the generated types whenever all their fields or variants do.
+pub trait FromVdmStr: Sized {
+    /// Parses a value at the current position of `p`.
+    fn parse_vdm(p: &mut Parser) -> Result<Self, VdmError>;
+
+    /// Parses `s`, which must hold exactly one value.
+    fn from_vdm_str(s: &str) -> Result<Self, VdmError> {
+        let mut p = Parser::new(s);
+        let val = Self::parse_vdm(&mut p)?;
+        p.end()?;
+        Ok(val)
+    }
+}
+
+/// Cursor over VDM value text. Whitespace between tokens is skipped.
+pub struct Parser<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    pub fn new(input: &'a str) -> Parser<'a> {
+        Parser { input, pos: 0 }
+    }
+
+    /// Returns the next non-whitespace character without consuming it.
+    pub fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.rest().chars().next()
+    }
+
+    /// Consumes `token` if the input continues with it.
+    pub fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+
+        if self.rest().starts_with(token) {
+            self.pos += token.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn expect(&mut self, token: &str) -> Result<(), VdmError> {
+        if self.eat(token) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", token)))
+        }
+    }
+
+    /// Succeeds if only whitespace is left.
+    pub fn end(&mut self) -> Result<(), VdmError> {
+        match self.peek() {
+            None => Ok(()),
+            Some(_) => Err(self.error("unexpected trailing input")),
+        }
+    }
+
+    /// Consumes an identifier: a letter followed by letters, digits, `_` or `'`.
+    pub fn ident(&mut self) -> Result<&'a str, VdmError> {
+        self.skip_whitespace();
+        let rest = self.rest();
+
+        match rest.chars().next() {
+            Some(c) if c.is_alphabetic() => {}
+            _ => return Err(self.error("expected identifier")),
+        }
+
+        let len = rest.find(|c: char| !(c.is_alphanumeric() || c == '_' || c == '\''))
+                      .unwrap_or(rest.len());
+        self.pos += len;
+        Ok(&rest[..len])
+    }
+
+    /// Consumes a numeric literal such as `-12`, `3.5` or `1.0E-3`.
+    pub fn number(&mut self) -> Result<&'a str, VdmError> {
+        self.skip_whitespace();
+        let bytes = self.rest().as_bytes();
+        let mut len = 0;
+
+        if bytes.first() == Some(&b'-') {
+            len += 1;
+        }
+
+        let digits = |from: usize| bytes[from..].iter().take_while(|b| b.is_ascii_digit()).count();
+
+        let int_digits = digits(len);
+        if int_digits == 0 {
+            return Err(self.error("expected number"));
+        }
+        len += int_digits;
+
+        if bytes.get(len) == Some(&b'.') && digits(len + 1) > 0 {
+            len += 1 + digits(len + 1);
+        }
+
+        if let Some(&b'e') | Some(&b'E') = bytes.get(len) {
+            let sign = match bytes.get(len + 1) {
+                Some(&b'+') | Some(&b'-') => 1,
+                _ => 0,
+            };
+
+            if digits(len + 1 + sign) > 0 {
+                len += 1 + sign + digits(len + 1 + sign);
+            }
+        }
+
+        let lexeme = &self.rest()[..len];
+        self.pos += len;
+        Ok(lexeme)
+    }
+
+    /// Consumes a numeric literal and converts it with `FromStr`.
+    pub fn parse_number<T: FromStr>(&mut self) -> Result<T, VdmError> {
+        let start = self.pos;
+        let lexeme = self.number()?;
+
+        lexeme.parse().map_err(|_| {
+            self.pos = start;
+            self.error(&format!("invalid number `{}`", lexeme))
+        })
+    }
+
+    /// Consumes a string literal in double quotes, resolving escapes.
+    pub fn string_literal(&mut self) -> Result<String, VdmError> {
+        self.expect("\"")?;
+        let mut result = String::new();
+
+        loop {
+            match self.next_char() {
+                Some('"') => return Ok(result),
+                Some('\\') => result.push(self.escape()?),
+                Some(c) => result.push(c),
+                None => return Err(self.error("unterminated string literal")),
+            }
+        }
+    }
+
+    /// Consumes a character literal in single quotes, resolving escapes.
+    pub fn char_literal(&mut self) -> Result<char, VdmError> {
+        self.expect("'")?;
+
+        let c = match self.next_char() {
+            Some('\\') => self.escape()?,
+            Some(c) => c,
+            None => return Err(self.error("unterminated character literal")),
+        };
+
+        if self.next_char() == Some('\'') {
+            Ok(c)
+        } else {
+            Err(self.error("unterminated character literal"))
+        }
+    }
+
+    /// Parses values separated by commas up to and including `close`.
+    pub fn list<T, F>(&mut self, close: &str, mut item: F) -> Result<Vec<T>, VdmError>
+        where F: FnMut(&mut Parser<'a>) -> Result<T, VdmError>
+    {
+        let mut items = Vec::new();
+
+        if self.eat(close) {
+            return Ok(items);
+        }
+
+        loop {
+            items.push(item(self)?);
+
+            if self.eat(close) {
+                return Ok(items);
+            }
+            self.expect(",")?;
+        }
+    }
+
+    /// Runs `parse`, and rewinds to the current position if it fails.
+    pub fn try_parse<T, F>(&mut self, parse: F) -> Result<T, VdmError>
+        where F: FnOnce(&mut Parser<'a>) -> Result<T, VdmError>
+    {
+        let start = self.pos;
+        let result = parse(self);
+
+        if result.is_err() {
+            self.pos = start;
+        }
+        result
+    }
+
+    /// Creates a `ParseError` describing a problem at the current position.
+    pub fn error(&self, msg: &str) -> VdmError {
+        VdmError::ParseError(format!("{} at offset {}", msg, self.pos))
+    }
+
+    fn escape(&mut self) -> Result<char, VdmError> {
+        match self.next_char() {
+            Some('n') => Ok('\n'),
+            Some('t') => Ok('\t'),
+            Some('r') => Ok('\r'),
+            Some('0') => Ok('\0'),
+            Some(c @ '\\') | Some(c @ '"') | Some(c @ '\'') => Ok(c),
+            _ => Err(self.error("invalid escape sequence")),
+        }
+    }
+
+    fn next_char(&mut self) -> Option<char> {
+        let c = self.rest().chars().next()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.input[self.pos..]
+    }
+}
+
+impl FromVdmStr for bool {
+    fn parse_vdm(p: &mut Parser) -> Result<bool, VdmError> {
+        if p.eat("true") {
+            Ok(true)
+        } else if p.eat("false") {
+            Ok(false)
+        } else {
+            Err(p.error("expected bool"))
+        }
+    }
+}
+
+impl FromVdmStr for String {
+    fn parse_vdm(p: &mut Parser) -> Result<String, VdmError> {
+        p.string_literal()
+    }
+}
+
+impl FromVdmStr for char {
+    fn parse_vdm(p: &mut Parser) -> Result<char, VdmError> {
+        p.char_literal()
+    }
+}
+
+macro_rules! from_str_impl {
+    ($($t:ty)*) => ($(
+        impl FromVdmStr for $t {
+            fn parse_vdm(p: &mut Parser) -> Result<$t, VdmError> {
+                p.parse_number()
+            }
+        }
+    )*)
+}
+
+from_str_impl! { i8 i16 i32 i64 isize u8 u16 u32 u64 usize f32 f64 }
+
+macro_rules! int_type_impl {
+    ($($t:ident)*) => ($(
+        impl FromVdmStr for $t {
+            fn parse_vdm(p: &mut Parser) -> Result<$t, VdmError> {
+                $t::try_new(p.parse_number()?)
+            }
+        }
+    )*)
+}
+
+int_type_impl! { Int Nat Nat1 }
+
+impl FromVdmStr for BigInt {
+    fn parse_vdm(p: &mut Parser) -> Result<BigInt, VdmError> {
+        p.parse_number().map(BigInt)
+    }
+}
+
+impl FromVdmStr for F64 {
+    fn parse_vdm(p: &mut Parser) -> Result<F64, VdmError> {
+        p.parse_number().map(F64)
+    }
+}
+
+impl<const FRAC: u32> FromVdmStr for Fixed<FRAC> {
+    fn parse_vdm(p: &mut Parser) -> Result<Fixed<FRAC>, VdmError> {
+        p.number()?.parse()
+    }
+}
+
+impl<const PLACES: u32> FromVdmStr for Decimal<PLACES> {
+    fn parse_vdm(p: &mut Parser) -> Result<Decimal<PLACES>, VdmError> {
+        p.number()?.parse()
+    }
+}
+
+/// Reads `mk_token(...)`. The text between the parentheses becomes the
+/// token value, as printed by `Display`.
+impl FromVdmStr for Token {
+    fn parse_vdm(p: &mut Parser) -> Result<Token, VdmError> {
+        p.expect("mk_token")?;
+        p.expect("(")?;
+        let start = p.pos;
+        let mut depth = 0;
+
+        loop {
+            match p.next_char() {
+                Some('(') => depth += 1,
+                Some(')') if depth == 0 => break,
+                Some(')') => depth -= 1,
+                Some('"') => {
+                    p.pos -= 1;
+                    p.string_literal()?;
+                }
+                Some(_) => {}
+                None => return Err(p.error("expected `)`")),
+            }
+        }
+
+        Ok(Token::new(&p.input[start..p.pos - 1].trim()))
+    }
+}
+
+/// Reads `nil` or a value of `T`.
+impl<T: FromVdmStr> FromVdmStr for Opt<T> {
+    fn parse_vdm(p: &mut Parser) -> Result<Opt<T>, VdmError> {
+        if p.eat("nil") {
+            Ok(Opt::nil())
+        } else {
+            T::parse_vdm(p).map(Opt::some)
+        }
+    }
+}
+
+/// Reads the fields after the first one of a `mk_(...)` tuple.
+pub trait FromVdmFields: Sized {
+    fn parse_fields(p: &mut Parser) -> Result<Self, VdmError>;
+}
+
+impl FromVdmFields for () {
+    fn parse_fields(p: &mut Parser) -> Result<(), VdmError> {
+        p.expect(")")
+    }
+}
+
+impl<H: FromVdmStr, T: FromVdmFields> FromVdmFields for Tuple<H, T> {
+    fn parse_fields(p: &mut Parser) -> Result<Tuple<H, T>, VdmError> {
+        p.expect(",")?;
+        let head = H::parse_vdm(p)?;
+        Ok(Tuple(head, T::parse_fields(p)?))
+    }
+}
+
+/// Reads `mk_(a, b, ...)`.
+impl<H: FromVdmStr, T: FromVdmFields> FromVdmStr for Tuple<H, T> {
+    fn parse_vdm(p: &mut Parser) -> Result<Tuple<H, T>, VdmError> {
+        p.expect("mk_(")?;
+        let head = H::parse_vdm(p)?;
+        Ok(Tuple(head, T::parse_fields(p)?))
+    }
+}
+
+/// Reads `[a, b]`, or a string literal if `T` is `char`.
+fn parse_seq<T: FromVdmStr + Any>(p: &mut Parser) -> Result<Vec<T>, VdmError> {
+    if p.peek() == Some('"') {
+        let chars: Vec<char> = p.string_literal()?.chars().collect();
+        let chars: Box<dyn Any> = Box::new(chars);
+
+        return match chars.downcast::<Vec<T>>() {
+            Ok(chars) => Ok(*chars),
+            Err(_) => Err(p.error("string literal is not a sequence of this type")),
+        };
+    }
+
+    p.expect("[")?;
+    p.list("]", T::parse_vdm)
+}
+
+/// Reads `{a, b}`.
+fn parse_set<T: FromVdmStr>(p: &mut Parser) -> Result<Vec<T>, VdmError> {
+    p.expect("{")?;
+    p.list("}", T::parse_vdm)
+}
+
+/// Reads `{k |-> v}`. The empty map is `{|->}` or `{}`. A key may repeat
+/// only with the same value, as in a VDM map enumeration.
+fn parse_map<K: FromVdmStr, V: FromVdmStr>(p: &mut Parser) -> Result<Vec<(K, V)>, VdmError> {
+    p.expect("{")?;
+
+    if p.eat("|->") {
+        p.expect("}")?;
+        return Ok(Vec::new());
+    }
+
+    p.list("}", |p| {
+        let key = K::parse_vdm(p)?;
+        p.expect("|->")?;
+        Ok((key, V::parse_vdm(p)?))
+    })
+}
+
+impl<T: FromVdmStr + Hash + Clone + Any> FromVdmStr for Seq<T> {
+    fn parse_vdm(p: &mut Parser) -> Result<Seq<T>, VdmError> {
+        Ok(parse_seq(p)?.into_iter().collect())
+    }
+}
+
+impl<T: FromVdmStr + Hash + Eq + Clone> FromVdmStr for Set<T> {
+    fn parse_vdm(p: &mut Parser) -> Result<Set<T>, VdmError> {
+        Ok(parse_set(p)?.into_iter().collect())
+    }
+}
+
+impl<T: FromVdmStr + Ord + Clone> FromVdmStr for OrdSet<T> {
+    fn parse_vdm(p: &mut Parser) -> Result<OrdSet<T>, VdmError> {
+        Ok(parse_set(p)?.into_iter().collect())
+    }
+}
+
+impl<K, V> FromVdmStr for Map<K, V>
+    where K: FromVdmStr + Hash + Eq + Clone,
+          V: FromVdmStr + Hash + Eq + Clone
+{
+    fn parse_vdm(p: &mut Parser) -> Result<Map<K, V>, VdmError> {
+        let mut map = Map::new();
+
+        for (key, val) in parse_map::<K, V>(p)? {
+            if map.insert(key, val.clone()).is_some_and(|old| old != val) {
+                return Err(VdmError::IncompatibleMerge);
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+impl<K, V> FromVdmStr for OrdMap<K, V>
+    where K: FromVdmStr + Ord + Clone,
+          V: FromVdmStr + Ord + Clone
+{
+    fn parse_vdm(p: &mut Parser) -> Result<OrdMap<K, V>, VdmError> {
+        let mut map = OrdMap::new();
+
+        for (key, val) in parse_map::<K, V>(p)? {
+            if map.insert(key, val.clone()).is_some_and(|old| old != val) {
+                return Err(VdmError::IncompatibleMerge);
+            }
+        }
+
+        Ok(map)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::ToOwned;
+    use contracts::{self, Policy};
+    use Seq;
+    use Set;
+    use Map;
+    use Nat;
+    use Opt;
+    use Token;
+    use F64;
+    use VdmError;
+
+    #[test]
+    fn numbers() {
+        assert_eq!(Ok(-12), i64::from_vdm_str(" -12 "));
+        assert_eq!(Ok(1.5e-3), f64::from_vdm_str("1.5E-3"));
+        assert_eq!(Ok(Nat::new(3)), Nat::from_vdm_str("3"));
+        assert_eq!(Err(VdmError::NotInType(-1, "nat")), Nat::from_vdm_str("-1"));
+        assert!(u8::from_vdm_str("300").is_err());
+        assert!(i64::from_vdm_str("1.5").is_err());
+    }
+
+    #[test]
+    fn bool_and_char() {
+        assert_eq!(Ok(true), bool::from_vdm_str("true"));
+        assert_eq!(Ok('\n'), char::from_vdm_str("'\\n'"));
+    }
+
+    #[test]
+    fn strings() {
+        assert_eq!(Ok(strseq!("a \"b\"")), Seq::<char>::from_vdm_str("\"a \\\"b\\\"\""));
+        assert_eq!(Ok(strseq!("ab")), Seq::<char>::from_vdm_str("['a', 'b']"));
+        assert!(Seq::<i32>::from_vdm_str("\"ab\"").is_err());
+    }
+
+    #[test]
+    fn collections() {
+        assert_eq!(Ok(seq![set!{1, 2}, set!{}]), Seq::<Set<i32>>::from_vdm_str("[{1, 2}, {}]"));
+        assert_eq!(Ok(map!{1 => strseq!("a")}), Map::<i32, Seq<char>>::from_vdm_str("{1 |-> \"a\"}"));
+        assert_eq!(Ok(map!{}), Map::<i32, i32>::from_vdm_str("{|->}"));
+    }
+
+    #[test]
+    fn repeated_maplets() {
+        assert_eq!(Ok(map!{1 => 2}), Map::<i32, i32>::from_vdm_str("{1 |-> 2, 1 |-> 2}"));
+        assert_eq!(Err(VdmError::IncompatibleMerge), Map::<i32, i32>::from_vdm_str("{1 |-> 2, 1 |-> 3}"));
+        assert_eq!(Err(VdmError::IncompatibleMerge), OrdMap::<i32, i32>::from_vdm_str("{1 |-> 2, 1 |-> 3}"));
+    }
+
+    impl_quote! { Red }
+
+    def_record! {
+        Pixel { pos: tuple_t!(i64, i64), color: Opt<Red>, id :- Token };
+        inv p => tuple_field!(p.pos, 1) >= 0
+    }
+
+    enum Value {
+        N(i64),
+        P(Pixel),
+        S(Seq<char>),
+    }
+
+    impl_union!{ Value: i64 as Value::N, Pixel as Value::P, Seq<char> as Value::S; derive(Clone, PartialEq, Eq, Hash) }
+
+    #[test]
+    fn tokens_and_quotes() {
+        assert_eq!(Ok(Token::new(&"f(a, \")\")")), Token::from_vdm_str("mk_token( f(a, \")\") )"));
+        assert_eq!(Ok(Red), Red::from_vdm_str("<Red>"));
+        assert!(Red::from_vdm_str("<Blue>").is_err());
+    }
+
+    #[test]
+    fn optional_values() {
+        assert_eq!(Ok(seq![Opt::nil(), Opt::some(F64(2.5))]), Seq::<Opt<F64>>::from_vdm_str("[nil, 2.5]"));
+    }
+
+    #[test]
+    fn tuples() {
+        let t = <tuple_t!(i32, Seq<char>, bool)>::from_vdm_str("mk_(1, \"a\", true)");
+
+        assert_eq!(Ok(mk_tuple!(1, strseq!("a"), true)), t);
+        assert!(<tuple_t!(i32, i32)>::from_vdm_str("mk_(1, 2, 3)").is_err());
+    }
+
+    #[test]
+    fn records() {
+        contracts::set_thread_policy(Some(Policy::Error));
+        let p = Pixel::from_vdm_str("mk_Pixel(mk_(1, 2), <Red>, mk_token(a))");
+
+        assert_eq!(Ok(Pixel::new(mk_tuple!(1, 2), Opt::some(Red), Token::new(&"a"))), p);
+        assert!(Pixel::from_vdm_str("mk_Pixel(mk_(-1, 2), nil, mk_token(a))").is_err());
+    }
+
+    #[test]
+    fn unions() {
+        let vals = Seq::<Value>::from_vdm_str("[3, \"x\", mk_Pixel(mk_(0, 0), nil, mk_token(b))]").unwrap();
+
+        assert!(vals.get(1).is_N());
+        assert!(vals.get(2).is_S());
+        assert!(vals.get(3).is_P());
+        assert!(Value::from_vdm_str("<Red>").is_err());
+    }
+
+    #[test]
+    fn ordered_collections() {
+        assert_eq!(Ok(ord_map!{1 => ord_set!{2}}), OrdMap::<i32, OrdSet<i32>>::from_vdm_str("{1 |-> {2}}"));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Err(VdmError::ParseError("expected `,` at offset 3".to_owned())),
+                   Seq::<i32>::from_vdm_str("[1 2]"));
+        assert_eq!(Err(VdmError::ParseError("unexpected trailing input at offset 4".to_owned())),
+                   Set::<i32>::from_vdm_str("{1} 2"));
+    }
+}
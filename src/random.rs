@@ -0,0 +1,213 @@
+#[cfg(feature = "std")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "std")]
+use std::time::{SystemTime, UNIX_EPOCH};
+use lib::Box;
+
+/// A pluggable source of random numbers for `MATH::rand`.
+pub trait RandomSource {
+    fn set_seed(&mut self, seed: i64);
+
+    /// Returns a uniformly distributed `i64`.
+    fn next_i64(&mut self) -> i64;
+}
+
+impl<R: RandomSource + ?Sized> RandomSource for Box<R> {
+    fn set_seed(&mut self, seed: i64) {
+        (**self).set_seed(seed)
+    }
+
+    fn next_i64(&mut self) -> i64 {
+        (**self).next_i64()
+    }
+}
+
+const MULTIPLIER: u64 = 0x5_DEEC_E66D;
+const ADDEND: u64 = 0xB;
+const MASK: u64 = (1 << 48) - 1;
+
+/// The 48-bit linear congruential generator of `java.util.Random`, which
+/// the Overture interpreter uses for `MATH`. For the same seed, it yields
+/// the same numbers as Java, bit for bit. This algorithm will not change.
+///
+/// # Examples
+///
+/// ```
+/// use codegen_runtime::JavaRandom;
+///
+/// let mut rnd = JavaRandom::new(42);
+///
+/// assert_eq!(-5025562857975149833, rnd.next_i64());
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct JavaRandom {
+    seed: u64,
+}
+
+impl JavaRandom {
+    pub fn new(seed: i64) -> JavaRandom {
+        let mut rnd = JavaRandom { seed: 0 };
+        rnd.set_seed(seed);
+        rnd
+    }
+
+    /// A generator seeded from the system clock, distinct from any other
+    /// created by this function.
+    #[cfg(feature = "std")]
+    pub fn from_time() -> JavaRandom {
+        static UNIQUIFIER: AtomicU64 = AtomicU64::new(8_682_522_807_148_012);
+
+        let unique = UNIQUIFIER.fetch_add(181_783_497_276_652_981, Ordering::Relaxed);
+        let nanos = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map(|d| d.as_nanos() as u64)
+                        .unwrap_or(0);
+
+        JavaRandom::new((unique ^ nanos) as i64)
+    }
+
+    fn next(&mut self, bits: u32) -> i32 {
+        self.seed = self.seed.wrapping_mul(MULTIPLIER).wrapping_add(ADDEND) & MASK;
+        (self.seed >> (48 - bits)) as i32
+    }
+
+    pub fn next_i32(&mut self) -> i32 {
+        self.next(32)
+    }
+
+    pub fn next_i64(&mut self) -> i64 {
+        ((self.next(32) as i64) << 32).wrapping_add(self.next(32) as i64)
+    }
+
+    /// Returns a value in `[0, 1)`, like `Random.nextDouble`.
+    pub fn next_f64(&mut self) -> f64 {
+        let bits = ((self.next(26) as i64) << 27) + self.next(27) as i64;
+        bits as f64 / (1u64 << 53) as f64
+    }
+}
+
+impl RandomSource for JavaRandom {
+    fn set_seed(&mut self, seed: i64) {
+        self.seed = (seed as u64 ^ MULTIPLIER) & MASK;
+    }
+
+    fn next_i64(&mut self) -> i64 {
+        JavaRandom::next_i64(self)
+    }
+}
+
+/// The state behind the VDM `MATH` random functions, with their Overture
+/// semantics: `rand(top)` yields a number in `[0, |top|)`, except after
+/// `srand(-1)`, when it yields `top` itself.
+///
+/// `MATH` uses one `Generator` per thread. Create your own for an
+/// independent sequence.
+///
+/// # Examples
+///
+/// ```
+/// use codegen_runtime::{Generator, JavaRandom};
+///
+/// let mut a = Generator::new(JavaRandom::new(1));
+/// let mut b = Generator::new(JavaRandom::new(2));
+/// a.srand(7);
+/// b.srand(7);
+///
+/// assert_eq!(a.rand(100), b.rand(100));
+///
+/// a.srand(-1);
+/// assert_eq!(100, a.rand(100));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Generator<R: RandomSource> {
+    source: R,
+    disabled: bool,
+}
+
+impl<R: RandomSource> Generator<R> {
+    pub fn new(source: R) -> Generator<R> {
+        Generator {
+            source,
+            disabled: false,
+        }
+    }
+
+    pub fn srand(&mut self, seed: i64) {
+        self.disabled = seed == -1;
+        self.source.set_seed(seed);
+    }
+
+    pub fn srand2(&mut self, seed: i64) -> i64 {
+        self.srand(seed);
+        seed
+    }
+
+    pub fn rand(&mut self, top: i64) -> i64 {
+        if self.disabled {
+            top
+        } else if top == 0 {
+            0
+        } else {
+            self.source.next_i64().wrapping_rem(top).wrapping_abs()
+        }
+    }
+
+    pub fn source_mut(&mut self) -> &mut R {
+        &mut self.source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::Vec;
+
+    #[test]
+    fn java_sequence() {
+        let mut rnd = JavaRandom::new(42);
+        assert_eq!(-1170105035, rnd.next_i32());
+
+        let mut rnd = JavaRandom::new(42);
+        assert_eq!(0.7275636800328681, rnd.next_f64());
+
+        let mut rnd = JavaRandom::new(0);
+        assert_eq!(-1155484576, rnd.next_i32());
+        assert_eq!(-723955400, rnd.next_i32());
+    }
+
+    #[test]
+    fn reseeding_restarts() {
+        let mut g = Generator::new(JavaRandom::new(1));
+        let first: Vec<i64> = (0..5).map(|_| g.rand(1000)).collect();
+
+        assert_eq!(42, g.srand2(42));
+        g.srand(1);
+        let second: Vec<i64> = (0..5).map(|_| g.rand(1000)).collect();
+
+        assert_eq!(first, second);
+        assert!(first.iter().all(|&n| (0..1000).contains(&n)));
+        assert_eq!(0, g.rand(0));
+    }
+
+    #[test]
+    fn pluggable_source() {
+        struct Counter(i64);
+
+        impl RandomSource for Counter {
+            fn set_seed(&mut self, seed: i64) {
+                self.0 = seed;
+            }
+
+            fn next_i64(&mut self) -> i64 {
+                self.0 += 1;
+                self.0
+            }
+        }
+
+        let mut g: Generator<Box<dyn RandomSource>> = Generator::new(Box::new(Counter(0)));
+        g.srand(10);
+
+        assert_eq!(1, g.rand(10));
+        assert_eq!(2, g.rand(-10));
+    }
+}
@@ -0,0 +1,225 @@
+use lib::ops::{Add, Sub, Mul, Div, Neg};
+use lib::cmp;
+use lib::convert::TryFrom;
+use lib::fmt;
+use lib::ToString;
+use num_bigint;
+use num_rational::BigRational;
+use num_traits::{One, Signed, ToPrimitive, Zero};
+use error::unwrap_vdm;
+use BigInt;
+use F64;
+use VdmError;
+
+/// Exact rational number for VDM `rat` and `real` values.
+/// Unlike `F64`, arithmetic never rounds.
+#[derive(Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Rat(pub BigRational);
+
+impl Rat {
+    /// Creates `numer / denom` in lowest terms. Panics if `denom` is zero.
+    pub fn new(numer: BigInt, denom: BigInt) -> Rat {
+        Rat(BigRational::new(numer.0, denom.0))
+    }
+
+    pub fn numer(&self) -> BigInt {
+        BigInt(self.0.numer().clone())
+    }
+
+    pub fn denom(&self) -> BigInt {
+        BigInt(self.0.denom().clone())
+    }
+
+    pub fn is_integer(&self) -> bool {
+        self.0.is_integer()
+    }
+
+    pub fn floor(&self) -> BigInt {
+        BigInt(self.0.floor().to_integer())
+    }
+
+    pub fn abs(&self) -> Rat {
+        Rat(self.0.abs())
+    }
+
+    pub fn pow(&self, exp: i32) -> Rat {
+        Rat(self.0.pow(exp))
+    }
+
+    pub fn try_div(&self, other: Rat) -> Result<Rat, VdmError> {
+        if other.0.is_zero() {
+            return Err(VdmError::DivisionByZero);
+        }
+
+        Ok(Rat(&self.0 / other.0))
+    }
+}
+
+/// Prints the exact value: integral values without a fractional part,
+/// values with a finite decimal expansion in full, and others as
+/// `numer/denom`.
+impl fmt::Display for Rat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let (numer, denom) = (self.0.numer(), self.0.denom());
+
+        // The expansion is finite iff the denominator has no prime factors
+        // but 2 and 5, and then has as many digits as the larger power.
+        let mut rest = denom.clone();
+        let mut powers = [0u32; 2];
+        for (power, &factor) in powers.iter_mut().zip(&[2u32, 5]) {
+            while (&rest % factor).is_zero() {
+                rest /= factor;
+                *power += 1;
+            }
+        }
+
+        if !rest.is_one() {
+            return write!(f, "{}/{}", numer, denom);
+        }
+
+        let places = cmp::max(powers[0], powers[1]) as usize;
+        if places == 0 {
+            return write!(f, "{}", numer);
+        }
+
+        let digits = (numer.abs() * num_bigint::BigInt::from(10u32).pow(places as u32) / denom).to_string();
+        let digits = format!("{:0>width$}", digits, width = places + 1);
+        let (int, frac) = digits.split_at(digits.len() - places);
+        let sign = if numer.is_negative() { "-" } else { "" };
+
+        write!(f, "{}{}.{}", sign, int, frac)
+    }
+}
+
+impl fmt::Debug for Rat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}/{}", self.0.numer(), self.0.denom())
+    }
+}
+
+macro_rules! from_impl {
+    ($($t:ty)*) => ($(
+        impl From<$t> for Rat {
+            fn from(val: $t) -> Rat {
+                Rat::from(BigInt::from(val))
+            }
+        }
+    )*)
+}
+
+from_impl! { u64 i32 i64 }
+
+impl From<BigInt> for Rat {
+    fn from(val: BigInt) -> Rat {
+        Rat(BigRational::from_integer(val.0))
+    }
+}
+
+/// Exact conversion of the binary value held by the `F64`. NaN and the
+/// infinities are not rationals.
+impl TryFrom<F64> for Rat {
+    type Error = VdmError;
+
+    fn try_from(val: F64) -> Result<Rat, VdmError> {
+        BigRational::from_float(val.0).map(Rat).ok_or(VdmError::NotANumber)
+    }
+}
+
+impl From<Rat> for F64 {
+    fn from(val: Rat) -> F64 {
+        F64(val.0.to_f64().expect("Rat is not representable as F64."))
+    }
+}
+
+impl Add for Rat {
+    type Output = Rat;
+
+    #[inline]
+    fn add(self, other: Rat) -> Rat {
+        Rat(self.0 + other.0)
+    }
+}
+
+impl Sub for Rat {
+    type Output = Rat;
+
+    #[inline]
+    fn sub(self, other: Rat) -> Rat {
+        Rat(self.0 - other.0)
+    }
+}
+
+impl Mul for Rat {
+    type Output = Rat;
+
+    #[inline]
+    fn mul(self, other: Rat) -> Rat {
+        Rat(self.0 * other.0)
+    }
+}
+
+impl Div for Rat {
+    type Output = Rat;
+
+    #[inline]
+    fn div(self, other: Rat) -> Rat {
+        unwrap_vdm(self.try_div(other))
+    }
+}
+
+impl Neg for Rat {
+    type Output = Rat;
+
+    #[inline]
+    fn neg(self) -> Rat {
+        Rat(-self.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use BigInt;
+    use F64;
+
+    #[test]
+    fn add_1() {
+        let r = Rat::try_from(F64(0.5)).unwrap() + Rat::new(BigInt::from(1i64), BigInt::from(10i64)) * Rat::from(5i64);
+        assert_eq!(Rat::from(1i64), r);
+    }
+
+    #[test]
+    fn floor_1() {
+        assert_eq!(BigInt::from(-2i64), Rat::try_from(F64(-1.5)).unwrap().floor());
+    }
+
+    #[test]
+    fn display_formatting() {
+        assert_eq!("2", Rat::from(2i64).to_string());
+        assert_eq!("0.25", Rat::try_from(F64(0.25)).unwrap().to_string());
+        assert_eq!("-0.05", (Rat::from(-1i64) / Rat::from(20i64)).to_string());
+        assert_eq!("12.5", (Rat::from(25i64) / Rat::from(2i64)).to_string());
+        assert_eq!("-1/3", (Rat::from(-1i64) / Rat::from(3i64)).to_string());
+        assert_eq!("0.1000000000000000055511151231257827021181583404541015625",
+                   Rat::try_from(F64(0.1)).unwrap().to_string());
+        assert_eq!("1/3", format!("{:?}", Rat::from(1i64) / Rat::from(3i64)));
+    }
+
+    #[test]
+    fn from_f64_not_finite() {
+        assert_eq!(Err(VdmError::NotANumber), F64::try_new(f64::NAN).and_then(Rat::try_from));
+        assert_eq!(Err(VdmError::NotANumber), Rat::try_from(F64(f64::NEG_INFINITY)));
+    }
+
+    #[test]
+    #[should_panic(expected = "Division by zero.")]
+    fn div_zero() {
+        let _ = Rat::from(1i64) / Rat::from(0i64);
+    }
+
+    #[test]
+    fn try_div() {
+        assert_eq!(Ok(Rat::from(2i64)), Rat::from(1i64).try_div(Rat::try_from(F64(0.5)).unwrap()));
+        assert_eq!(Err(VdmError::DivisionByZero), Rat::from(1i64).try_div(Rat::from(0i64)));
+    }
+}
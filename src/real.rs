@@ -0,0 +1,633 @@
+use lib::{f64, fmt};
+use lib::convert::TryFrom;
+use lib::hash::Hash;
+use lib::ops::{Add, Sub, Mul, Div, Neg};
+use lib::str::FromStr;
+use num_bigint::BigUint;
+use num_traits::{Float, One, ToPrimitive};
+use error::unwrap_vdm;
+use fixed_math;
+use F64;
+use VdmError;
+
+/// Representations of VDM `real` values. `F64` is the default; `Fixed`
+/// and `Decimal` avoid floating-point arithmetic for targets without an
+/// FPU. The `MATH` functions are generic over it.
+///
+/// Conversions to and from `f64` must round to the nearest representable
+/// value, and fail rather than produce NaN or a value out of range.
+///
+/// The elementary functions behind `MATH` are computed without `f64` for
+/// the scaled types. `MATH` checks the VDM preconditions; these functions
+/// fail with `NotANumber` outside their domain and with
+/// `ArithmeticOverflow` when the result is out of range.
+pub trait Real: Copy + Ord + Hash + fmt::Debug + fmt::Display
+    + Add<Output = Self> + Sub<Output = Self> + Mul<Output = Self>
+    + Div<Output = Self> + Neg<Output = Self>
+{
+    fn try_from_f64(val: f64) -> Result<Self, VdmError>;
+
+    fn to_f64(self) -> f64;
+
+    fn try_from_int(val: i64) -> Result<Self, VdmError>;
+
+    fn try_div(self, other: Self) -> Result<Self, VdmError>;
+
+    /// π, rounded to the nearest representable value. Panics if it is out
+    /// of range.
+    fn pi() -> Self;
+
+    /// The magnitude below which the result of an elementary function is
+    /// indistinguishable from zero, as its argument is rounded. `MATH`
+    /// takes a `cos v` or `sin v` this small to be zero.
+    fn epsilon() -> Self;
+
+    fn try_sin(self) -> Result<Self, VdmError>;
+
+    fn try_cos(self) -> Result<Self, VdmError>;
+
+    fn try_tan(self) -> Result<Self, VdmError>;
+
+    fn try_asin(self) -> Result<Self, VdmError>;
+
+    fn try_acos(self) -> Result<Self, VdmError>;
+
+    fn try_atan(self) -> Result<Self, VdmError>;
+
+    fn try_sqrt(self) -> Result<Self, VdmError>;
+
+    fn try_exp(self) -> Result<Self, VdmError>;
+
+    fn try_ln(self) -> Result<Self, VdmError>;
+
+    fn try_log10(self) -> Result<Self, VdmError>;
+
+    /// Panics if `val` is NaN or out of range.
+    fn from_f64(val: f64) -> Self {
+        unwrap_vdm(Self::try_from_f64(val))
+    }
+
+    fn floor(self) -> i64 {
+        Float::floor(self.to_f64()) as i64
+    }
+
+    fn abs(self) -> Self {
+        if self.to_f64() < 0.0 {
+            -self
+        } else {
+            self
+        }
+    }
+}
+
+impl Real for F64 {
+    fn try_from_f64(val: f64) -> Result<F64, VdmError> {
+        F64::try_new(val)
+    }
+
+    fn to_f64(self) -> f64 {
+        self.0
+    }
+
+    fn try_from_int(val: i64) -> Result<F64, VdmError> {
+        Ok(F64(val as f64))
+    }
+
+    fn try_div(self, other: F64) -> Result<F64, VdmError> {
+        F64::try_div(&self, other)
+    }
+
+    fn pi() -> F64 {
+        F64(f64::consts::PI)
+    }
+
+    /// `cos` of the `f64` nearest to π/2 is 6e-17, and the rounding error
+    /// grows with the argument.
+    fn epsilon() -> F64 {
+        F64(1e-12)
+    }
+
+    fn try_sin(self) -> Result<F64, VdmError> {
+        F64::try_new(Float::sin(self.0))
+    }
+
+    fn try_cos(self) -> Result<F64, VdmError> {
+        F64::try_new(Float::cos(self.0))
+    }
+
+    fn try_tan(self) -> Result<F64, VdmError> {
+        F64::try_new(Float::tan(self.0))
+    }
+
+    fn try_asin(self) -> Result<F64, VdmError> {
+        F64::try_new(self.0.asin())
+    }
+
+    fn try_acos(self) -> Result<F64, VdmError> {
+        F64::try_new(self.0.acos())
+    }
+
+    fn try_atan(self) -> Result<F64, VdmError> {
+        F64::try_new(Float::atan(self.0))
+    }
+
+    fn try_sqrt(self) -> Result<F64, VdmError> {
+        F64::try_new(Float::sqrt(self.0))
+    }
+
+    fn try_exp(self) -> Result<F64, VdmError> {
+        F64::try_new(Float::exp(self.0))
+    }
+
+    fn try_ln(self) -> Result<F64, VdmError> {
+        F64::try_new(Float::ln(self.0))
+    }
+
+    fn try_log10(self) -> Result<F64, VdmError> {
+        F64::try_new(Float::log10(self.0))
+    }
+
+    fn floor(self) -> i64 {
+        F64::floor(&self)
+    }
+
+    fn abs(self) -> F64 {
+        F64::abs(&self)
+    }
+}
+
+/// Declares a real number type holding `value / SCALE` in an `i64`.
+/// Arithmetic truncates towards zero, and panics on overflow and division
+/// by zero; the `try_*` variants return the `VdmError` instead.
+macro_rules! scaled_real {
+    ($(#[$attr:meta])* $name:ident<const $param:ident: u32>, $scale:expr) => {
+        $(#[$attr])*
+        #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+        pub struct $name<const $param: u32>(i64);
+
+        impl<const $param: u32> $name<$param> {
+            pub const SCALE: i64 = $scale;
+
+            /// The value `raw / SCALE`.
+            pub fn from_raw(raw: i64) -> $name<$param> {
+                $name(raw)
+            }
+
+            pub fn raw(&self) -> i64 {
+                self.0
+            }
+
+            pub fn from_int(val: i64) -> $name<$param> {
+                unwrap_vdm($name::try_from_int(val))
+            }
+
+            pub fn try_from_int(val: i64) -> Result<$name<$param>, VdmError> {
+                match val.checked_mul(Self::SCALE) {
+                    Some(raw) => Ok($name(raw)),
+                    None => Err(VdmError::ArithmeticOverflow),
+                }
+            }
+
+            pub fn try_add(&self, other: $name<$param>) -> Result<$name<$param>, VdmError> {
+                self.0.checked_add(other.0).map($name).ok_or(VdmError::ArithmeticOverflow)
+            }
+
+            pub fn try_sub(&self, other: $name<$param>) -> Result<$name<$param>, VdmError> {
+                self.0.checked_sub(other.0).map($name).ok_or(VdmError::ArithmeticOverflow)
+            }
+
+            pub fn try_mul(&self, other: $name<$param>) -> Result<$name<$param>, VdmError> {
+                $name::narrow(self.0 as i128 * other.0 as i128 / Self::SCALE as i128)
+            }
+
+            pub fn try_div(&self, other: $name<$param>) -> Result<$name<$param>, VdmError> {
+                if other.0 == 0 {
+                    return Err(VdmError::DivisionByZero);
+                }
+
+                $name::narrow(self.0 as i128 * Self::SCALE as i128 / other.0 as i128)
+            }
+
+            fn narrow(raw: i128) -> Result<$name<$param>, VdmError> {
+                if raw < i64::MIN as i128 || raw > i64::MAX as i128 {
+                    Err(VdmError::ArithmeticOverflow)
+                } else {
+                    Ok($name(raw as i64))
+                }
+            }
+
+            fn to_q60(self) -> i128 {
+                fixed_math::from_raw(self.0, Self::SCALE)
+            }
+
+            fn from_q60(x: i128) -> Result<$name<$param>, VdmError> {
+                fixed_math::to_raw(x, Self::SCALE).map($name)
+            }
+
+            /// Positive values too small for Q60 have a logarithm out of
+            /// range, as the type has more than 60 fractional bits.
+            fn try_log_q60<F: FnOnce(i128) -> i128>(self, log: F) -> Result<$name<$param>, VdmError> {
+                if self.0 <= 0 {
+                    Err(VdmError::NotANumber)
+                } else if self.to_q60() == 0 {
+                    Err(VdmError::ArithmeticOverflow)
+                } else {
+                    $name::from_q60(log(self.to_q60()))
+                }
+            }
+        }
+
+        impl<const $param: u32> Real for $name<$param> {
+            fn try_from_f64(val: f64) -> Result<$name<$param>, VdmError> {
+                let raw = Float::round(val * Self::SCALE as f64);
+
+                if val.is_nan() {
+                    Err(VdmError::NotANumber)
+                } else if raw < i64::MIN as f64 || raw >= i64::MAX as f64 {
+                    Err(VdmError::ArithmeticOverflow)
+                } else {
+                    Ok($name(raw as i64))
+                }
+            }
+
+            fn to_f64(self) -> f64 {
+                self.0 as f64 / Self::SCALE as f64
+            }
+
+            fn try_from_int(val: i64) -> Result<$name<$param>, VdmError> {
+                $name::try_from_int(val)
+            }
+
+            fn try_div(self, other: $name<$param>) -> Result<$name<$param>, VdmError> {
+                $name::try_div(&self, other)
+            }
+
+            fn pi() -> $name<$param> {
+                unwrap_vdm($name::from_q60(fixed_math::PI))
+            }
+
+            /// The resolution: the kernels are exact to within it.
+            fn epsilon() -> $name<$param> {
+                $name(1)
+            }
+
+            fn try_sin(self) -> Result<$name<$param>, VdmError> {
+                $name::from_q60(fixed_math::sin(self.to_q60()))
+            }
+
+            fn try_cos(self) -> Result<$name<$param>, VdmError> {
+                $name::from_q60(fixed_math::cos(self.to_q60()))
+            }
+
+            fn try_tan(self) -> Result<$name<$param>, VdmError> {
+                $name::from_q60(fixed_math::tan(self.to_q60())?)
+            }
+
+            fn try_asin(self) -> Result<$name<$param>, VdmError> {
+                if self.0.unsigned_abs() > Self::SCALE as u64 {
+                    return Err(VdmError::NotANumber);
+                }
+
+                $name::from_q60(fixed_math::asin(self.to_q60()))
+            }
+
+            fn try_acos(self) -> Result<$name<$param>, VdmError> {
+                if self.0.unsigned_abs() > Self::SCALE as u64 {
+                    return Err(VdmError::NotANumber);
+                }
+
+                $name::from_q60(fixed_math::acos(self.to_q60()))
+            }
+
+            fn try_atan(self) -> Result<$name<$param>, VdmError> {
+                $name::from_q60(fixed_math::atan(self.to_q60()))
+            }
+
+            /// Computed on the raw value, so that it is exact to the
+            /// resolution of the type.
+            fn try_sqrt(self) -> Result<$name<$param>, VdmError> {
+                if self.0 < 0 {
+                    return Err(VdmError::NotANumber);
+                }
+
+                let square = self.0 as u128 * Self::SCALE as u128;
+                let root = fixed_math::isqrt(square);
+                let rounded = if square - root * root > root { root + 1 } else { root };
+
+                $name::narrow(rounded as i128)
+            }
+
+            fn try_exp(self) -> Result<$name<$param>, VdmError> {
+                $name::from_q60(fixed_math::exp(self.to_q60())?)
+            }
+
+            fn try_ln(self) -> Result<$name<$param>, VdmError> {
+                self.try_log_q60(fixed_math::ln)
+            }
+
+            fn try_log10(self) -> Result<$name<$param>, VdmError> {
+                self.try_log_q60(fixed_math::log10)
+            }
+
+            fn floor(self) -> i64 {
+                self.0.div_euclid(Self::SCALE)
+            }
+
+            fn abs(self) -> $name<$param> {
+                unwrap_vdm($name::narrow((self.0 as i128).abs()))
+            }
+        }
+
+        impl<const $param: u32> Add for $name<$param> {
+            type Output = $name<$param>;
+
+            fn add(self, other: $name<$param>) -> $name<$param> {
+                unwrap_vdm(self.try_add(other))
+            }
+        }
+
+        impl<const $param: u32> Sub for $name<$param> {
+            type Output = $name<$param>;
+
+            fn sub(self, other: $name<$param>) -> $name<$param> {
+                unwrap_vdm(self.try_sub(other))
+            }
+        }
+
+        impl<const $param: u32> Mul for $name<$param> {
+            type Output = $name<$param>;
+
+            fn mul(self, other: $name<$param>) -> $name<$param> {
+                unwrap_vdm(self.try_mul(other))
+            }
+        }
+
+        impl<const $param: u32> Div for $name<$param> {
+            type Output = $name<$param>;
+
+            fn div(self, other: $name<$param>) -> $name<$param> {
+                unwrap_vdm(self.try_div(other))
+            }
+        }
+
+        impl<const $param: u32> Neg for $name<$param> {
+            type Output = $name<$param>;
+
+            fn neg(self) -> $name<$param> {
+                unwrap_vdm($name::narrow(-(self.0 as i128)))
+            }
+        }
+
+        /// Prints the exact value, with as many decimals as it needs.
+        impl<const $param: u32> fmt::Display for $name<$param> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt_scaled(f, self.0, Self::SCALE, false)
+            }
+        }
+
+        /// Like `Display`, but integral values end in `.0`, as for `f64`.
+        impl<const $param: u32> fmt::Debug for $name<$param> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt_scaled(f, self.0, Self::SCALE, true)
+            }
+        }
+
+        /// Reads decimal text such as `-12`, `3.25` or `1.5E-3`, rounding to
+        /// the nearest representable value.
+        impl<const $param: u32> FromStr for $name<$param> {
+            type Err = VdmError;
+
+            fn from_str(text: &str) -> Result<$name<$param>, VdmError> {
+                parse_scaled(text, Self::SCALE).map($name)
+            }
+        }
+
+        impl<const $param: u32> From<i64> for $name<$param> {
+            fn from(val: i64) -> $name<$param> {
+                $name::from_int(val)
+            }
+        }
+
+        impl<const $param: u32> From<$name<$param>> for F64 {
+            fn from(val: $name<$param>) -> F64 {
+                F64(val.to_f64())
+            }
+        }
+    };
+}
+
+/// Writes `raw / scale` in decimal. The division by `scale` is exact
+/// in decimal for both powers of two and powers of ten, so the digits
+/// are generated until the remainder is zero.
+fn fmt_scaled(f: &mut fmt::Formatter, raw: i64, scale: i64, point: bool) -> fmt::Result {
+    let scale = scale as u64;
+    let (int, mut frac) = (raw.unsigned_abs() / scale, raw.unsigned_abs() % scale);
+
+    write!(f, "{}{}", if raw < 0 { "-" } else { "" }, int)?;
+
+    if frac == 0 {
+        return if point { f.write_str(".0") } else { Ok(()) };
+    }
+
+    f.write_str(".")?;
+    while frac != 0 {
+        let shifted = frac as u128 * 10;
+        write!(f, "{}", shifted / scale as u128)?;
+        frac = (shifted % scale as u128) as u64;
+    }
+
+    Ok(())
+}
+
+/// Parses decimal text into `raw` such that `raw / scale` is the value,
+/// rounded half away from zero.
+fn parse_scaled(text: &str, scale: i64) -> Result<i64, VdmError> {
+    let invalid = || VdmError::ParseError(format!("invalid number `{}`", text));
+    let is_digits = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+
+    let (negative, rest) = match text.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, text),
+    };
+
+    let (mantissa, exp) = match rest.find(['e', 'E']) {
+        Some(i) => (&rest[..i], Some(&rest[i + 1..])),
+        None => (rest, None),
+    };
+
+    let (int, frac) = match mantissa.find('.') {
+        Some(i) if is_digits(&mantissa[i + 1..]) => (&mantissa[..i], &mantissa[i + 1..]),
+        Some(_) => return Err(invalid()),
+        None => (mantissa, ""),
+    };
+
+    if !is_digits(int) {
+        return Err(invalid());
+    }
+
+    // Exponents too large for an i64 are clamped; the value then rounds
+    // to zero or overflows either way.
+    let exp = match exp {
+        None => 0,
+        Some(exp) => {
+            let (sign, digits) = match exp.strip_prefix('-') {
+                Some(digits) => (-1, digits),
+                None => (1, exp.strip_prefix('+').unwrap_or(exp)),
+            };
+
+            if !is_digits(digits) {
+                return Err(invalid());
+            }
+
+            sign * digits.parse::<i64>().unwrap_or(i64::MAX / 2)
+        }
+    };
+
+    let digits = [int, frac].concat();
+    let significant = digits.trim_start_matches('0').len() as i64;
+    if significant == 0 {
+        return Ok(0);
+    }
+
+    // The value lies in [10^magnitude, 10^(magnitude + 1)), and scales are
+    // below 10^19.
+    let exp = exp - frac.len() as i64;
+    let magnitude = significant - 1 + exp;
+    if magnitude >= 19 {
+        return Err(VdmError::ArithmeticOverflow);
+    } else if magnitude < -20 {
+        return Ok(0);
+    }
+
+    let ten = BigUint::from(10u32);
+    let mut numer = BigUint::from_str(&digits).map_err(|_| invalid())? * scale as u64;
+    let mut denom = BigUint::one();
+
+    if exp >= 0 {
+        numer *= ten.pow(exp as u32);
+    } else {
+        denom = ten.pow(-exp as u32);
+    }
+
+    let magnitude = ((numer * 2u32 + &denom) / (denom * 2u32)).to_i128().ok_or(VdmError::ArithmeticOverflow)?;
+    let raw = if negative { -magnitude } else { magnitude };
+
+    i64::try_from(raw).map_err(|_| VdmError::ArithmeticOverflow)
+}
+
+scaled_real! {
+    /// Binary fixed-point real with `FRAC` fractional bits, i.e. the
+    /// Q(63 - FRAC).FRAC format in an `i64`. Resolution is `2^-FRAC`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use codegen_runtime::{Fixed, Real};
+    ///
+    /// let x = Fixed::<16>::from_f64(1.5);
+    ///
+    /// assert_eq!(Fixed::from_f64(2.25), x * x);
+    /// assert_eq!(98304, x.raw());
+    /// ```
+    Fixed<const FRAC: u32>, 1 << FRAC
+}
+
+scaled_real! {
+    /// Decimal real with `PLACES` digits after the decimal point, so that
+    /// decimal fractions such as `0.1` are exact.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use codegen_runtime::{Decimal, Real};
+    ///
+    /// let tenth = Decimal::<4>::from_f64(0.1);
+    ///
+    /// assert_eq!(Decimal::from_f64(0.3), tenth + tenth + tenth);
+    /// ```
+    Decimal<const PLACES: u32>, 10i64.pow(PLACES)
+}
+
+/// Q32.32 fixed-point real.
+pub type Q32_32 = Fixed<32>;
+
+/// Q48.16 fixed-point real.
+pub type Q48_16 = Fixed<16>;
+
+/// The representation of VDM `real` the generated code uses, chosen by
+/// cargo feature: `F64` by default, `Q32_32` with `real-fixed` and
+/// `Decimal<9>` with `real-decimal`. With both features, `real-fixed`
+/// takes precedence.
+#[cfg(not(any(feature = "real-fixed", feature = "real-decimal")))]
+pub type VdmReal = F64;
+
+#[cfg(feature = "real-fixed")]
+pub type VdmReal = Q32_32;
+
+#[cfg(all(feature = "real-decimal", not(feature = "real-fixed")))]
+pub type VdmReal = Decimal<9>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lib::ToString;
+    use FromVdmStr;
+    use VdmDisplay;
+
+    #[test]
+    fn fixed_arithmetic() {
+        let a = Q48_16::from_f64(2.5);
+        let b = Q48_16::from_int(-4);
+
+        assert_eq!(Q48_16::from_f64(-10.0), a * b);
+        assert_eq!(Q48_16::from_f64(-0.5), b / Q48_16::from_int(8));
+        assert_eq!(-2, Q48_16::from_f64(-1.25).floor());
+        assert_eq!(2, a.floor());
+        assert_eq!(Q48_16::from_f64(1.5), (b + a).abs());
+        assert!(a < b.abs());
+    }
+
+    #[test]
+    fn decimal_is_exact() {
+        let cent = Decimal::<2>::from_f64(0.01);
+        let sum = (0..100).fold(Decimal::<2>::default(), |acc, _| acc + cent);
+
+        assert_eq!(Decimal::from_int(1), sum);
+        assert_eq!("1", sum.to_string());
+        assert_eq!(Ok(Decimal::from_f64(-2.25)), Decimal::<2>::from_vdm_str("-2.25"));
+        assert_eq!("{0.5, 1}", ord_set!{Decimal::<2>::from_int(1), cent * Decimal::from_int(50)}.to_vdm_string());
+        assert_eq!(-1, Decimal::<2>::from_f64(-0.5).floor());
+    }
+
+    #[test]
+    fn exact_text() {
+        let q = Q32_32::from_raw(0x7fff_ffff_ffff_ffff);
+
+        assert_eq!("2147483647.99999999976716935634613037109375", q.to_string());
+        assert_eq!(Ok(q), q.to_string().parse());
+        assert_eq!("-0.5", format!("{:?}", Q48_16::from_raw(-32768)));
+        assert_eq!("3.0", format!("{:?}", Q48_16::from_int(3)));
+        assert_eq!("-92233720368547758.08", Decimal::<2>::from_raw(i64::MIN).to_string());
+        assert_eq!(Ok(Decimal::<2>::from_raw(i64::MIN)), "-92233720368547758.08".parse());
+        assert_eq!(Ok(Decimal::<9>::from_raw(123_456_789_012_345_678)), "123456789.012345678".parse());
+        assert_eq!(Ok(Decimal::<2>::from_raw(-13)), "-0.125".parse());
+        assert_eq!(Ok(Decimal::<2>::from_raw(150)), "15E-1".parse());
+        assert_eq!(Ok(Decimal::<2>::default()), "1e-99999999999999999999".parse());
+        assert_eq!(Err(VdmError::ArithmeticOverflow), "92233720368547758.08".parse::<Decimal<2>>());
+        assert_eq!(Err(VdmError::ArithmeticOverflow), "1e19".parse::<Fixed<0>>());
+        assert!("1.".parse::<Decimal<2>>().is_err());
+        assert!("1e".parse::<Decimal<2>>().is_err());
+    }
+
+    #[test]
+    fn errors() {
+        let max = Fixed::<32>::from_raw(i64::MAX);
+
+        assert_eq!(Err(VdmError::ArithmeticOverflow), max.try_add(Fixed::from_raw(1)));
+        assert_eq!(Err(VdmError::ArithmeticOverflow), max.try_mul(Fixed::from_int(2)));
+        assert_eq!(Err(VdmError::DivisionByZero), max.try_div(Fixed::default()));
+        assert_eq!(Err(VdmError::NotANumber), Fixed::<32>::try_from_f64(f64::NAN));
+        assert_eq!(Err(VdmError::ArithmeticOverflow), Fixed::<32>::try_from_f64(1e10));
+        assert_eq!(Err(VdmError::ArithmeticOverflow), Decimal::<4>::try_from_int(i64::MAX));
+    }
+}
@@ -0,0 +1,898 @@
+//! Simulated time for VDM-RT models.
+//!
+//! A `Simulator` runs the threads of a model on `CPU`s connected by
+//! `BUS`es in virtual time. Everything happens on the calling thread and
+//! in a fixed order, so a run takes no longer than the computation itself
+//! and always gives the same result.
+//!
+//! Threads are `Task`s: state machines that return a `Step` each time
+//! they are scheduled. A `Step` says what the statement they ran costs,
+//! as `duration(...)` and `cycles(...)` do in the model, or what the task
+//! waits for. A task must not block the calling thread, e.g. in
+//! `Monitor::call`; it returns `Step::Blocked` instead.
+
+use lib::cell::Cell;
+use lib::cmp::Reverse;
+use lib::collections::BinaryHeap;
+use lib::fmt;
+use lib::{Box, String, Vec};
+use lib::sync::Mutex;
+use concurrency::Clock;
+use rt_log::{RtEvent, RtLogger};
+use std::io::Write;
+use std::sync::Arc;
+
+/// Time units per second: the unit is a nanosecond, as in Overture.
+pub const UNITS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Simulated time, which only moves when it is advanced. As a `Clock`,
+/// waiting for a time advances the clock to it immediately.
+#[derive(Debug, Default)]
+pub struct VirtualClock {
+    now: Mutex<u64>,
+}
+
+impl VirtualClock {
+    pub fn new() -> VirtualClock {
+        VirtualClock::default()
+    }
+
+    /// Moves the clock forward by `duration`.
+    pub fn advance(&self, duration: u64) {
+        let mut now = self.now.lock();
+        *now = now.saturating_add(duration);
+    }
+
+    /// Moves the clock forward to `time`; a time in the past is ignored.
+    pub fn advance_to(&self, time: u64) {
+        let mut now = self.now.lock();
+        *now = (*now).max(time);
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> u64 {
+        *self.now.lock()
+    }
+
+    fn wait_until(&self, time: u64) {
+        self.advance_to(time);
+    }
+}
+
+/// How a `CPU` picks the next thread, or a `BUS` the next message.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Policy {
+    /// `<FCFS>`: in the order they became ready.
+    Fcfs,
+    /// `<FP>`: highest priority first, and in the order they became ready
+    /// among equal priorities.
+    FixedPriority,
+}
+
+/// A `CPU` of the system, numbered from 1 in the order of `add_cpu`. The
+/// virtual CPU of Overture is 0.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct CpuId(pub u64);
+
+/// A `BUS` of the system, numbered from 1 in the order of `add_bus`.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct BusId(pub u64);
+
+/// A thread of the simulation, numbered from 1 in the order of creation.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct TaskId(pub u64);
+
+/// What a task does when it is scheduled.
+pub enum Step {
+    /// `duration(d)`: the task keeps its CPU for `d` time units.
+    Duration(u64),
+    /// `cycles(c)`: the task keeps its CPU for `c` cycles, so for a time
+    /// that depends on the speed of the CPU.
+    Cycles(u64),
+    /// The task leaves its CPU until the given time, as a periodic thread
+    /// between releases.
+    WaitUntil(u64),
+    /// The task leaves its CPU until the guard returns true, e.g. for a
+    /// call whose `sync` permission predicates do not hold yet. The guard
+    /// is checked at once, and again whenever another step has run, in
+    /// the order the tasks blocked. Once it holds, the task is ready again.
+    ///
+    /// For a call of `op` on an object with a `Monitor`, call
+    /// `monitor.request(op)` and block on `monitor.try_activate(op)`.
+    Blocked(Box<dyn FnMut() -> bool>),
+    /// An asynchronous call to an object on another CPU. The task carries
+    /// on, and the message runs as a new task when the bus delivers it.
+    Send(BusId, Message),
+    /// The task has ended.
+    Done,
+}
+
+impl fmt::Debug for Step {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Step::Duration(d) => write!(f, "Duration({})", d),
+            Step::Cycles(c) => write!(f, "Cycles({})", c),
+            Step::WaitUntil(t) => write!(f, "WaitUntil({})", t),
+            Step::Blocked(_) => write!(f, "Blocked"),
+            Step::Send(bus, ref msg) => write!(f, "Send({:?}, {:?})", bus, msg),
+            Step::Done => write!(f, "Done"),
+        }
+    }
+}
+
+/// A thread body, called with the current time each time it is scheduled.
+pub trait Task {
+    fn step(&mut self, now: u64) -> Step;
+}
+
+impl<F: FnMut(u64) -> Step> Task for F {
+    fn step(&mut self, now: u64) -> Step {
+        self(now)
+    }
+}
+
+/// A message sent over a `BUS`: the call of `op` to run on the CPU `to`,
+/// with `size` determining how long the transfer takes.
+pub struct Message {
+    pub to: CpuId,
+    pub op: &'static str,
+    pub size: u64,
+    pub priority: u32,
+    pub task: Box<dyn Task>,
+}
+
+impl fmt::Debug for Message {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Message")
+         .field("to", &self.to)
+         .field("op", &self.op)
+         .field("size", &self.size)
+         .field("priority", &self.priority)
+         .finish()
+    }
+}
+
+/// A periodic or sporadic thread: runs a new job from `job` at each of
+/// `releases`, e.g. the `ReleaseTimes` of a `concurrency::Release`. A
+/// release that falls while the previous job runs is started as soon as
+/// it ends.
+pub struct Periodic<I, F, T> {
+    releases: I,
+    job: F,
+    current: Option<T>,
+}
+
+impl<I, F, T> Periodic<I, F, T>
+    where I: Iterator<Item = u64>,
+          F: FnMut() -> T,
+          T: Task
+{
+    pub fn new(releases: I, job: F) -> Periodic<I, F, T> {
+        Periodic {
+            releases,
+            job,
+            current: None,
+        }
+    }
+}
+
+impl<I, F, T> Task for Periodic<I, F, T>
+    where I: Iterator<Item = u64>,
+          F: FnMut() -> T,
+          T: Task
+{
+    fn step(&mut self, now: u64) -> Step {
+        loop {
+            if let Some(ref mut job) = self.current {
+                match job.step(now) {
+                    Step::Done => {}
+                    step => return step,
+                }
+            }
+
+            self.current = None;
+
+            match self.releases.next() {
+                Some(time) => {
+                    self.current = Some((self.job)());
+
+                    if time > now {
+                        return Step::WaitUntil(time);
+                    }
+                }
+                None => return Step::Done,
+            }
+        }
+    }
+}
+
+struct Cpu {
+    name: String,
+    speed: u64,
+    policy: Policy,
+    ready: Vec<(u32, u64, TaskId)>,
+    running: Option<TaskId>,
+    /// The thread last swapped in, until it is swapped out.
+    current: Option<TaskId>,
+}
+
+struct Bus {
+    name: String,
+    speed: u64,
+    policy: Policy,
+    cpus: Vec<CpuId>,
+    queue: Vec<(u32, u64, u64, Message)>,
+    transfer: Option<(u64, Message)>,
+}
+
+struct TaskState {
+    cpu: CpuId,
+    priority: u32,
+    body: Box<dyn Task>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+enum Event {
+    Ready(TaskId),
+    StepDone(TaskId),
+    BusDone(BusId),
+}
+
+/// A deterministic discrete-event simulation of a VDM-RT `system`.
+///
+/// # Examples
+///
+/// ```
+/// use codegen_runtime::rt::{Policy, Simulator, Step};
+///
+/// let mut sim = Simulator::new();
+/// let cpu = sim.add_cpu("cpu1", 1_000_000, Policy::Fcfs);
+///
+/// // duration(5) skip
+/// let mut work = Some(Step::Duration(5));
+/// sim.spawn(cpu, 1, move |_| work.take().unwrap_or(Step::Done));
+///
+/// // cycles(2000) skip
+/// let mut work = Some(Step::Cycles(2000));
+/// sim.spawn(cpu, 1, move |_| work.take().unwrap_or(Step::Done));
+///
+/// assert_eq!(2_000_005, sim.run());
+/// ```
+pub struct Simulator {
+    clock: Arc<VirtualClock>,
+    cpus: Vec<Cpu>,
+    buses: Vec<Bus>,
+    tasks: Vec<Option<TaskState>>,
+    blocked: Vec<(TaskId, Box<dyn FnMut() -> bool>)>,
+    events: BinaryHeap<Reverse<(u64, u64, Event)>>,
+    seq: u64,
+    messages: u64,
+    log: Option<(String, RtLogger)>,
+    vbus_logged: Cell<bool>,
+}
+
+impl Simulator {
+    pub fn new() -> Simulator {
+        Simulator {
+            clock: Arc::new(VirtualClock::new()),
+            cpus: Vec::new(),
+            buses: Vec::new(),
+            tasks: Vec::new(),
+            blocked: Vec::new(),
+            events: BinaryHeap::new(),
+            seq: 0,
+            messages: 0,
+            log: None,
+            vbus_logged: Cell::new(false),
+        }
+    }
+
+    /// The clock of the simulation, for the VDM `time` expression.
+    pub fn clock(&self) -> Arc<VirtualClock> {
+        self.clock.clone()
+    }
+
+    pub fn time(&self) -> u64 {
+        self.clock.now()
+    }
+
+    /// `CPU(policy, speed)`, with `speed` in cycles per second.
+    pub fn add_cpu<S: Into<String>>(&mut self, name: S, speed: u64, policy: Policy) -> CpuId {
+        assert!(speed > 0, "CPU speed must be positive");
+
+        self.cpus.push(Cpu {
+            name: name.into(),
+            speed,
+            policy,
+            ready: Vec::new(),
+            running: None,
+            current: None,
+        });
+
+        let id = CpuId(self.cpus.len() as u64);
+        self.log_cpu(id);
+        id
+    }
+
+    /// `BUS(policy, speed, cpus)`, with `speed` in message size units per
+    /// second, or 0 for instantaneous transfers.
+    pub fn add_bus<S: Into<String>>(&mut self, name: S, speed: u64, policy: Policy, cpus: &[CpuId]) -> BusId {
+        for &cpu in cpus {
+            self.cpu(cpu);
+        }
+
+        self.buses.push(Bus {
+            name: name.into(),
+            speed,
+            policy,
+            cpus: cpus.to_vec(),
+            queue: Vec::new(),
+            transfer: None,
+        });
+
+        let id = BusId(self.buses.len() as u64);
+        self.log_bus(id);
+        id
+    }
+
+    pub fn cpu_name(&self, cpu: CpuId) -> &str {
+        &self.cpus[cpu.0 as usize - 1].name
+    }
+
+    pub fn bus_name(&self, bus: BusId) -> &str {
+        &self.buses[bus.0 as usize - 1].name
+    }
+
+    /// Writes the `.logrt` events of the simulation to `out`: the CPUs and
+    /// buses of `system`, threads being scheduled, and messages. Log the
+    /// events of the model itself, such as operation calls, with the
+    /// returned logger, which shares the output.
+    ///
+    /// As in Overture, the log also declares the virtual CPU 0 and the
+    /// virtual bus 0, which connects all CPUs. The virtual bus is declared
+    /// before the first other event of the simulation, so CPUs added later
+    /// are not on it.
+    pub fn start_log<W: Write + Send + 'static>(&mut self, system: &str, out: W) -> RtLogger {
+        let log = RtLogger::new(out, self.clock.clone());
+        self.log = Some((system.into(), log.clone()));
+        self.vbus_logged.set(false);
+
+        log.log(&RtEvent::CpuDecl { cpu: 0, explicit: false, system: "none", name: "vCPU 0" });
+
+        for index in 0..self.cpus.len() {
+            self.log_cpu(CpuId(index as u64 + 1));
+        }
+
+        for index in 0..self.buses.len() {
+            self.log_bus(BusId(index as u64 + 1));
+        }
+
+        log
+    }
+
+    /// Starts a thread on `cpu`, ready to run at the current time.
+    pub fn spawn<T: Task + 'static>(&mut self, cpu: CpuId, priority: u32, task: T) -> TaskId {
+        self.spawn_boxed(cpu, priority, Box::new(task))
+    }
+
+    /// Runs until no thread can run any more, and returns the time then.
+    /// Threads whose guard never holds are left blocked.
+    pub fn run(&mut self) -> u64 {
+        self.run_until(u64::MAX)
+    }
+
+    /// Runs until no thread can run before `limit`, and returns the time
+    /// then. Steps that end after `limit` are left pending.
+    pub fn run_until(&mut self, limit: u64) -> u64 {
+        loop {
+            self.dispatch();
+
+            let time = match self.events.peek() {
+                Some(&Reverse((time, _, _))) if time <= limit => time,
+                _ => break,
+            };
+
+            self.clock.advance_to(time);
+
+            while let Some(&Reverse((next, _, event))) = self.events.peek() {
+                if next != time {
+                    break;
+                }
+
+                self.events.pop();
+                self.handle(event);
+            }
+        }
+
+        self.time()
+    }
+
+    /// Whether there is nothing left to run. Blocked threads cannot run
+    /// until another thread does.
+    pub fn is_idle(&self) -> bool {
+        self.events.is_empty() && self.cpus.iter().all(|cpu| cpu.ready.is_empty())
+    }
+
+    fn spawn_boxed(&mut self, cpu: CpuId, priority: u32, body: Box<dyn Task>) -> TaskId {
+        self.cpu(cpu);
+        self.tasks.push(Some(TaskState { cpu, priority, body }));
+
+        let id = TaskId(self.tasks.len() as u64);
+        self.log(&RtEvent::ThreadCreate { thread: id.0, periodic: false, obj: None, cpu: cpu.0 });
+        self.make_ready(id);
+        id
+    }
+
+    fn log(&self, event: &RtEvent) {
+        if let Some((_, ref log)) = self.log {
+            if !self.vbus_logged.replace(true) {
+                let topology: Vec<u64> = (0..=self.cpus.len() as u64).collect();
+                log.log(&RtEvent::BusDecl { bus: 0, topology: &topology, name: "vBUS" });
+            }
+
+            log.log(event);
+        }
+    }
+
+    fn log_cpu(&self, cpu: CpuId) {
+        if let Some((ref system, ref log)) = self.log {
+            log.log(&RtEvent::CpuDecl { cpu: cpu.0, explicit: true, system, name: &self.cpus[cpu.0 as usize - 1].name });
+        }
+    }
+
+    fn log_bus(&self, bus: BusId) {
+        if let Some((_, ref log)) = self.log {
+            let state = &self.buses[bus.0 as usize - 1];
+            let topology: Vec<u64> = state.cpus.iter().map(|cpu| cpu.0).collect();
+            log.log(&RtEvent::BusDecl { bus: bus.0, topology: &topology, name: &state.name });
+        }
+    }
+
+    /// Gives `cpu` to `task`. A thread keeps the CPU between its steps, so
+    /// swaps are only logged when the CPU changes threads.
+    fn swap_in(&mut self, cpu: CpuId, task: TaskId) {
+        if self.cpu(cpu).current == Some(task) {
+            return;
+        }
+
+        self.swap_out(cpu);
+        self.cpu(cpu).current = Some(task);
+        self.log(&RtEvent::ThreadSwapIn { thread: task.0, obj: None, cpu: cpu.0, overhead: 0 });
+    }
+
+    fn swap_out(&mut self, cpu: CpuId) {
+        if let Some(task) = self.cpu(cpu).current.take() {
+            self.log(&RtEvent::ThreadSwapOut { thread: task.0, obj: None, cpu: cpu.0, overhead: 0 });
+        }
+    }
+
+    fn cpu(&mut self, cpu: CpuId) -> &mut Cpu {
+        match self.cpus.get_mut((cpu.0 as usize).wrapping_sub(1)) {
+            Some(state) => state,
+            None => panic!("No such CPU: {}", cpu.0),
+        }
+    }
+
+    fn task(&mut self, task: TaskId) -> &mut TaskState {
+        self.tasks[task.0 as usize - 1].as_mut().expect("task has ended")
+    }
+
+    fn schedule(&mut self, time: u64, event: Event) {
+        self.seq += 1;
+        self.events.push(Reverse((time, self.seq, event)));
+    }
+
+    fn make_ready(&mut self, task: TaskId) {
+        let (cpu, priority) = {
+            let state = self.task(task);
+            (state.cpu, state.priority)
+        };
+
+        self.seq += 1;
+        let seq = self.seq;
+        self.cpu(cpu).ready.push((priority, seq, task));
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Ready(task) => self.make_ready(task),
+            Event::StepDone(task) => {
+                let cpu = self.task(task).cpu;
+                self.cpu(cpu).running = None;
+                self.make_ready(task);
+            }
+            Event::BusDone(bus) => {
+                let (id, msg) = self.buses[bus.0 as usize - 1].transfer.take().expect("no message in transfer");
+                self.log(&RtEvent::MessageCompleted { msg: id });
+                self.spawn_boxed(msg.to, msg.priority, msg.task);
+            }
+        }
+    }
+
+    /// Starts steps on all idle CPUs and transfers on all idle buses.
+    fn dispatch(&mut self) {
+        let now = self.time();
+        let mut progress = true;
+
+        while progress {
+            progress = self.unblock();
+
+            for index in 0..self.cpus.len() {
+                let cpu = CpuId(index as u64 + 1);
+
+                while self.cpus[index].running.is_none() {
+                    let task = match pick(self.cpus[index].policy, &mut self.cpus[index].ready) {
+                        Some(task) => task,
+                        None => break,
+                    };
+
+                    progress = true;
+                    self.run_step(cpu, task, now);
+                }
+            }
+
+            for index in 0..self.buses.len() {
+                let bus = &mut self.buses[index];
+
+                if bus.transfer.is_none() && !bus.queue.is_empty() {
+                    let next = select(bus.policy, bus.queue.iter().map(|&(p, s, _, _)| (p, s)));
+                    let (_, _, id, msg) = bus.queue.remove(next);
+                    let duration = transfer_time(msg.size, bus.speed);
+                    bus.transfer = Some((id, msg));
+
+                    self.log(&RtEvent::MessageActivate { msg: id });
+                    self.schedule(now.saturating_add(duration), Event::BusDone(BusId(index as u64 + 1)));
+                }
+            }
+        }
+    }
+
+    /// Makes the blocked tasks whose guard holds ready, and returns whether
+    /// there were any.
+    fn unblock(&mut self) -> bool {
+        let mut released = Vec::new();
+
+        self.blocked.retain_mut(|&mut (task, ref mut guard)| {
+            if guard() {
+                released.push(task);
+                false
+            } else {
+                true
+            }
+        });
+
+        for &task in &released {
+            self.make_ready(task);
+        }
+        !released.is_empty()
+    }
+
+    fn run_step(&mut self, cpu: CpuId, task: TaskId, now: u64) {
+        self.swap_in(cpu, task);
+        let step = self.task(task).body.step(now);
+
+        match step {
+            Step::Duration(duration) => self.occupy(cpu, task, now.saturating_add(duration)),
+            Step::Cycles(cycles) => {
+                let speed = self.cpu(cpu).speed;
+                let duration = ((cycles as u128 * UNITS_PER_SECOND as u128).div_ceil(speed as u128)) as u64;
+                self.occupy(cpu, task, now.saturating_add(duration));
+            }
+            Step::WaitUntil(time) => {
+                self.swap_out(cpu);
+                self.schedule(time.max(now), Event::Ready(task));
+            }
+            Step::Blocked(guard) => {
+                self.swap_out(cpu);
+                self.blocked.push((task, guard));
+            }
+            Step::Send(bus, msg) => {
+                self.send(cpu, bus, task, msg);
+                self.make_ready(task);
+            }
+            Step::Done => {
+                self.log(&RtEvent::ThreadKill { thread: task.0, cpu: cpu.0 });
+                self.cpu(cpu).current = None;
+                self.tasks[task.0 as usize - 1] = None;
+            }
+        }
+    }
+
+    fn occupy(&mut self, cpu: CpuId, task: TaskId, until: u64) {
+        self.cpu(cpu).running = Some(task);
+        self.schedule(until, Event::StepDone(task));
+    }
+
+    fn send(&mut self, from: CpuId, bus: BusId, task: TaskId, msg: Message) {
+        self.cpu(msg.to);
+        self.seq += 1;
+        self.messages += 1;
+        let (seq, id) = (self.seq, self.messages);
+
+        let state = match self.buses.get_mut((bus.0 as usize).wrapping_sub(1)) {
+            Some(state) => state,
+            None => panic!("No such BUS: {}", bus.0),
+        };
+
+        if !state.cpus.contains(&from) || !state.cpus.contains(&msg.to) {
+            panic!("BUS {} does not connect CPU {} and CPU {}", state.name, from.0, msg.to.0);
+        }
+
+        if let Some((_, ref log)) = self.log {
+            log.log(&RtEvent::MessageRequest {
+                bus: bus.0,
+                from_cpu: from.0,
+                to_cpu: msg.to.0,
+                msg: id,
+                thread: task.0,
+                op: msg.op,
+                obj: None,
+                size: msg.size,
+            });
+        }
+
+        state.queue.push((msg.priority, seq, id, msg));
+    }
+}
+
+impl Default for Simulator {
+    fn default() -> Simulator {
+        Simulator::new()
+    }
+}
+
+/// The index of the next of `entries`, given as `(priority, arrival)`.
+fn select<I: Iterator<Item = (u32, u64)>>(policy: Policy, entries: I) -> usize {
+    let keyed = entries.enumerate().map(|(i, (priority, seq))| match policy {
+        Policy::Fcfs => (0, seq, i),
+        Policy::FixedPriority => (u32::MAX - priority, seq, i),
+    });
+
+    keyed.min().map(|(_, _, i)| i).unwrap_or(0)
+}
+
+fn pick(policy: Policy, ready: &mut Vec<(u32, u64, TaskId)>) -> Option<TaskId> {
+    if ready.is_empty() {
+        return None;
+    }
+
+    let next = select(policy, ready.iter().map(|&(p, s, _)| (p, s)));
+    Some(ready.remove(next).2)
+}
+
+fn transfer_time(size: u64, speed: u64) -> u64 {
+    if speed == 0 {
+        0
+    } else {
+        (size as u128 * UNITS_PER_SECOND as u128).div_ceil(speed as u128) as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use concurrency::{Monitor, Release};
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    type Trace = Rc<RefCell<Vec<(&'static str, u64)>>>;
+
+    /// A task that takes `steps` durations of `d`, recording its start times.
+    fn worker(name: &'static str, steps: u32, d: u64, trace: &Trace) -> impl Task {
+        let trace = trace.clone();
+        let mut left = steps;
+
+        move |now| {
+            if left == 0 {
+                return Step::Done;
+            }
+
+            left -= 1;
+            trace.borrow_mut().push((name, now));
+            Step::Duration(d)
+        }
+    }
+
+    #[test]
+    fn virtual_clock() {
+        let clock = VirtualClock::new();
+        clock.advance(10);
+        clock.wait_until(25);
+        clock.advance_to(5);
+
+        assert_eq!(25, clock.now());
+    }
+
+    #[test]
+    fn fcfs_interleaves() {
+        let trace = Trace::default();
+        let mut sim = Simulator::new();
+        let cpu = sim.add_cpu("cpu", 1_000, Policy::Fcfs);
+
+        sim.spawn(cpu, 1, worker("a", 2, 10, &trace));
+        sim.spawn(cpu, 9, worker("b", 2, 5, &trace));
+
+        assert_eq!(30, sim.run());
+        assert_eq!(vec![("a", 0), ("b", 10), ("a", 15), ("b", 25)], *trace.borrow());
+        assert!(sim.is_idle());
+    }
+
+    #[test]
+    fn fixed_priority_prefers_high() {
+        let trace = Trace::default();
+        let mut sim = Simulator::new();
+        let cpu = sim.add_cpu("cpu", 1_000, Policy::FixedPriority);
+
+        sim.spawn(cpu, 1, worker("low", 2, 10, &trace));
+        sim.spawn(cpu, 9, worker("high", 2, 5, &trace));
+
+        sim.run();
+        assert_eq!(vec![("high", 0), ("high", 5), ("low", 10), ("low", 20)], *trace.borrow());
+    }
+
+    #[test]
+    fn cpus_run_in_parallel() {
+        let trace = Trace::default();
+        let mut sim = Simulator::new();
+        let cpu1 = sim.add_cpu("cpu1", 1_000_000, Policy::Fcfs);
+        let cpu2 = sim.add_cpu("cpu2", 2_000_000, Policy::Fcfs);
+
+        sim.spawn(cpu1, 1, |_| Step::Cycles(1));
+        let mut cycles = Some(Step::Cycles(3));
+        sim.spawn(cpu2, 1, move |_| cycles.take().unwrap_or(Step::Done));
+        sim.spawn(cpu2, 1, worker("b", 1, 1, &trace));
+
+        assert_eq!(1500, sim.run_until(1500));
+        assert_eq!(vec![("b", 1500)], *trace.borrow());
+        assert_eq!("cpu2", sim.cpu_name(cpu2));
+    }
+
+    #[test]
+    fn messages_cross_the_bus() {
+        let trace = Trace::default();
+        let mut sim = Simulator::new();
+        let cpu1 = sim.add_cpu("cpu1", 1_000, Policy::Fcfs);
+        let cpu2 = sim.add_cpu("cpu2", 1_000, Policy::Fcfs);
+        let bus = sim.add_bus("bus", 100_000_000, Policy::Fcfs, &[cpu1, cpu2]);
+
+        let mut sent = 0;
+        let t = trace.clone();
+        sim.spawn(cpu1, 1, move |_| {
+            sent += 1;
+            match sent {
+                1 | 2 => Step::Send(bus, Message {
+                    to: cpu2,
+                    op: "B`op",
+                    size: 10,
+                    priority: 1,
+                    task: Box::new(worker(if sent == 1 { "m1" } else { "m2" }, 1, 50, &t)),
+                }),
+                _ => Step::Done,
+            }
+        });
+
+        assert_eq!(250, sim.run());
+        assert_eq!(vec![("m1", 100), ("m2", 200)], *trace.borrow());
+    }
+
+    #[test]
+    fn periodic_task() {
+        let trace = Trace::default();
+        let mut sim = Simulator::new();
+        let cpu = sim.add_cpu("cpu", 1_000, Policy::Fcfs);
+        let release = Release::Periodic { period: 100, jitter: 0, delay: 0, offset: 10 };
+
+        let t = trace.clone();
+        sim.spawn(cpu, 1, Periodic::new(release.times(0).take(3), move || worker("job", 1, 120, &t)));
+
+        assert_eq!(370, sim.run());
+        assert_eq!(vec![("job", 10), ("job", 130), ("job", 250)], *trace.borrow());
+    }
+
+    #[test]
+    fn reproducible() {
+        let run = || {
+            let trace = Trace::default();
+            let mut sim = Simulator::new();
+            let cpu = sim.add_cpu("cpu", 1_000, Policy::FixedPriority);
+            let release = Release::Sporadic { min_delay: 5, max_delay: 50, offset: 0 };
+
+            for i in 0..3 {
+                let t = trace.clone();
+                sim.spawn(cpu, i, Periodic::new(release.times(i as i64).take(20),
+                                                move || worker("job", 1, 7, &t)));
+            }
+
+            sim.run();
+            let result = trace.borrow().clone();
+            result
+        };
+
+        assert_eq!(run(), run());
+    }
+
+    #[test]
+    fn sync_guards_block_in_virtual_time() {
+        let trace = Trace::default();
+        let mut sim = Simulator::new();
+        let cpu1 = sim.add_cpu("cpu1", 1_000, Policy::Fcfs);
+        let cpu2 = sim.add_cpu("cpu2", 1_000, Policy::Fcfs);
+        let monitor = Arc::new(Monitor::new().per("Get", |h| h.fin("Put") > h.fin("Get")));
+
+        // Calls Get, which takes duration(5).
+        let (m, t) = (monitor.clone(), trace.clone());
+        let mut state = 0;
+        sim.spawn(cpu1, 1, move |now| {
+            state += 1;
+            match state {
+                1 => {
+                    m.request("Get");
+                    let m = m.clone();
+                    Step::Blocked(Box::new(move || m.try_activate("Get")))
+                }
+                2 => {
+                    t.borrow_mut().push(("get", now));
+                    Step::Duration(5)
+                }
+                3 => {
+                    m.finish("Get");
+                    Step::Done
+                }
+                _ => unreachable!(),
+            }
+        });
+
+        // Calls Put, which takes duration(20).
+        let (m, t) = (monitor.clone(), trace.clone());
+        let mut state = 0;
+        sim.spawn(cpu2, 1, move |now| {
+            state += 1;
+            match state {
+                1 => {
+                    assert!(m.try_activate("Put"));
+                    t.borrow_mut().push(("put", now));
+                    Step::Duration(20)
+                }
+                _ => {
+                    m.finish("Put");
+                    Step::Done
+                }
+            }
+        });
+
+        assert_eq!(25, sim.run());
+        assert_eq!(vec![("put", 0), ("get", 20)], *trace.borrow());
+        assert_eq!(1, monitor.history().fin("Get"));
+    }
+
+    #[test]
+    fn never_permitted_is_left_blocked() {
+        let mut sim = Simulator::new();
+        let cpu = sim.add_cpu("cpu", 1_000, Policy::Fcfs);
+
+        sim.spawn(cpu, 1, |_| Step::Blocked(Box::new(|| false)));
+        sim.spawn(cpu, 1, worker("w", 1, 10, &Trace::default()));
+
+        assert_eq!(10, sim.run());
+        assert!(sim.is_idle());
+    }
+
+    #[test]
+    #[should_panic(expected = "does not connect")]
+    fn unconnected_bus() {
+        let mut sim = Simulator::new();
+        let cpu1 = sim.add_cpu("cpu1", 1_000, Policy::Fcfs);
+        let cpu2 = sim.add_cpu("cpu2", 1_000, Policy::Fcfs);
+        let bus = sim.add_bus("bus", 0, Policy::Fcfs, &[cpu1]);
+
+        sim.spawn(cpu1, 1, move |_| Step::Send(bus, Message {
+            to: cpu2,
+            op: "B`op",
+            size: 1,
+            priority: 1,
+            task: Box::new(|_| Step::Done),
+        }));
+        sim.run();
+    }
+}
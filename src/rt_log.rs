@@ -0,0 +1,358 @@
+//! Event log of VDM-RT executions, in the `.logrt` format of the Overture
+//! interpreter, so that its RT log viewer can show runs of generated code.
+//!
+//! Each event is one line: the event name, `->`, its fields as
+//! `name: value` pairs, and the time, e.g.
+//!
+//! ```text
+//! OpRequest -> id: 3 opname: "Sensor`read" objref: 5 clnm: "Sensor" cpunm: 1 async: false time: 120
+//! ```
+
+use lib::fmt;
+use lib::{Box, ToString, Vec};
+use lib::sync::Mutex;
+use concurrency::Clock;
+use std::io::{self, Write};
+use std::sync::Arc;
+
+/// An object in the log: its reference number and class name.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct LogObj<'a> {
+    pub id: u64,
+    pub class: &'a str,
+}
+
+/// One event of a VDM-RT execution. Thread, CPU, bus and message numbers
+/// are those of `rt::Simulator` when running under it; CPU and bus 0 are
+/// the virtual ones.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RtEvent<'a> {
+    CpuDecl { cpu: u64, explicit: bool, system: &'a str, name: &'a str },
+    BusDecl { bus: u64, topology: &'a [u64], name: &'a str },
+    DeployObj { obj: LogObj<'a>, cpu: u64 },
+    ThreadCreate { thread: u64, periodic: bool, obj: Option<LogObj<'a>>, cpu: u64 },
+    ThreadSwapIn { thread: u64, obj: Option<LogObj<'a>>, cpu: u64, overhead: u64 },
+    DelayedThreadSwapIn { thread: u64, obj: Option<LogObj<'a>>, delay: u64, cpu: u64, overhead: u64 },
+    ThreadSwapOut { thread: u64, obj: Option<LogObj<'a>>, cpu: u64, overhead: u64 },
+    ThreadKill { thread: u64, cpu: u64 },
+    OpRequest { thread: u64, op: &'a str, obj: Option<LogObj<'a>>, cpu: u64, asynchronous: bool },
+    OpActivate { thread: u64, op: &'a str, obj: Option<LogObj<'a>>, cpu: u64, asynchronous: bool },
+    OpCompleted { thread: u64, op: &'a str, obj: Option<LogObj<'a>>, cpu: u64, asynchronous: bool },
+    MessageRequest {
+        bus: u64,
+        from_cpu: u64,
+        to_cpu: u64,
+        msg: u64,
+        thread: u64,
+        op: &'a str,
+        obj: Option<LogObj<'a>>,
+        size: u64,
+    },
+    ReplyRequest {
+        bus: u64,
+        from_cpu: u64,
+        to_cpu: u64,
+        msg: u64,
+        orig_msg: u64,
+        caller: u64,
+        callee: u64,
+        size: u64,
+    },
+    MessageActivate { msg: u64 },
+    MessageCompleted { msg: u64 },
+}
+
+struct ObjFields<'a>(Option<LogObj<'a>>);
+
+impl<'a> fmt::Display for ObjFields<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(obj) => write!(f, "objref: {} clnm: \"{}\"", obj.id, obj.class),
+            None => write!(f, "objref: nil clnm: nil"),
+        }
+    }
+}
+
+/// The line of the event, without the time.
+impl<'a> fmt::Display for RtEvent<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RtEvent::CpuDecl { cpu, explicit, system, name } => {
+                write!(f, "CPUdecl -> id: {} expl: {} sys: \"{}\" name: \"{}\"", cpu, explicit, system, name)
+            }
+            RtEvent::BusDecl { bus, topology, name } => {
+                let topology: Vec<_> = topology.iter().map(|cpu| cpu.to_string()).collect();
+                write!(f, "BUSdecl -> id: {} topo: {{{}}} name: \"{}\"", bus, topology.join(","), name)
+            }
+            RtEvent::DeployObj { obj, cpu } => {
+                write!(f, "DeployObj -> objref: {} clnm: \"{}\" cpunm: {}", obj.id, obj.class, cpu)
+            }
+            RtEvent::ThreadCreate { thread, periodic, obj, cpu } => {
+                write!(f, "ThreadCreate -> id: {} period: {} {} cpunm: {}", thread, periodic, ObjFields(obj), cpu)
+            }
+            RtEvent::ThreadSwapIn { thread, obj, cpu, overhead } => {
+                write!(f, "ThreadSwapIn -> id: {} {} cpunm: {} overhead: {}", thread, ObjFields(obj), cpu, overhead)
+            }
+            RtEvent::DelayedThreadSwapIn { thread, obj, delay, cpu, overhead } => {
+                write!(f, "DelayedThreadSwapIn -> id: {} {} delay: {} cpunm: {} overhead: {}",
+                       thread, ObjFields(obj), delay, cpu, overhead)
+            }
+            RtEvent::ThreadSwapOut { thread, obj, cpu, overhead } => {
+                write!(f, "ThreadSwapOut -> id: {} {} cpunm: {} overhead: {}", thread, ObjFields(obj), cpu, overhead)
+            }
+            RtEvent::ThreadKill { thread, cpu } => write!(f, "ThreadKill -> id: {} cpunm: {}", thread, cpu),
+            RtEvent::OpRequest { thread, op, obj, cpu, asynchronous } => {
+                write!(f, "OpRequest -> id: {} opname: \"{}\" {} cpunm: {} async: {}",
+                       thread, op, ObjFields(obj), cpu, asynchronous)
+            }
+            RtEvent::OpActivate { thread, op, obj, cpu, asynchronous } => {
+                write!(f, "OpActivate -> id: {} opname: \"{}\" {} cpunm: {} async: {}",
+                       thread, op, ObjFields(obj), cpu, asynchronous)
+            }
+            RtEvent::OpCompleted { thread, op, obj, cpu, asynchronous } => {
+                write!(f, "OpCompleted -> id: {} opname: \"{}\" {} cpunm: {} async: {}",
+                       thread, op, ObjFields(obj), cpu, asynchronous)
+            }
+            RtEvent::MessageRequest { bus, from_cpu, to_cpu, msg, thread, op, obj, size } => {
+                let objref = match obj {
+                    Some(obj) => obj.id.to_string(),
+                    None => "nil".to_string(),
+                };
+
+                write!(f, "MessageRequest -> busid: {} fromcpu: {} tocpu: {} msgid: {} callthr: {} opname: \"{}\" objref: {} size: {}",
+                       bus, from_cpu, to_cpu, msg, thread, op, objref, size)
+            }
+            RtEvent::ReplyRequest { bus, from_cpu, to_cpu, msg, orig_msg, caller, callee, size } => {
+                write!(f, "ReplyRequest -> busid: {} fromcpu: {} tocpu: {} msgid: {} origmsgid: {} callthr: {} calleethr: {} size: {}",
+                       bus, from_cpu, to_cpu, msg, orig_msg, caller, callee, size)
+            }
+            RtEvent::MessageActivate { msg } => write!(f, "MessageActivate -> msgid: {}", msg),
+            RtEvent::MessageCompleted { msg } => write!(f, "MessageCompleted -> msgid: {}", msg),
+        }
+    }
+}
+
+struct Sink {
+    out: Box<dyn Write + Send>,
+    error: Option<io::Error>,
+}
+
+/// Writes `RtEvent`s with the time of a clock, normally the `VirtualClock`
+/// of the simulation. Clones write to the same output, so that each
+/// object of a model can hold one.
+///
+/// Logging never interrupts the model: the first write error is kept, and
+/// reported by `flush`, and later events are dropped.
+///
+/// # Examples
+///
+/// ```
+/// use std::sync::Arc;
+/// use codegen_runtime::rt::VirtualClock;
+/// use codegen_runtime::rt_log::{RtEvent, RtLogger};
+///
+/// let clock = Arc::new(VirtualClock::new());
+/// let log = RtLogger::new(std::io::sink(), clock.clone());
+///
+/// clock.advance(20);
+/// log.log(&RtEvent::ThreadKill { thread: 3, cpu: 1 });
+///
+/// assert!(log.flush().is_ok());
+/// ```
+#[derive(Clone)]
+pub struct RtLogger {
+    sink: Arc<Mutex<Sink>>,
+    clock: Arc<dyn Clock>,
+}
+
+impl RtLogger {
+    pub fn new<W, C>(out: W, clock: Arc<C>) -> RtLogger
+        where W: Write + Send + 'static,
+              C: Clock + 'static
+    {
+        RtLogger {
+            sink: Arc::new(Mutex::new(Sink {
+                out: Box::new(out),
+                error: None,
+            })),
+            clock,
+        }
+    }
+
+    /// Writes `event` with the current time.
+    pub fn log(&self, event: &RtEvent) {
+        let time = self.clock.now();
+        let mut sink = self.sink.lock();
+
+        if sink.error.is_none() {
+            if let Err(err) = writeln!(sink.out, "{} time: {}", event, time) {
+                sink.error = Some(err);
+            }
+        }
+    }
+
+    /// Flushes the output, and returns the first error writing to it.
+    pub fn flush(&self) -> io::Result<()> {
+        let mut sink = self.sink.lock();
+
+        match sink.error.take() {
+            Some(err) => Err(err),
+            None => sink.out.flush(),
+        }
+    }
+}
+
+impl fmt::Debug for RtLogger {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RtLogger").field("time", &self.clock.now()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rt::{Message, Policy, Simulator, Step, VirtualClock};
+
+    /// A shared buffer, to read back what was logged.
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Buffer {
+        fn lines(&self) -> Vec<String> {
+            String::from_utf8(self.0.lock().clone()).unwrap().lines().map(String::from).collect()
+        }
+    }
+
+    impl Write for Buffer {
+        fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+            self.0.lock().extend_from_slice(bytes);
+            Ok(bytes.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn overture_format() {
+        let buffer = Buffer::default();
+        let clock = Arc::new(VirtualClock::new());
+        let log = RtLogger::new(buffer.clone(), clock.clone());
+        let sensor = Some(LogObj { id: 5, class: "Sensor" });
+
+        log.log(&RtEvent::CpuDecl { cpu: 1, explicit: true, system: "Sys", name: "cpu1" });
+        log.log(&RtEvent::BusDecl { bus: 1, topology: &[1, 2], name: "bus1" });
+        clock.advance(120);
+        log.log(&RtEvent::OpRequest { thread: 3, op: "Sensor`read", obj: sensor, cpu: 1, asynchronous: false });
+        log.clone().log(&RtEvent::ThreadSwapOut { thread: 3, obj: None, cpu: 1, overhead: 0 });
+        log.log(&RtEvent::MessageRequest {
+            bus: 1,
+            from_cpu: 1,
+            to_cpu: 2,
+            msg: 7,
+            thread: 3,
+            op: "Actuator`set",
+            obj: Some(LogObj { id: 6, class: "Actuator" }),
+            size: 64,
+        });
+        log.log(&RtEvent::MessageCompleted { msg: 7 });
+
+        assert_eq!(vec![
+            "CPUdecl -> id: 1 expl: true sys: \"Sys\" name: \"cpu1\" time: 0",
+            "BUSdecl -> id: 1 topo: {1,2} name: \"bus1\" time: 0",
+            "OpRequest -> id: 3 opname: \"Sensor`read\" objref: 5 clnm: \"Sensor\" cpunm: 1 async: false time: 120",
+            "ThreadSwapOut -> id: 3 objref: nil clnm: nil cpunm: 1 overhead: 0 time: 120",
+            "MessageRequest -> busid: 1 fromcpu: 1 tocpu: 2 msgid: 7 callthr: 3 opname: \"Actuator`set\" objref: 6 size: 64 time: 120",
+            "MessageCompleted -> msgid: 7 time: 120",
+        ], buffer.lines());
+    }
+
+    #[test]
+    fn simulator_events() {
+        let buffer = Buffer::default();
+        let mut sim = Simulator::new();
+        let cpu1 = sim.add_cpu("cpu1", 1_000, Policy::Fcfs);
+        let log = sim.start_log("Sys", buffer.clone());
+        let cpu2 = sim.add_cpu("cpu2", 1_000, Policy::Fcfs);
+        let bus = sim.add_bus("bus1", 0, Policy::Fcfs, &[cpu1, cpu2]);
+
+        let mut steps = vec![Step::Duration(10), Step::Send(bus, Message {
+            to: cpu2,
+            op: "B`op",
+            size: 8,
+            priority: 1,
+            task: Box::new(|_| Step::Done),
+        })].into_iter();
+        sim.spawn(cpu1, 1, move |_| {
+            log.log(&RtEvent::OpActivate { thread: 1, op: "A`op", obj: None, cpu: 1, asynchronous: false });
+            steps.next().unwrap_or(Step::Done)
+        });
+        sim.run();
+
+        assert_eq!(vec![
+            "CPUdecl -> id: 0 expl: false sys: \"none\" name: \"vCPU 0\" time: 0",
+            "CPUdecl -> id: 1 expl: true sys: \"Sys\" name: \"cpu1\" time: 0",
+            "CPUdecl -> id: 2 expl: true sys: \"Sys\" name: \"cpu2\" time: 0",
+            "BUSdecl -> id: 1 topo: {1,2} name: \"bus1\" time: 0",
+            "BUSdecl -> id: 0 topo: {0,1,2} name: \"vBUS\" time: 0",
+            "ThreadCreate -> id: 1 period: false objref: nil clnm: nil cpunm: 1 time: 0",
+            "ThreadSwapIn -> id: 1 objref: nil clnm: nil cpunm: 1 overhead: 0 time: 0",
+            "OpActivate -> id: 1 opname: \"A`op\" objref: nil clnm: nil cpunm: 1 async: false time: 0",
+            "OpActivate -> id: 1 opname: \"A`op\" objref: nil clnm: nil cpunm: 1 async: false time: 10",
+            "MessageRequest -> busid: 1 fromcpu: 1 tocpu: 2 msgid: 1 callthr: 1 opname: \"B`op\" objref: nil size: 8 time: 10",
+            "OpActivate -> id: 1 opname: \"A`op\" objref: nil clnm: nil cpunm: 1 async: false time: 10",
+            "ThreadKill -> id: 1 cpunm: 1 time: 10",
+            "MessageActivate -> msgid: 1 time: 10",
+            "MessageCompleted -> msgid: 1 time: 10",
+            "ThreadCreate -> id: 2 period: false objref: nil clnm: nil cpunm: 2 time: 10",
+            "ThreadSwapIn -> id: 2 objref: nil clnm: nil cpunm: 2 overhead: 0 time: 10",
+            "ThreadKill -> id: 2 cpunm: 2 time: 10",
+        ], buffer.lines());
+    }
+
+    #[test]
+    fn swaps_on_thread_change() {
+        let buffer = Buffer::default();
+        let mut sim = Simulator::new();
+        let cpu = sim.add_cpu("cpu1", 1_000, Policy::FixedPriority);
+        sim.start_log("Sys", buffer.clone());
+
+        let mut steps = vec![Step::Duration(10), Step::Duration(10)].into_iter();
+        sim.spawn(cpu, 1, move |_| steps.next().unwrap_or(Step::Done));
+        let mut steps = vec![Step::WaitUntil(5), Step::Duration(10), Step::Duration(10)].into_iter();
+        sim.spawn(cpu, 9, move |_| steps.next().unwrap_or(Step::Done));
+        sim.run();
+
+        let swaps: Vec<_> = buffer.lines().into_iter().filter(|line| line.starts_with("ThreadSwap")).collect();
+        assert_eq!(vec![
+            "ThreadSwapIn -> id: 2 objref: nil clnm: nil cpunm: 1 overhead: 0 time: 0",
+            "ThreadSwapOut -> id: 2 objref: nil clnm: nil cpunm: 1 overhead: 0 time: 0",
+            "ThreadSwapIn -> id: 1 objref: nil clnm: nil cpunm: 1 overhead: 0 time: 0",
+            "ThreadSwapOut -> id: 1 objref: nil clnm: nil cpunm: 1 overhead: 0 time: 10",
+            "ThreadSwapIn -> id: 2 objref: nil clnm: nil cpunm: 1 overhead: 0 time: 10",
+            "ThreadSwapIn -> id: 1 objref: nil clnm: nil cpunm: 1 overhead: 0 time: 30",
+        ], swaps);
+    }
+
+    #[test]
+    fn keeps_first_error() {
+        struct Broken;
+
+        impl Write for Broken {
+            fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+                Err(io::Error::other("disk full"))
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let log = RtLogger::new(Broken, Arc::new(VirtualClock::new()));
+        log.log(&RtEvent::MessageActivate { msg: 1 });
+
+        assert_eq!("disk full", log.flush().unwrap_err().to_string());
+        assert!(log.flush().is_ok());
+    }
+}
@@ -1,43 +1,110 @@
-use std::{cmp, fmt};
-use std::hash::Hash;
-use std::ops::{Deref, DerefMut};
-use std::iter::{Iterator, FromIterator, IntoIterator};
-use std::slice::Iter;
+use lib::{cmp, fmt};
+use lib::hash::{Hash, Hasher};
+use lib::iter::{Iterator, DoubleEndedIterator, Extend, FromIterator, IntoIterator};
+#[cfg(not(feature = "persistent"))]
+use lib::slice;
+use lib::{String, ToOwned, Vec};
+#[cfg(feature = "persistent")]
+use im::{vector, Vector};
 use Set;
 use Map;
-
-#[derive(Clone, PartialEq, Eq, Hash)]
+use VdmError;
+use error::unwrap_vdm;
+
+/// Sequence backed by a `Vec`, or by the persistent RRB vector
+/// `im::Vector` with the `persistent` feature, where `tail`, `conc`,
+/// `sub_seq` and updates share structure instead of copying. The backing
+/// is private, so the API is the same with either.
+///
+/// Ordered lexicographically, for use as element of ordered collections.
 pub struct Seq<T: Hash> {
-    inner: Vec<T>,
+    inner: Backing<T>,
+}
+
+#[cfg(not(feature = "persistent"))]
+type Backing<T> = Vec<T>;
+#[cfg(feature = "persistent")]
+type Backing<T> = Vector<T>;
+
+#[cfg(not(feature = "persistent"))]
+type BackingIter<'a, T> = slice::Iter<'a, T>;
+#[cfg(feature = "persistent")]
+type BackingIter<'a, T> = vector::Iter<'a, T>;
+
+wrap_iter! {
+    /// Iterator over the elements of a `Seq`, in order.
+    Iter<'a, T>(BackingIter<'a, T>) -> &'a T where T: Clone
+}
+
+impl<'a, T: Clone> DoubleEndedIterator for Iter<'a, T> {
+    fn next_back(&mut self) -> Option<&'a T> {
+        self.0.next_back()
+    }
 }
 
 impl<T: Clone + Hash> Seq<T> {
     pub fn new() -> Seq<T> {
-        Seq { inner: Vec::new() }
+        Seq { inner: Backing::new() }
     }
 
     pub fn len(&self) -> u64 {
         self.inner.len() as u64
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter(self.inner.iter())
+    }
+
     pub fn get_ref(&mut self, index: u64) -> &mut T {
-        &mut self.inner[(index - 1) as usize]
+        unwrap_vdm(self.try_get_ref(index))
+    }
+
+    pub fn try_get_ref(&mut self, index: u64) -> Result<&mut T, VdmError> {
+        let idx = self.check_index(index)?;
+        Ok(&mut self.inner[idx])
     }
 
     pub fn get(&self, index: u64) -> T {
-        self.inner[(index - 1) as usize].clone()
+        unwrap_vdm(self.try_get(index))
+    }
+
+    pub fn try_get(&self, index: u64) -> Result<T, VdmError> {
+        let idx = self.check_index(index)?;
+        Ok(self.inner[idx].clone())
     }
 
     pub fn put(&mut self, index: u64, value: T) {
-        self.inner[(index - 1) as usize] = value;
+        unwrap_vdm(self.try_put(index, value))
+    }
+
+    pub fn try_put(&mut self, index: u64, value: T) -> Result<(), VdmError> {
+        let idx = self.check_index(index)?;
+        self.inner[idx] = value;
+        Ok(())
     }
 
     pub fn head(&self) -> T {
-        self.get(1)
+        unwrap_vdm(self.try_head())
+    }
+
+    pub fn try_head(&self) -> Result<T, VdmError> {
+        self.first_elem().cloned().ok_or(VdmError::EmptySequence)
     }
 
     pub fn tail(&self) -> Seq<T> {
-        Seq { inner: self[1..].to_vec() }
+        unwrap_vdm(self.try_tail())
+    }
+
+    pub fn try_tail(&self) -> Result<Seq<T>, VdmError> {
+        if self.is_empty() {
+            Err(VdmError::EmptySequence)
+        } else {
+            Ok(self.slice(1, self.inner.len()))
+        }
     }
 
     pub fn sub_seq(&self, start: u64, end: u64) -> Seq<T> {
@@ -48,7 +115,7 @@ impl<T: Clone + Hash> Seq<T> {
         let start = start - 1;
         let end = cmp::min(end, self.len());
 
-        Seq { inner: self[start as usize..end as usize].to_vec() }
+        self.slice(start as usize, end as usize)
     }
 
     pub fn elems(&self) -> Set<T>
@@ -63,45 +130,126 @@ impl<T: Clone + Hash> Seq<T> {
     }
 
     pub fn reverse(&self) -> Seq<T> {
-        let mut rev = self.clone();
-        rev.inner.reverse();
-        rev
+        self.iter().rev().cloned().collect()
     }
 
     pub fn conc(&self, right: Seq<T>) -> Seq<T> {
-        self.iter().chain(right.iter()).cloned().collect()
+        let mut result = self.clone();
+        result.append(right);
+        result
+    }
+
+    /// Appends `value`, as `Vec::push` does, whatever the backing.
+    pub fn push(&mut self, value: T) {
+        self.inner.extend(Some(value));
     }
 
     pub fn modify(&self, map: Map<usize, T>) -> Seq<T> {
+        unwrap_vdm(self.try_modify(map))
+    }
+
+    pub fn try_modify(&self, map: Map<usize, T>) -> Result<Seq<T>, VdmError> {
         let mut result = self.clone();
 
         for (idx, value) in &map {
-            result[*idx - 1] = value.clone();
+            result.try_put(*idx as u64, value.clone())?;
         }
 
-        result
+        Ok(result)
+    }
+
+    fn first_elem(&self) -> Option<&T> {
+        self.into_iter().next()
+    }
+
+    #[cfg(not(feature = "persistent"))]
+    fn slice(&self, start: usize, end: usize) -> Seq<T> {
+        Seq { inner: self.inner[start..end].to_vec() }
+    }
+
+    #[cfg(feature = "persistent")]
+    fn slice(&self, start: usize, end: usize) -> Seq<T> {
+        Seq { inner: self.inner.skip(start).take(end - start) }
+    }
+
+    #[cfg(not(feature = "persistent"))]
+    fn append(&mut self, other: Seq<T>) {
+        self.inner.extend(other.inner);
+    }
+
+    #[cfg(feature = "persistent")]
+    fn append(&mut self, other: Seq<T>) {
+        self.inner.append(other.inner);
+    }
+
+    /// Converts a 1-based VDM index into a 0-based vector index.
+    fn check_index(&self, index: u64) -> Result<usize, VdmError> {
+        if index < 1 || index > self.len() {
+            Err(VdmError::IndexOutOfRange(index, self.len()))
+        } else {
+            Ok((index - 1) as usize)
+        }
+    }
+}
+
+// Not derived, as `im::Vector` only implements these for `Clone` elements.
+impl<T: Hash + Clone> Clone for Seq<T> {
+    fn clone(&self) -> Seq<T> {
+        Seq { inner: self.inner.clone() }
+    }
+}
+
+impl<T: Hash + Clone + PartialEq> PartialEq for Seq<T> {
+    fn eq(&self, other: &Seq<T>) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<T: Hash + Clone + Eq> Eq for Seq<T> {}
+
+impl<T: Hash + Clone + PartialOrd> PartialOrd for Seq<T> {
+    fn partial_cmp(&self, other: &Seq<T>) -> Option<cmp::Ordering> {
+        self.inner.partial_cmp(&other.inner)
+    }
+}
+
+impl<T: Hash + Clone + Ord> Ord for Seq<T> {
+    fn cmp(&self, other: &Seq<T>) -> cmp::Ordering {
+        self.inner.cmp(&other.inner)
+    }
+}
+
+impl<T: Hash + Clone> Hash for Seq<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner.hash(state)
     }
 }
 
 impl<T: Clone + Hash> Seq<Seq<T>> {
     pub fn dconc(&self) -> Seq<T> {
-        self.iter().flat_map(|s| s).cloned().collect()
+        self.iter().flatten().cloned().collect()
     }
 }
 
-impl<T: Hash> Default for Seq<T> {
+impl<T: Hash + Clone> Default for Seq<T> {
     fn default() -> Seq<T> {
         Seq { inner: Default::default() }
     }
 }
 
-impl<T: Hash> FromIterator<T> for Seq<T> {
+impl<T: Hash + Clone> FromIterator<T> for Seq<T> {
     fn from_iter<I: IntoIterator<Item = T>>(iterable: I) -> Seq<T> {
         Seq { inner: iterable.into_iter().collect() }
     }
 }
 
-impl<'a, T: Hash> IntoIterator for &'a Seq<T> {
+impl<T: Hash + Clone> Extend<T> for Seq<T> {
+    fn extend<I: IntoIterator<Item = T>>(&mut self, iterable: I) {
+        self.inner.extend(iterable)
+    }
+}
+
+impl<'a, T: Hash + Clone> IntoIterator for &'a Seq<T> {
     type Item = &'a T;
     type IntoIter = Iter<'a, T>;
 
@@ -110,39 +258,24 @@ impl<'a, T: Hash> IntoIterator for &'a Seq<T> {
     }
 }
 
-
-impl<T: Hash> Deref for Seq<T> {
-    type Target = Vec<T>;
-
-    fn deref(&self) -> &Vec<T> {
-        &self.inner
+impl<T: Hash + Clone> From<Seq<T>> for Vec<T> {
+    fn from(seq: Seq<T>) -> Vec<T> {
+        seq.inner.into_iter().collect()
     }
 }
 
-impl<T: Hash> DerefMut for Seq<T> {
-    fn deref_mut(&mut self) -> &mut Vec<T> {
-        &mut self.inner
-    }
-}
-
-impl<T: Hash> Into<Vec<T>> for Seq<T> {
-    fn into(self) -> Vec<T> {
-        self.inner
-    }
-}
-
-impl Into<String> for Seq<char> {
-    fn into(self) -> String {
-        self.iter().cloned().collect()
+impl From<Seq<char>> for String {
+    fn from(seq: Seq<char>) -> String {
+        seq.iter().cloned().collect()
     }
 }
 
-use std::any::Any;
+use lib::any::Any;
 
-impl<T: Hash + fmt::Display + Any> fmt::Display for Seq<T> {
+impl<T: Hash + Clone + fmt::Display + Any> fmt::Display for Seq<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let is_string = match self.inner.get(0) {
-            Some(elem) => Any::is::<char>(elem),
+        let is_string = match self.first_elem() {
+            Some(elem) => <dyn Any>::is::<char>(elem),
             None => false,
         };
 
@@ -165,10 +298,10 @@ impl<T: Hash + fmt::Display + Any> fmt::Display for Seq<T> {
     }
 }
 
-impl<T: Hash + fmt::Debug + Any> fmt::Debug for Seq<T> {
+impl<T: Hash + Clone + fmt::Debug + Any> fmt::Debug for Seq<T> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let is_string = match self.inner.get(0) {
-            Some(elem) => Any::is::<char>(elem),
+        let is_string = match self.first_elem() {
+            Some(elem) => <dyn Any>::is::<char>(elem),
             None => false,
         };
 
@@ -189,8 +322,10 @@ impl<T: Hash + fmt::Debug + Any> fmt::Debug for Seq<T> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use lib::ToString;
     use Set;
     use Map;
+    use VdmError;
 
     #[test]
     fn default() {
@@ -242,12 +377,46 @@ mod tests {
     }
 
     #[test]
-    #[should_panic(expected = "index out of bounds: the len is 3 but the index is 99")]
+    #[should_panic(expected = "Sequence index 100 out of range 1..3.")]
     fn apply_read_2() {
         let s1 = seq![1, 2, 3];
         let _ = s1.get(100);
     }
 
+    #[test]
+    #[should_panic(expected = "Sequence index 0 out of range 1..3.")]
+    fn apply_read_0() {
+        let s1 = seq![1, 2, 3];
+        let _ = s1.get(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Sequence index 4 out of range 1..3.")]
+    fn apply_write_past_end() {
+        let mut s1 = seq![1, 2, 3];
+        s1.put(4, 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "Sequence index 0 out of range 1..3.")]
+    fn get_ref_0() {
+        let mut s1 = seq![1, 2, 3];
+        let _ = s1.get_ref(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Sequence is empty.")]
+    fn head_empty() {
+        let s1: Seq<i32> = seq![];
+        let _ = s1.head();
+    }
+
+    #[test]
+    #[should_panic(expected = "Sequence index 3 out of range 1..2.")]
+    fn modify_past_end() {
+        let _ = seq![1, 2].modify(map!{3 => 5});
+    }
+
     #[test]
     fn apply_write() {
         let mut s1 = seq![1, 2, 3];
@@ -260,6 +429,24 @@ mod tests {
         assert_eq!(5, elem2);
     }
 
+    #[test]
+    fn try_get() {
+        let s1 = seq![1, 2, 3];
+
+        assert_eq!(Ok(2), s1.try_get(2));
+        assert_eq!(Err(VdmError::IndexOutOfRange(0, 3)), s1.try_get(0));
+        assert_eq!(Err(VdmError::IndexOutOfRange(4, 3)), s1.try_get(4));
+    }
+
+    #[test]
+    fn try_put() {
+        let mut s1 = seq![1, 2, 3];
+
+        assert_eq!(Ok(()), s1.try_put(3, 5));
+        assert_eq!(Err(VdmError::IndexOutOfRange(4, 3)), s1.try_put(4, 5));
+        assert_eq!(seq![1, 2, 5], s1);
+    }
+
     #[test]
     fn tail() {
         let s1 = seq![1, 2, 3];
@@ -268,6 +455,21 @@ mod tests {
         assert_eq!(seq![2, 3], tail);
     }
 
+    #[test]
+    fn try_head_tail_empty() {
+        let s1: Seq<i32> = seq![];
+
+        assert_eq!(Err(VdmError::EmptySequence), s1.try_head());
+        assert_eq!(Err(VdmError::EmptySequence), s1.try_tail());
+    }
+
+    #[test]
+    #[should_panic(expected = "Sequence is empty.")]
+    fn tail_empty() {
+        let s1: Seq<i32> = seq![];
+        let _ = s1.tail();
+    }
+
     #[test]
     fn elems() {
         let s1 = seq![1, 2, 2, 3, 3];
@@ -301,6 +503,15 @@ mod tests {
         assert_eq!(seq![1, 2, 3, 4, 5], conc);
     }
 
+    #[test]
+    fn push_and_convert() {
+        let mut s1 = seq![1, 2];
+        s1.push(3);
+
+        assert_eq!(vec![1, 2, 3], Vec::from(s1.clone()));
+        assert_eq!(seq![2, 3], s1.tail());
+    }
+
     #[test]
     fn dconc() {
         let ss = seq![seq![1, 2], seq![3, 4], seq![5, 6]];
@@ -316,6 +527,14 @@ mod tests {
         assert_eq!(seq![5, 2], modified);
     }
 
+    #[test]
+    fn try_modify() {
+        let s1 = seq![1, 2];
+
+        assert_eq!(Ok(seq![5, 2]), s1.try_modify(map!{1 => 5}));
+        assert_eq!(Err(VdmError::IndexOutOfRange(3, 2)), s1.try_modify(map!{3 => 5}));
+    }
+
     #[test]
     fn sub_seq() {
         let s1 = seq![1, 2, 2, 3, 3];
@@ -0,0 +1,487 @@
+//! `serde` support, enabled by the `serde` feature.
+//!
+//! Strings (`Seq<char>`) serialize as strings, other sequences and sets
+//! as arrays, and tuples as arrays of their fields. Maps with string keys
+//! become objects; other maps become arrays of `[key, value]` pairs. Unbounded numbers (`BigInt`, `Rat`) serialize as
+//! strings, so no precision is lost in formats such as JSON, and so do the
+//! scaled reals (`Fixed`, `Decimal`).
+
+use lib::any::{Any, TypeId};
+use lib::fmt;
+use lib::hash::Hash;
+use lib::marker::PhantomData;
+use lib::str::FromStr;
+use lib::{Box, String, ToString, Vec};
+use num_bigint;
+use num_rational::BigRational;
+use serde::de::{self, Deserialize, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::ser::{Serialize, SerializeTuple, Serializer};
+use BigInt;
+use F64;
+use Int;
+use Nat;
+use Nat1;
+use Rat;
+use Opt;
+use Token;
+use Tuple;
+use Fixed;
+use Decimal;
+use Seq;
+use Set;
+use Map;
+use OrdSet;
+use OrdMap;
+
+fn serialize_seq<'a, S, T, I>(serializer: S, items: I) -> Result<S::Ok, S::Error>
+    where S: Serializer,
+          T: Serialize + Any,
+          I: Iterator<Item = &'a T>
+{
+    if TypeId::of::<T>() == TypeId::of::<char>() {
+        let text: String = items.filter_map(|c| <dyn Any>::downcast_ref::<char>(c)).collect();
+        serializer.serialize_str(&text)
+    } else {
+        serializer.collect_seq(items)
+    }
+}
+
+/// Reads an array, or a string if `T` is `char`.
+fn deserialize_seq<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+    where D: Deserializer<'de>,
+          T: Deserialize<'de> + Any
+{
+    if TypeId::of::<T>() != TypeId::of::<char>() {
+        return Vec::deserialize(deserializer);
+    }
+
+    let chars: Vec<char> = String::deserialize(deserializer)?.chars().collect();
+    let chars: Box<dyn Any> = Box::new(chars);
+
+    match chars.downcast::<Vec<T>>() {
+        Ok(chars) => Ok(*chars),
+        Err(_) => Err(de::Error::custom("string is not a sequence of this type")),
+    }
+}
+
+/// Whether maps with keys of type `K` are objects: keys that are strings
+/// can be object keys in formats such as JSON.
+fn string_keyed<K: Any>() -> bool {
+    TypeId::of::<K>() == TypeId::of::<Seq<char>>()
+}
+
+fn serialize_map<'a, S, K, V, I>(serializer: S, maplets: I) -> Result<S::Ok, S::Error>
+    where S: Serializer,
+          K: Serialize + Any,
+          V: Serialize + 'a,
+          I: Iterator<Item = (&'a K, &'a V)>
+{
+    if string_keyed::<K>() {
+        serializer.collect_map(maplets)
+    } else {
+        serializer.collect_seq(maplets)
+    }
+}
+
+/// Reads an object if `K` is a string type, or else an array of
+/// `[key, value]` pairs.
+fn deserialize_map<'de, D, K, V>(deserializer: D) -> Result<Vec<(K, V)>, D::Error>
+    where D: Deserializer<'de>,
+          K: Deserialize<'de> + Any,
+          V: Deserialize<'de>
+{
+    struct MapletVisitor<K, V>(PhantomData<(K, V)>);
+
+    impl<'de, K: Deserialize<'de>, V: Deserialize<'de>> Visitor<'de> for MapletVisitor<K, V> {
+        type Value = Vec<(K, V)>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "a map")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Vec<(K, V)>, A::Error> {
+            let mut maplets = Vec::new();
+
+            while let Some(maplet) = map.next_entry()? {
+                maplets.push(maplet);
+            }
+
+            Ok(maplets)
+        }
+
+        fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<(K, V)>, A::Error> {
+            let mut maplets = Vec::new();
+
+            while let Some(maplet) = seq.next_element()? {
+                maplets.push(maplet);
+            }
+
+            Ok(maplets)
+        }
+    }
+
+    if string_keyed::<K>() {
+        deserializer.deserialize_map(MapletVisitor(PhantomData))
+    } else {
+        deserializer.deserialize_seq(MapletVisitor(PhantomData))
+    }
+}
+
+impl<T: Serialize + Hash + Clone + Any> Serialize for Seq<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_seq(serializer, self.iter())
+    }
+}
+
+impl<'de, T: Deserialize<'de> + Hash + Clone + Any> Deserialize<'de> for Seq<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Seq<T>, D::Error> {
+        Ok(deserialize_seq(deserializer)?.into_iter().collect())
+    }
+}
+
+impl<T: Serialize + Hash + Eq + Clone> Serialize for Set<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T: Deserialize<'de> + Hash + Eq + Clone> Deserialize<'de> for Set<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Set<T>, D::Error> {
+        Ok(Vec::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+impl<T: Serialize + Ord + Clone> Serialize for OrdSet<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.iter())
+    }
+}
+
+impl<'de, T: Deserialize<'de> + Ord + Clone> Deserialize<'de> for OrdSet<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<OrdSet<T>, D::Error> {
+        Ok(Vec::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
+impl<K: Serialize + Hash + Eq + Any, V: Serialize> Serialize for Map<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_map(serializer, self.iter())
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for Map<K, V>
+    where K: Deserialize<'de> + Hash + Eq + Clone + Any,
+          V: Deserialize<'de> + Hash + Eq + Clone
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Map<K, V>, D::Error> {
+        Ok(deserialize_map(deserializer)?.into_iter().collect())
+    }
+}
+
+impl<K: Serialize + Ord + Any, V: Serialize> Serialize for OrdMap<K, V> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serialize_map(serializer, self.iter())
+    }
+}
+
+impl<'de, K, V> Deserialize<'de> for OrdMap<K, V>
+    where K: Deserialize<'de> + Ord + Clone + Any,
+          V: Deserialize<'de> + Clone
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<OrdMap<K, V>, D::Error> {
+        Ok(deserialize_map(deserializer)?.into_iter().collect())
+    }
+}
+
+impl<T: Serialize> Serialize for Opt<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Opt<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Opt<T>, D::Error> {
+        Ok(Opt(Option::deserialize(deserializer)?))
+    }
+}
+
+/// Serializes the fields of a tuple, as elements of one flat tuple.
+pub trait SerializeFields {
+    const LEN: usize;
+
+    fn serialize_fields<S: SerializeTuple>(&self, s: &mut S) -> Result<(), S::Error>;
+}
+
+/// Deserializes the fields of a tuple, as elements of one flat tuple.
+pub trait DeserializeFields<'de>: Sized {
+    fn deserialize_fields<A: SeqAccess<'de>>(seq: &mut A, index: usize) -> Result<Self, A::Error>;
+}
+
+impl SerializeFields for () {
+    const LEN: usize = 0;
+
+    fn serialize_fields<S: SerializeTuple>(&self, _: &mut S) -> Result<(), S::Error> {
+        Ok(())
+    }
+}
+
+impl<'de> DeserializeFields<'de> for () {
+    fn deserialize_fields<A: SeqAccess<'de>>(_: &mut A, _: usize) -> Result<(), A::Error> {
+        Ok(())
+    }
+}
+
+impl<H: Serialize, T: SerializeFields> SerializeFields for Tuple<H, T> {
+    const LEN: usize = 1 + T::LEN;
+
+    fn serialize_fields<S: SerializeTuple>(&self, s: &mut S) -> Result<(), S::Error> {
+        s.serialize_element(&self.0)?;
+        self.1.serialize_fields(s)
+    }
+}
+
+impl<'de, H: Deserialize<'de>, T: DeserializeFields<'de>> DeserializeFields<'de> for Tuple<H, T> {
+    fn deserialize_fields<A: SeqAccess<'de>>(seq: &mut A, index: usize) -> Result<Self, A::Error> {
+        let head = match seq.next_element()? {
+            Some(head) => head,
+            None => return Err(de::Error::invalid_length(index, &"a longer tuple")),
+        };
+
+        Ok(Tuple(head, T::deserialize_fields(seq, index + 1)?))
+    }
+}
+
+impl<H: Serialize, T: SerializeFields> Serialize for Tuple<H, T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut s = serializer.serialize_tuple(Self::LEN)?;
+        self.serialize_fields(&mut s)?;
+        s.end()
+    }
+}
+
+impl<'de, H: Deserialize<'de>, T: DeserializeFields<'de>> Deserialize<'de> for Tuple<H, T>
+    where Tuple<H, T>: SerializeFields
+{
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Tuple<H, T>, D::Error> {
+        struct TupleVisitor<H, T>(PhantomData<(H, T)>);
+
+        impl<'de, H: Deserialize<'de>, T: DeserializeFields<'de>> Visitor<'de> for TupleVisitor<H, T> {
+            type Value = Tuple<H, T>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a tuple")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Tuple<H, T>, A::Error> {
+                Tuple::deserialize_fields(&mut seq, 0)
+            }
+        }
+
+        deserializer.deserialize_tuple(Self::LEN, TupleVisitor(PhantomData))
+    }
+}
+
+impl Serialize for Token {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_newtype_struct("Token", self.value())
+    }
+}
+
+impl<'de> Deserialize<'de> for Token {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Token, D::Error> {
+        struct TokenVisitor;
+
+        impl<'de> Visitor<'de> for TokenVisitor {
+            type Value = Token;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a token")
+            }
+
+            fn visit_newtype_struct<D: Deserializer<'de>>(self, d: D) -> Result<Token, D::Error> {
+                Ok(Token::new(&String::deserialize(d)?))
+            }
+
+            fn visit_str<E: de::Error>(self, value: &str) -> Result<Token, E> {
+                Ok(Token::new(&value))
+            }
+        }
+
+        deserializer.deserialize_newtype_struct("Token", TokenVisitor)
+    }
+}
+
+impl Serialize for F64 {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for F64 {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<F64, D::Error> {
+        F64::try_new(f64::deserialize(deserializer)?).map_err(de::Error::custom)
+    }
+}
+
+/// Serializes as the exact decimal text, e.g. `"0.25"`, as an `f64`
+/// cannot hold every value.
+impl<const FRAC: u32> Serialize for Fixed<FRAC> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de, const FRAC: u32> Deserialize<'de> for Fixed<FRAC> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Fixed<FRAC>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Fixed::from_str(&text).map_err(de::Error::custom)
+    }
+}
+
+/// Serializes as the exact decimal text, e.g. `"0.1"`.
+impl<const PLACES: u32> Serialize for Decimal<PLACES> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de, const PLACES: u32> Deserialize<'de> for Decimal<PLACES> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Decimal<PLACES>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        Decimal::from_str(&text).map_err(de::Error::custom)
+    }
+}
+
+macro_rules! int_serde {
+    ($($t:ident)*) => ($(
+        impl Serialize for $t {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_i64(self.value())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $t {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<$t, D::Error> {
+                $t::try_new(i64::deserialize(deserializer)?).map_err(de::Error::custom)
+            }
+        }
+    )*)
+}
+
+int_serde! { Int Nat Nat1 }
+
+impl Serialize for BigInt {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for BigInt {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<BigInt, D::Error> {
+        let digits = String::deserialize(deserializer)?;
+        num_bigint::BigInt::from_str(&digits).map(BigInt).map_err(de::Error::custom)
+    }
+}
+
+/// Serializes as `"numerator/denominator"`, or just the numerator if the
+/// value is an integer.
+impl Serialize for Rat {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.0.to_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for Rat {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Rat, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        BigRational::from_str(&text).map(Rat).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bincode;
+    use serde_json::{self, json};
+    use contracts::{self, Policy};
+
+    impl_quote! { Red }
+
+    def_record! {
+        Pixel { pos: tuple_t!(i64, i64), color: Opt<Red>, name :- Seq<char> };
+        inv p => p.pos.0 >= 0
+    }
+
+    impl_subtype! { Small: u64; inv n => n < 10 }
+
+    fn round_trip<T: Serialize + for<'de> Deserialize<'de>>(val: &T) -> T {
+        serde_json::from_str(&serde_json::to_string(val).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn strings_and_collections() {
+        let s = seq![strseq!("ab"), strseq!("c")];
+
+        assert_eq!(json!(["ab", "c"]), serde_json::to_value(&s).unwrap());
+        assert_eq!(s, round_trip(&s));
+        assert_eq!(json!([1, 2, 3]), serde_json::to_value(&ord_set!{3, 1, 2}).unwrap());
+        assert_eq!(set!{1, 2}, round_trip(&set!{1, 2}));
+    }
+
+    #[test]
+    fn maps() {
+        let by_name = ord_map!{strseq!("a") => 1, strseq!("b") => 2};
+        let by_number = ord_map!{1 => strseq!("a"), 2 => strseq!("b")};
+        let by_quote = map!{Red => 1};
+
+        assert_eq!(json!({"a": 1, "b": 2}), serde_json::to_value(&by_name).unwrap());
+        assert_eq!(json!([[1, "a"], [2, "b"]]), serde_json::to_value(&by_number).unwrap());
+        assert_eq!(json!([["Red", 1]]), serde_json::to_value(&by_quote).unwrap());
+        assert_eq!(by_name, round_trip(&by_name));
+        assert_eq!(by_number, round_trip(&by_number));
+        assert_eq!(by_quote, round_trip(&by_quote));
+        assert_eq!(Map::<i32, i32>::new(), round_trip(&Map::new()));
+    }
+
+    #[test]
+    fn not_self_describing() {
+        let by_name = ord_map!{strseq!("a") => seq![1, 2]};
+        let by_number = map!{1 => set!{strseq!("b")}};
+
+        let bytes = bincode::serialize(&by_name).unwrap();
+        assert_eq!(by_name, bincode::deserialize::<OrdMap<Seq<char>, Seq<i32>>>(&bytes).unwrap());
+        let bytes = bincode::serialize(&by_number).unwrap();
+        assert_eq!(by_number, bincode::deserialize::<Map<i32, Set<Seq<char>>>>(&bytes).unwrap());
+    }
+
+    #[test]
+    fn scalars() {
+        let t = mk_tuple!(Nat::new(1), Token::new(&"t"), Opt::<F64>::nil());
+
+        assert_eq!(json!([1, "t", null]), serde_json::to_value(&t).unwrap());
+        assert_eq!(t, round_trip(&t));
+        assert_eq!(json!("1/3"), serde_json::to_value(&(Rat::from(1i64) / Rat::from(3i64))).unwrap());
+        assert_eq!(BigInt::from(-7i64), round_trip(&BigInt::from(-7i64)));
+        assert_eq!(json!("0.1"), serde_json::to_value(Decimal::<9>::from_raw(100_000_000)).unwrap());
+        assert_eq!(Fixed::<32>::from_raw(i64::MAX), round_trip(&Fixed::<32>::from_raw(i64::MAX)));
+        assert!(serde_json::from_str::<Nat1>("0").is_err());
+        assert!(serde_json::from_str::<tuple_t!(i32, i32)>("[1]").is_err());
+    }
+
+    #[test]
+    fn generated_types() {
+        contracts::set_thread_policy(Some(Policy::Error));
+        let p = Pixel::new(mk_tuple!(1, 2), Opt::some(Red), strseq!("p"));
+
+        assert_eq!(json!({"pos": [1, 2], "color": "Red", "name": "p"}),
+                   serde_json::to_value(&p).unwrap());
+        assert_eq!(p, round_trip(&p));
+        assert_eq!(Small::new(4), round_trip(&Small::new(4)));
+
+        assert!(serde_json::from_str::<Pixel>(r#"{"pos": [-1, 2], "color": null, "name": ""}"#)
+                    .is_err());
+        assert!(serde_json::from_str::<Pixel>(r#"{"pos": [1, 2], "name": ""}"#).is_err());
+        assert!(serde_json::from_str::<Red>(r#""Blue""#).is_err());
+        assert!(serde_json::from_str::<Small>("10").is_err());
+    }
+}
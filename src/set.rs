@@ -1,317 +1,412 @@
-use std::collections::HashSet;
-use std::collections::hash_set::{Iter, IntoIter};
-use std::hash::{Hash, Hasher};
-use std::fmt;
-use std::ops::{Deref, DerefMut, BitOr, BitAnd, Sub};
-use std::iter::{Iterator, FromIterator, IntoIterator};
+use lib::collections::HashSet;
+#[cfg(not(feature = "persistent"))]
+use lib::collections::hash_set;
+#[cfg(feature = "persistent")]
+use im::hashset;
+use lib::hash::{Hash, Hasher};
+use lib::fmt;
+use lib::ops::{BitOr, BitAnd, Sub};
+use lib::iter::{Iterator, Extend, FromIterator, IntoIterator};
+use lib::Vec;
+use num_traits::Float;
 use util::*;
 use Map;
 use Seq;
+use VdmError;
+use error::unwrap_vdm;
+
+/// Implements the VDM set operators for `$set`, a wrapper around the
+/// backing set `$inner` whose elements are bounded by `$bound`. Shared by
+/// `Set` and `OrdSet`; `Iter` and `IntoIter`, wrapping the iterators of
+/// the backing set, must be in scope, and comprehensions build a `$map`.
+/// Only uses operations that std, `hashbrown` and `im` sets have in
+/// common.
+macro_rules! set_type {
+    ($set:ident, $inner:ident, $map:ident, $($bound:tt)+) => {
+        impl<T: $($bound)+ + Clone> $set<T> {
+            pub fn new() -> $set<T> {
+                $set { inner: $inner::new() }
+            }
 
-#[derive(Clone, PartialEq, Eq)]
-pub struct Set<T: Hash + Eq> {
-    inner: HashSet<T>,
-}
+            pub fn in_set(&self, value: T) -> bool {
+                self.inner.contains(&value)
+            }
 
-impl<T: Hash + Eq + Clone> Set<T> {
-    pub fn new() -> Set<T> {
-        Set { inner: HashSet::new() }
-    }
+            pub fn card(&self) -> u64 {
+                self.inner.len() as u64
+            }
 
-    pub fn in_set(&self, value: T) -> bool {
-        self.inner.contains(&value)
-    }
+            pub fn len(&self) -> usize {
+                self.inner.len()
+            }
 
-    pub fn card(&self) -> u64 {
-        self.inner.len() as u64
-    }
+            pub fn is_empty(&self) -> bool {
+                self.inner.is_empty()
+            }
 
-    pub fn union(&self, other: Set<T>) -> Set<T> {
-        self | &other
-    }
+            pub fn iter(&self) -> Iter<'_, T> {
+                Iter(self.inner.iter())
+            }
 
-    pub fn inter(&self, other: Set<T>) -> Set<T> {
-        self & &other
-    }
+            pub fn contains(&self, value: &T) -> bool {
+                self.inner.contains(value)
+            }
 
-    pub fn difference(&self, other: Set<T>) -> Set<T> {
-        self - &other
-    }
+            /// Adds `value`, and returns whether it was not yet in the set.
+            pub fn insert(&mut self, value: T) -> bool {
+                let added = !self.inner.contains(&value);
+                self.inner.insert(value);
+                added
+            }
 
-    pub fn is_subset(&self, other: Set<T>) -> bool {
-        self.inner.is_subset(&other)
-    }
+            /// Removes `value`, and returns whether it was in the set.
+            pub fn remove(&mut self, value: &T) -> bool {
+                let removed = self.inner.contains(value);
+                self.inner.remove(value);
+                removed
+            }
 
-    pub fn is_psubset(&self, other: Set<T>) -> bool {
-        self.len() < other.len() && self.is_subset(other)
-    }
+            pub fn union(&self, other: $set<T>) -> $set<T> {
+                self | &other
+            }
+
+            pub fn inter(&self, other: $set<T>) -> $set<T> {
+                self & &other
+            }
+
+            pub fn difference(&self, other: $set<T>) -> $set<T> {
+                self - &other
+            }
+
+            pub fn is_subset(&self, other: $set<T>) -> bool {
+                self.inner.is_subset(&other.inner)
+            }
+
+            pub fn is_psubset(&self, other: $set<T>) -> bool {
+                self.len() < other.len() && self.is_subset(other)
+            }
+
+            pub fn powersets(&self) -> $set<$set<T>> {
+                let mut iter = self.iter().cloned();
 
-    pub fn powersets(&self) -> Set<Set<T>> {
-        let mut iter = self.iter().cloned();
+                if let Some(first_elem) = iter.next() {
+                    let rest: $set<T> = iter.collect();
 
-        if let Some(first_elem) = iter.next() {
-            let rest: Set<T> = iter.collect();
+                    let powersets = rest.powersets();
 
-            let powersets = rest.powersets();
+                    let mut sets = $set::new();
+                    for set in &powersets {
+                        let mut new_set = set.clone();
+                        new_set.insert(first_elem.clone());
 
-            let mut sets = Set::new();
-            for set in &powersets {
-                let mut new_set = set.clone();
-                new_set.insert(first_elem.clone());
+                        sets.insert(new_set);
+                        sets.insert(set.clone());
+                    }
+                    sets
 
-                sets.insert(new_set);
-                sets.insert(set.clone());
+                } else {
+                    let mut sets = $set::new();
+                    sets.insert($set::new());
+                    sets
+                }
             }
-            sets
 
-        } else {
-            let mut sets = Set::new();
-            sets.insert(Set::new());
-            sets
-        }
-    }
+            pub fn exists1<P>(&self, mut pred: P) -> bool
+                where P: FnMut(T) -> bool
+            {
+                let filtered = self.iter().filter(|&e| pred(e.clone()));
+                filtered.count() == 1
+            }
 
-    pub fn exists1<P>(&self, mut pred: P) -> bool
-        where P: FnMut(T) -> bool
-    {
-        let filtered = self.iter().filter(|&e| pred(e.clone()));
-        filtered.count() == 1
-    }
+            pub fn iota<P>(&self, pred: P) -> T
+                where P: FnMut(T) -> bool
+            {
+                unwrap_vdm(self.try_iota(pred))
+            }
 
-    pub fn iota<P>(&self, mut pred: P) -> T
-        where P: FnMut(T) -> bool
-    {
-        let mut filtered = self.iter().filter(|&e| pred(e.clone()));
-        let item = filtered.next().expect("Iota does not select a result.");
+            pub fn try_iota<P>(&self, mut pred: P) -> Result<T, VdmError>
+                where P: FnMut(T) -> bool
+            {
+                let mut filtered = self.iter().filter(|&e| pred(e.clone()));
+                let item = filtered.next().ok_or(VdmError::IotaNoMatch)?;
+
+                // make sure only one item satisfied the expression.
+                match filtered.next() {
+                    None => Ok(item.clone()),
+                    Some(_) => Err(VdmError::IotaMultipleMatches),
+                }
+            }
 
-        // make sure only one item satisfied the expression.
-        match filtered.next() {
-            None => item.clone(),
-            Some(_) => panic!("Iota selects more than one result."),
-        }
-    }
+            pub fn exists<P>(&self, mut pred: P) -> bool
+                where P: FnMut(T) -> bool
+            {
+                self.iter().any(|e| pred(e.clone()))
+            }
 
-    pub fn exists<P>(&self, mut pred: P) -> bool
-        where P: FnMut(T) -> bool
-    {
-        self.iter().any(|e| pred(e.clone()))
-    }
+            pub fn forall<P>(&self, mut pred: P) -> bool
+                where P: FnMut(T) -> bool
+            {
+                self.iter().all(|e| pred(e.clone()))
+            }
 
-    pub fn forall<P>(&self, mut pred: P) -> bool
-        where P: FnMut(T) -> bool
-    {
-        self.iter().all(|e| pred(e.clone()))
-    }
+            /// Selects the first element satisfying `pred` in iteration
+            /// order, i.e. the smallest for an `OrdSet`.
+            pub fn be_such_that<P>(&self, pred: P) -> T
+                where P: FnMut(T) -> bool
+            {
+                unwrap_vdm(self.try_be_such_that(pred))
+            }
 
-    pub fn be_such_that<P>(&self, mut pred: P) -> T
-        where P: FnMut(T) -> bool
-    {
-        self.iter()
-            .find(|&e| pred(e.clone()))
-            .expect("Let Be St found no applicable bindings")
-            .clone()
-    }
+            pub fn try_be_such_that<P>(&self, mut pred: P) -> Result<T, VdmError>
+                where P: FnMut(T) -> bool
+            {
+                self.iter()
+                    .find(|&e| pred(e.clone()))
+                    .cloned()
+                    .ok_or(VdmError::BeSuchThatEmpty)
+            }
 
-    fn compr<P, E, O, B>(&self, mut pred: P, mut expr: E) -> B
-        where P: FnMut(T) -> bool,
-              E: FnMut(T) -> O,
-              O: Eq + Hash + Clone,
-              B: FromIterator<O>
-    {
-        let f = |e: &T| {
-            if pred(e.clone()) {
-                Some(expr(e.clone()))
-            } else {
-                None
+            fn compr<P, E, O, B>(&self, mut pred: P, mut expr: E) -> B
+                where P: FnMut(T) -> bool,
+                      E: FnMut(T) -> O,
+                      B: FromIterator<O>
+            {
+                let f = |e: &T| {
+                    if pred(e.clone()) {
+                        Some(expr(e.clone()))
+                    } else {
+                        None
+                    }
+                };
+
+                self.iter().filter_map(f).collect()
             }
-        };
 
-        self.iter().filter_map(f).collect()
-    }
+            pub fn set_compr<P, E, O>(&self, pred: P, expr: E) -> $set<O>
+                where P: FnMut(T) -> bool,
+                      E: FnMut(T) -> O,
+                      O: $($bound)+ + Clone
+            {
+                self.compr(pred, expr)
+            }
 
-    pub fn set_compr<P, E, O>(&self, pred: P, expr: E) -> Set<O>
-        where P: FnMut(T) -> bool,
-              E: FnMut(T) -> O,
-              O: Eq + Hash + Clone
-    {
-        self.compr(pred, expr)
-    }
+            /// `expr` gives the maplet `k |-> v` for an element as the pair
+            /// `(k, v)`, which is not a VDM value and so not a `Tuple`.
+            pub fn map_compr<P, E, V, K>(&self, pred: P, expr: E) -> $map<K, V>
+                where P: FnMut(T) -> bool,
+                      E: FnMut(T) -> (K, V),
+                      K: $($bound)+ + Clone,
+                      V: Clone
+            {
+                self.compr(pred, expr)
+            }
+        }
 
-    pub fn map_compr<P, E, V, K>(&self, pred: P, expr: E) -> Map<K, V>
-        where P: FnMut(T) -> bool,
-              E: FnMut(T) -> (K, V),
-              K: Eq + Hash + Clone,
-              V: Eq + Hash + Clone
-    {
-        self.compr(pred, expr)
-    }
-}
+        impl<T: $($bound)+ + Clone> $set<$set<T>> {
+            pub fn dunion(&self) -> $set<T> {
+                self.iter().flatten().cloned().collect()
+            }
 
-impl<T: Hash + Eq + Clone + Ord> Set<T> {
-    pub fn seq_compr<P, E, O>(&self, mut pred: P, mut expr: E) -> Seq<O>
-        where P: FnMut(T) -> bool,
-              E: FnMut(T) -> O,
-              O: Eq + Hash + Clone
-    {
-        let f = |e: &T| -> Option<O> {
-            if pred(e.clone()) {
-                Some(expr(e.clone()))
-            } else {
-                None
+            pub fn dinter(&self) -> $set<T> {
+
+                if let Some(s) = self.iter().next() {
+                    self.iter().fold(s.clone(), |int, s| &int & s)
+                } else {
+                    $set::new()
+                }
             }
-        };
+        }
 
-        let mut v: Vec<_> = self.iter().cloned().collect();
-        v.sort();
-        v.iter().filter_map(f).collect()
-    }
-}
+        impl $set<i64> {
+            pub fn range(start: f64, end: f64) -> $set<i64> {
+                let start = Float::ceil(start) as isize;
+                let end = (Float::floor(end) as isize) + 1;
 
-impl<T: Hash + Eq + Clone> Set<Set<T>> {
-    pub fn dunion(&self) -> Set<T> {
-        self.iter().flat_map(|s| s).cloned().collect()
-    }
+                (start..end).map(|i| i as i64).collect()
+            }
+        }
 
-    pub fn dinter(&self) -> Set<T> {
+        impl<T: $($bound)+> Default for $set<T> {
+            fn default() -> $set<T> {
+                $set { inner: Default::default() }
+            }
+        }
 
-        if let Some(s) = self.iter().next() {
-            self.iter().fold(s.clone(), |int, s| s.intersection(&int).cloned().collect())
-        } else {
-            Set::new()
+        impl<T> Sub<&$set<T>> for &$set<T> where T: $($bound)+ + Clone
+        {
+            type Output = $set<T>;
+
+            /// Returns the difference of `self` and `rhs` as a new set.
+            fn sub(self, rhs: &$set<T>) -> $set<T> {
+                let mut result = self.clone();
+                for e in rhs {
+                    result.inner.remove(e);
+                }
+                result
+            }
         }
-    }
-}
 
-impl Set<i64> {
-    pub fn range(start: f64, end: f64) -> Set<i64> {
-        let start = start.ceil() as isize;
-        let end = (end.floor() as isize) + 1;
+        impl<T> BitAnd<&$set<T>> for &$set<T> where T: $($bound)+ + Clone
+        {
+            type Output = $set<T>;
 
-        (start..end).map(|i| i as i64).collect()
-    }
-}
+            /// Returns the intersection of `self` and `rhs` as a new set.
+            fn bitand(self, rhs: &$set<T>) -> $set<T> {
+                self.iter().filter(|&e| rhs.contains(e)).cloned().collect()
+            }
+        }
 
-impl<T: Hash + Eq> Default for Set<T> {
-    fn default() -> Set<T> {
-        Set { inner: Default::default() }
-    }
-}
+        impl<T> BitOr<&$set<T>> for &$set<T> where T: $($bound)+ + Clone
+        {
+            type Output = $set<T>;
 
-impl<'a, 'b, T> Sub<&'b Set<T>> for &'a Set<T> where T: Eq + Hash + Clone
-{
-    type Output = Set<T>;
+            /// Returns the union of `self` and `rhs` as a new set.
+            fn bitor(self, rhs: &$set<T>) -> $set<T> {
+                let mut result = self.clone();
+                result.inner.extend(rhs.iter().cloned());
+                result
+            }
+        }
 
-    /// Returns the difference of `self` and `rhs` as a new `Set<T>`.
-    fn sub(self, rhs: &Set<T>) -> Set<T> {
-        self.inner.difference(&rhs.inner).cloned().collect()
-    }
-}
+        impl<T> FromIterator<T> for $set<T> where T: $($bound)+ + Clone
+        {
+            fn from_iter<I: IntoIterator<Item = T>>(iterable: I) -> $set<T> {
+                $set { inner: iterable.into_iter().collect() }
+            }
+        }
 
-impl<'a, 'b, T> BitAnd<&'b Set<T>> for &'a Set<T> where T: Eq + Hash + Clone
-{
-    type Output = Set<T>;
+        impl<T> Extend<T> for $set<T> where T: $($bound)+ + Clone
+        {
+            fn extend<I: IntoIterator<Item = T>>(&mut self, iterable: I) {
+                self.inner.extend(iterable)
+            }
+        }
 
-    /// Returns the intersection of `self` and `rhs` as a new `Set<T>`.
-    fn bitand(self, rhs: &Set<T>) -> Set<T> {
-        self.inner.intersection(&rhs.inner).cloned().collect()
-    }
-}
+        impl<'a, T> IntoIterator for &'a $set<T> where T: $($bound)+ + Clone
+        {
+            type Item = &'a T;
+            type IntoIter = Iter<'a, T>;
 
-impl<'a, 'b, T> BitOr<&'b Set<T>> for &'a Set<T> where T: Eq + Hash + Clone
-{
-    type Output = Set<T>;
+            fn into_iter(self) -> Iter<'a, T> {
+                self.iter()
+            }
+        }
 
-    /// Returns the union of `self` and `rhs` as a new `Set<T>`.
-    fn bitor(self, rhs: &Set<T>) -> Set<T> {
-        self.inner.union(&rhs.inner).cloned().collect()
-    }
-}
+        impl<T> IntoIterator for $set<T> where T: $($bound)+ + Clone
+        {
+            type Item = T;
+            type IntoIter = IntoIter<T>;
 
-impl<T: Hash + Eq + Clone> Hash for Set<T> {
-    /// https://github.com/rust-lang/rust/issues/21182
-    fn hash<H>(&self, state: &mut H)
-        where H: Hasher
-    {
-        let set_hash = self.into_iter().fold(0, |sum, val| sum ^ get_hash(val));
-        set_hash.hash(state);
-    }
-}
+            fn into_iter(self) -> IntoIter<T> {
+                IntoIter(self.inner.into_iter())
+            }
+        }
 
-impl<T> FromIterator<T> for Set<T> where T: Eq + Hash + Clone
-{
-    fn from_iter<I: IntoIterator<Item = T>>(iterable: I) -> Set<T> {
-        Set { inner: iterable.into_iter().collect() }
-    }
-}
+        impl<T: $($bound)+ + Clone + fmt::Display> fmt::Display for $set<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                let item_string = self.iter()
+                                      .map(|i| format!("{}", i))
+                                      .collect::<Vec<_>>()
+                                      .join(", ");
 
-impl<'a, T> IntoIterator for &'a Set<T> where T: Eq + Hash + Clone
-{
-    type Item = &'a T;
-    type IntoIter = Iter<'a, T>;
+                write!(f, "{{{}}}", item_string)
+            }
+        }
 
-    fn into_iter(self) -> Iter<'a, T> {
-        self.iter()
-    }
+        impl<T: $($bound)+ + Clone + fmt::Debug> fmt::Debug for $set<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{:?}", self.inner)
+            }
+        }
+    };
 }
 
-impl<T> IntoIterator for Set<T> where T: Eq + Hash + Clone
-{
-    type Item = T;
-    type IntoIter = IntoIter<T>;
-
-    fn into_iter(self) -> IntoIter<T> {
-        self.inner.into_iter()
-    }
+/// Set backed by a `HashSet`, or by the persistent `im::HashSet` with the
+/// `persistent` feature. The backing is private, so the API, including
+/// the conversions from and to `HashSet`, is the same with either.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Set<T: Hash + Eq> {
+    inner: Backing<T>,
 }
 
-impl<T: Hash + Eq + Clone> Deref for Set<T> {
-    type Target = HashSet<T>;
+#[cfg(not(feature = "persistent"))]
+type Backing<T> = HashSet<T>;
+#[cfg(feature = "persistent")]
+type Backing<T> = im::HashSet<T>;
 
-    fn deref(&self) -> &HashSet<T> {
-        &self.inner
-    }
+#[cfg(not(feature = "persistent"))]
+wrap_iter! {
+    /// Iterator over the elements of a `Set`.
+    Iter<'a, T>(hash_set::Iter<'a, T>) -> &'a T where T: Hash + Eq
 }
-
-impl<T: Hash + Eq + Clone> DerefMut for Set<T> {
-    fn deref_mut(&mut self) -> &mut HashSet<T> {
-        &mut self.inner
-    }
+#[cfg(feature = "persistent")]
+wrap_iter! {
+    /// Iterator over the elements of a `Set`.
+    Iter<'a, T>(hashset::Iter<'a, T>) -> &'a T where T: Hash + Eq
 }
 
-impl<T: Hash + Eq + Clone> Into<HashSet<T>> for Set<T> {
-    fn into(self) -> HashSet<T> {
-        self.inner
-    }
+#[cfg(not(feature = "persistent"))]
+wrap_iter! {
+    /// Consuming iterator over the elements of a `Set`.
+    IntoIter<T>(hash_set::IntoIter<T>) -> T where T: Hash + Eq + Clone
+}
+#[cfg(feature = "persistent")]
+wrap_iter! {
+    /// Consuming iterator over the elements of a `Set`.
+    IntoIter<T>(hashset::ConsumingIter<T>) -> T where T: Hash + Eq + Clone
 }
 
+set_type!(Set, Backing, Map, Hash + Eq);
+
 impl<T: Hash + Eq + Clone> From<HashSet<T>> for Set<T> {
     fn from(s: HashSet<T>) -> Set<T> {
-        Set { inner: s }
+        Set { inner: Backing::from(s) }
+    }
+}
+
+impl<T: Hash + Eq + Clone> From<Set<T>> for HashSet<T> {
+    fn from(s: Set<T>) -> HashSet<T> {
+        s.into_iter().collect()
     }
 }
 
-impl<T: Hash + Eq + Clone + fmt::Display> fmt::Display for Set<T> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let item_string = self.iter()
-                              .map(|i| format!("{}", i))
-                              .collect::<Vec<_>>()
-                              .join(", ");
+impl<T: Hash + Eq + Clone + Ord> Set<T> {
+    pub fn seq_compr<P, E, O>(&self, mut pred: P, mut expr: E) -> Seq<O>
+        where P: FnMut(T) -> bool,
+              E: FnMut(T) -> O,
+              O: Eq + Hash + Clone
+    {
+        let f = |e: &T| -> Option<O> {
+            if pred(e.clone()) {
+                Some(expr(e.clone()))
+            } else {
+                None
+            }
+        };
 
-        write!(f, "{{{}}}", item_string)
+        let mut v: Vec<_> = self.iter().cloned().collect();
+        v.sort();
+        v.iter().filter_map(f).collect()
     }
 }
 
-impl<T: Hash + Eq + Clone + fmt::Debug> fmt::Debug for Set<T> {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:?}", self.inner)
+impl<T: Hash + Eq + Clone> Hash for Set<T> {
+    /// https://github.com/rust-lang/rust/issues/21182
+    fn hash<H>(&self, state: &mut H)
+        where H: Hasher
+    {
+        let set_hash = self.into_iter().fold(0, |sum, val| sum ^ get_hash(val));
+        set_hash.hash(state);
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use lib::ToString;
     use Seq;
     use Map;
+    use VdmError;
 
     #[test]
     fn equality() {
@@ -443,6 +538,15 @@ mod tests {
         let _ = s1.iota(|_| true);
     }
 
+    #[test]
+    fn try_iota() {
+        let s1 = set!{1,2,3};
+
+        assert_eq!(Ok(2), s1.try_iota(|i| i == 2));
+        assert_eq!(Err(VdmError::IotaNoMatch), s1.try_iota(|i| i == 4));
+        assert_eq!(Err(VdmError::IotaMultipleMatches), s1.try_iota(|i| i > 1));
+    }
+
     #[test]
     fn exists1_1() {
         let s1 = set!{1,2,3};
@@ -485,10 +589,10 @@ mod tests {
         let s3 = set!{5,6};
 
         let cart = cartesian_set!(s1, s2, s3);
-        let result = set!{(1,3,5), (1,3,6), 
- 						  (1,4,5), (1,4,6),
- 						  (2,3,5), (2,3,6), 
- 						  (2,4,5), (2,4,6)};
+        let result = set!{mk_tuple!(1,3,5), mk_tuple!(1,3,6),
+                          mk_tuple!(1,4,5), mk_tuple!(1,4,6),
+                          mk_tuple!(2,3,5), mk_tuple!(2,3,6),
+                          mk_tuple!(2,4,5), mk_tuple!(2,4,6)};
         assert_eq!(result, cart);
     }
 
@@ -496,13 +600,18 @@ mod tests {
     fn cartesian_2() {
 
         let cart = cartesian_set!(set!{1,2}, set!{3,4}, set!{5,6});
-        let result = set!{(1,3,5), (1,3,6), 
- 						  (1,4,5), (1,4,6),
- 						  (2,3,5), (2,3,6), 
- 						  (2,4,5), (2,4,6)};
+        let result = set!{mk_tuple!(1,3,5), mk_tuple!(1,3,6),
+                          mk_tuple!(1,4,5), mk_tuple!(1,4,6),
+                          mk_tuple!(2,3,5), mk_tuple!(2,3,6),
+                          mk_tuple!(2,4,5), mk_tuple!(2,4,6)};
         assert_eq!(result, cart);
     }
 
+    #[test]
+    fn cartesian_one_set() {
+        assert_eq!(set!{mk_tuple!(1), mk_tuple!(2)}, cartesian_set!(set!{1,2}));
+    }
+
     #[test]
     fn forall1() {
         let s1 = set!{strseq!("foo"), strseq!("bar")};
@@ -527,6 +636,20 @@ mod tests {
         assert_eq!(2, result);
     }
 
+    #[test]
+    fn try_let_be_such_that() {
+        let s1 = set!{1,3};
+
+        assert_eq!(Ok(3), s1.try_be_such_that(|i| i > 2));
+        assert_eq!(Err(VdmError::BeSuchThatEmpty), s1.try_be_such_that(|i| i % 2 == 0));
+    }
+
+    #[test]
+    #[should_panic(expected = "Let Be St found no applicable bindings")]
+    fn let_be_such_that_empty() {
+        let _ = set!{1,3}.be_such_that(|i| i % 2 == 0);
+    }
+
     #[test]
     fn set_comprehension_1() {
         let result = set!{1,2,3,0}.set_compr(|i| i % 2 == 0, |i| i * 2);
@@ -542,7 +665,7 @@ mod tests {
 
         let cart = cartesian_set!(s1, s2, s3);
 
-        let result = cart.set_compr(|(x, _y, _z)| x == 1, |(_x, y, z)| y * z);
+        let result = cart.set_compr(|t| tuple_field!(t, 1) == 1, |t| tuple_field!(t, 2) * tuple_field!(t, 3));
 
         let expected = set!{15, 18, 20, 24};
 
@@ -562,4 +685,16 @@ mod tests {
 
         assert_eq!(map!{2 => 4, 4 => 8}, result);
     }
+
+    #[test]
+    fn hash_set_conversions() {
+        let mut s = set!{1, 2};
+        assert!(s.insert(3));
+        assert!(!s.insert(3));
+        assert!(s.remove(&1));
+
+        let h: HashSet<i32> = s.clone().into();
+        assert_eq!(2, h.len());
+        assert_eq!(s, Set::from(h));
+    }
 }
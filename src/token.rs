@@ -1,14 +1,20 @@
-use std::fmt;
+use lib::fmt;
+use lib::{String, ToString};
 
-#[derive(PartialEq, Eq, Hash, Clone, Default)]
+/// Ordered by the token value, for use as element of ordered collections.
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Default)]
 pub struct Token {
     value: String,
 }
 
 impl Token {
-    pub fn new(expr: &ToString) -> Token {
+    pub fn new(expr: &dyn ToString) -> Token {
         Token { value: expr.to_string() }
     }
+
+    pub fn value(&self) -> &str {
+        &self.value
+    }
 }
 
 impl fmt::Display for Token {
@@ -0,0 +1,627 @@
+//! Combinatorial testing: the expansion of `traces` definitions into test
+//! cases, and running them with Overture's verdicts and filtering.
+//!
+//! Generated code builds one `Trace` per named trace. Each test case is a
+//! sequence of calls, run against fresh state from the `init` function
+//! given to `Trace::run`.
+
+use contracts::{self, Policy};
+use error::VdmError;
+use lib::cell::Cell;
+use lib::fmt;
+use lib::hash::Hash;
+use lib::iter;
+use lib::rc::Rc;
+use lib::{Box, String, ToString, Vec};
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Once;
+use vdm_display::VdmDisplay;
+use Set;
+
+/// The most repetitions of `T*` and `T+`, as in Overture.
+pub const MAX_REPEAT: u32 = 3;
+
+type Action<S> = Rc<dyn Fn(&mut S) -> Result<String, VdmError>>;
+
+type Pre<S> = (Rc<str>, Rc<dyn Fn(&S) -> bool>);
+
+/// One call of a test case, with the text it is reported by.
+pub struct Call<S> {
+    name: Rc<str>,
+    action: Action<S>,
+    pre: Option<Pre<S>>,
+}
+
+impl<S> Call<S> {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl<S> Clone for Call<S> {
+    fn clone(&self) -> Call<S> {
+        Call { name: self.name.clone(), action: self.action.clone(), pre: self.pre.clone() }
+    }
+}
+
+impl<S> fmt::Debug for Call<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.name)
+    }
+}
+
+/// A test case: the calls of one expansion of a trace, in order.
+pub struct TestCase<S> {
+    calls: Vec<Call<S>>,
+}
+
+impl<S> TestCase<S> {
+    pub fn calls(&self) -> &[Call<S>] {
+        &self.calls
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.calls.iter().map(Call::name).collect()
+    }
+}
+
+impl<S> Clone for TestCase<S> {
+    fn clone(&self) -> TestCase<S> {
+        TestCase { calls: self.calls.clone() }
+    }
+}
+
+/// Prints the calls separated by `; `, as Overture does.
+impl<S> fmt::Display for TestCase<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.names().join("; "))
+    }
+}
+
+impl<S> fmt::Debug for TestCase<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TestCase({})", self)
+    }
+}
+
+enum Node<S> {
+    Call(Call<S>),
+    Concat(Vec<Trace<S>>),
+    Alt(Vec<Trace<S>>),
+    Repeat(Trace<S>, u32, u32),
+    Perm(Vec<Trace<S>>),
+    Bind(Rc<dyn Fn(usize) -> Trace<S>>, usize),
+}
+
+/// A trace expression over the state `S`. Traces are cheap to clone, and
+/// are combined into larger ones by the builder methods.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate codegen_runtime;
+/// use codegen_runtime::*;
+/// use codegen_runtime::traces::{Trace, Verdict};
+///
+/// fn main() {
+///     // let x in set {1, 2} in (push(x); pop()){1,2}
+///     let t = Trace::let_in(&set!{1, 2}, |x: i64| {
+///         Trace::call_void(format!("push({})", x), move |s: &mut Vec<i64>| {
+///             s.push(x);
+///             Ok(())
+///         })
+///             .then(Trace::call("pop()", |s: &mut Vec<i64>| Ok(s.pop().unwrap())))
+///             .repeat(1, 2)
+///     });
+///
+///     assert_eq!(4, t.cases().count());
+///     assert!(t.run(Vec::new).all(|r| r.verdict == Verdict::Passed));
+/// }
+/// ```
+pub struct Trace<S> {
+    node: Rc<Node<S>>,
+}
+
+impl<S> Clone for Trace<S> {
+    fn clone(&self) -> Trace<S> {
+        Trace { node: self.node.clone() }
+    }
+}
+
+impl<S: 'static> Trace<S> {
+    fn new(node: Node<S>) -> Trace<S> {
+        Trace { node: Rc::new(node) }
+    }
+
+    /// A call of an operation or function, reported as `name` with the
+    /// result in VDM form.
+    pub fn call<N, F, R>(name: N, f: F) -> Trace<S>
+        where N: Into<String>,
+              F: Fn(&mut S) -> Result<R, VdmError> + 'static,
+              R: VdmDisplay
+    {
+        Trace::action(name.into(), Rc::new(move |s| f(s).map(|r| r.to_vdm_string())))
+    }
+
+    /// A call of an operation without a result, reported with `()`.
+    pub fn call_void<N, F>(name: N, f: F) -> Trace<S>
+        where N: Into<String>,
+              F: Fn(&mut S) -> Result<(), VdmError> + 'static
+    {
+        Trace::action(name.into(), Rc::new(move |s| f(s).map(|()| "()".to_owned())))
+    }
+
+    fn action(name: String, action: Action<S>) -> Trace<S> {
+        Trace::new(Node::Call(Call { name: name.into(), action, pre: None }))
+    }
+
+    /// Gives a call the precondition of the operation or function it
+    /// calls, described by `desc`. A test case is inconclusive only when
+    /// `pre` does not hold before the call; precondition violations within
+    /// the call fail it. Panics if the trace is not a call.
+    pub fn pre<D, P>(self, desc: D, pre: P) -> Trace<S>
+        where D: Into<String>,
+              P: Fn(&S) -> bool + 'static
+    {
+        let desc: String = desc.into();
+
+        match *self.node {
+            Node::Call(ref call) => Trace::new(Node::Call(Call {
+                name: call.name.clone(),
+                action: call.action.clone(),
+                pre: Some((desc.into(), Rc::new(pre))),
+            })),
+            _ => panic!("only a call has a precondition"),
+        }
+    }
+
+    /// `T1; T2; ...`: every test case of each trace, followed by every
+    /// test case of the next.
+    pub fn concat(traces: Vec<Trace<S>>) -> Trace<S> {
+        Trace::new(Node::Concat(traces))
+    }
+
+    /// `self; other`.
+    pub fn then(self, other: Trace<S>) -> Trace<S> {
+        Trace::concat(vec![self, other])
+    }
+
+    /// `T1 | T2 | ...`: the test cases of each trace in turn.
+    pub fn alt(traces: Vec<Trace<S>>) -> Trace<S> {
+        Trace::new(Node::Alt(traces))
+    }
+
+    /// `self | other`.
+    pub fn or(self, other: Trace<S>) -> Trace<S> {
+        Trace::alt(vec![self, other])
+    }
+
+    /// `T{n, m}`: `n` to `m` repetitions, fewest first. Panics if `n > m`.
+    pub fn repeat(self, n: u32, m: u32) -> Trace<S> {
+        assert!(n <= m, "repetition {{{}, {}}} is empty", n, m);
+        Trace::new(Node::Repeat(self, n, m))
+    }
+
+    /// `T{n}`.
+    pub fn times(self, n: u32) -> Trace<S> {
+        self.repeat(n, n)
+    }
+
+    /// `T*`: 0 to `MAX_REPEAT` repetitions.
+    pub fn star(self) -> Trace<S> {
+        self.repeat(0, MAX_REPEAT)
+    }
+
+    /// `T+`: 1 to `MAX_REPEAT` repetitions.
+    pub fn plus(self) -> Trace<S> {
+        self.repeat(1, MAX_REPEAT)
+    }
+
+    /// `T?`.
+    pub fn optional(self) -> Trace<S> {
+        self.repeat(0, 1)
+    }
+
+    /// `|| (T1, T2, ...)`: the traces concatenated in every order, in
+    /// lexicographic order of their positions.
+    pub fn perm(traces: Vec<Trace<S>>) -> Trace<S> {
+        Trace::new(Node::Perm(traces))
+    }
+
+    /// `let x in set s in T`: the test cases of `trace(x)` for each `x` in
+    /// `s`, in the order Overture prints the set. Bind several variables
+    /// with nested lets, and `let x in set power s` with
+    /// `s.powersets()`.
+    pub fn let_in<V, F>(s: &Set<V>, trace: F) -> Trace<S>
+        where V: Hash + Eq + Clone + VdmDisplay + 'static,
+              F: Fn(V) -> Trace<S> + 'static
+    {
+        let mut values: Vec<V> = s.iter().cloned().collect();
+        values.sort_by(VdmDisplay::vdm_cmp);
+        let len = values.len();
+
+        Trace::new(Node::Bind(Rc::new(move |i| trace(values[i].clone())), len))
+    }
+
+    /// `let x in set s be st pred(x) in T`. There are no test cases when
+    /// no element satisfies `pred`, unlike `Set::be_such_that`, which
+    /// picks a single one.
+    pub fn let_be_st<V, P, F>(s: &Set<V>, pred: P, trace: F) -> Trace<S>
+        where V: Hash + Eq + Clone + VdmDisplay + 'static,
+              P: FnMut(V) -> bool,
+              F: Fn(V) -> Trace<S> + 'static
+    {
+        Trace::let_in(&s.set_compr(pred, |x| x), trace)
+    }
+
+    /// Lazily enumerates the test cases of the trace.
+    pub fn cases(&self) -> Cases<S> {
+        Cases { iter: self.expand() }
+    }
+
+    /// Runs the test cases in order, each on fresh state from `init`.
+    pub fn run<I: FnMut() -> S>(&self, init: I) -> Execution<S, I> {
+        Execution { cases: self.cases(), init, number: 0, filters: Vec::new() }
+    }
+
+    fn expand(&self) -> Box<dyn Iterator<Item = Vec<Call<S>>>> {
+        match *self.node {
+            Node::Call(ref call) => Box::new(iter::once(vec![call.clone()])),
+            Node::Concat(ref traces) => concat(traces.clone()),
+            Node::Alt(ref traces) => Box::new(traces.clone().into_iter().flat_map(|t| t.expand())),
+            Node::Repeat(ref trace, n, m) => {
+                let trace = trace.clone();
+                Box::new((n..=m).flat_map(move |k| concat(vec![trace.clone(); k as usize])))
+            }
+            Node::Perm(ref traces) => {
+                let traces = traces.clone();
+                Box::new(Permutations::new(traces.len())
+                             .flat_map(move |p| concat(p.iter().map(|&i| traces[i].clone()).collect())))
+            }
+            Node::Bind(ref trace, len) => {
+                let trace = trace.clone();
+                Box::new((0..len).flat_map(move |i| trace(i).expand()))
+            }
+        }
+    }
+}
+
+fn concat<S: 'static>(mut traces: Vec<Trace<S>>) -> Box<dyn Iterator<Item = Vec<Call<S>>>> {
+    if traces.is_empty() {
+        return Box::new(iter::once(Vec::new()));
+    }
+
+    let first = traces.remove(0);
+
+    Box::new(first.expand().flat_map(move |prefix| {
+        concat(traces.clone()).map(move |suffix| {
+            let mut calls = prefix.clone();
+            calls.extend(suffix);
+            calls
+        })
+    }))
+}
+
+/// The permutations of `0..n` in lexicographic order.
+struct Permutations {
+    next: Option<Vec<usize>>,
+}
+
+impl Permutations {
+    fn new(n: usize) -> Permutations {
+        Permutations { next: Some((0..n).collect()) }
+    }
+}
+
+impl Iterator for Permutations {
+    type Item = Vec<usize>;
+
+    fn next(&mut self) -> Option<Vec<usize>> {
+        let perm = self.next.take()?;
+        let mut next = perm.clone();
+
+        if let Some(i) = (1..next.len()).rev().find(|&i| next[i - 1] < next[i]) {
+            let j = (i..next.len()).rev().find(|&j| next[i - 1] < next[j]).unwrap();
+            next.swap(i - 1, j);
+            next[i..].reverse();
+            self.next = Some(next);
+        }
+
+        Some(perm)
+    }
+}
+
+/// The test cases of a trace, from `Trace::cases`.
+pub struct Cases<S> {
+    iter: Box<dyn Iterator<Item = Vec<Call<S>>>>,
+}
+
+impl<S> Iterator for Cases<S> {
+    type Item = TestCase<S>;
+
+    fn next(&mut self) -> Option<TestCase<S>> {
+        self.iter.next().map(|calls| TestCase { calls })
+    }
+}
+
+/// The outcome of a test case.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Verdict {
+    Passed,
+    /// A call failed, or panicked.
+    Failed,
+    /// A call made directly from the trace was made outside of its
+    /// precondition, given with `Trace::pre`.
+    Inconclusive,
+    /// Not run, as it starts with the calls up to the failing call of the
+    /// test case with this number.
+    Filtered(u64),
+}
+
+impl fmt::Display for Verdict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Verdict::Passed => f.write_str("PASSED"),
+            Verdict::Failed => f.write_str("FAILED"),
+            Verdict::Inconclusive => f.write_str("INCONCLUSIVE"),
+            Verdict::Filtered(n) => write!(f, "FILTERED by test {}", n),
+        }
+    }
+}
+
+/// A test case that was run, or filtered.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TestResult {
+    /// Test cases are numbered from 1 in the order of `Trace::cases`.
+    pub number: u64,
+    pub calls: Vec<String>,
+    /// The result of each call that was made, up to the failing one,
+    /// whose result is the error message.
+    pub results: Vec<String>,
+    pub verdict: Verdict,
+}
+
+/// Prints `n: call = result; ... -> VERDICT`.
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.number)?;
+
+        for (i, call) in self.calls.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+
+            match self.results.get(i) {
+                Some(result) => write!(f, "{} = {}", call, result)?,
+                None => f.write_str(call)?,
+            }
+        }
+
+        write!(f, " -> {}", self.verdict)
+    }
+}
+
+/// The results of running a trace, from `Trace::run`.
+///
+/// As in Overture, a test case that fails or is inconclusive at a call
+/// filters out all later test cases that start with the same calls up to
+/// and including that one, since they would stop at the same point.
+pub struct Execution<S, I> {
+    cases: Cases<S>,
+    init: I,
+    number: u64,
+    filters: Vec<(Vec<String>, u64)>,
+}
+
+impl<S, I: FnMut() -> S> Execution<S, I> {
+    fn execute(&mut self, case: &TestCase<S>) -> (Vec<String>, Verdict) {
+        let mut state = (self.init)();
+        let mut results = Vec::new();
+
+        for (i, call) in case.calls.iter().enumerate() {
+            match run_call(call, &mut state) {
+                Ok(result) => results.push(result),
+                Err((message, verdict)) => {
+                    results.push(message);
+                    let prefix = case.calls[..=i].iter().map(|c| c.name().to_owned()).collect();
+                    self.filters.push((prefix, self.number));
+                    return (results, verdict);
+                }
+            }
+        }
+
+        (results, Verdict::Passed)
+    }
+}
+
+impl<S, I: FnMut() -> S> Iterator for Execution<S, I> {
+    type Item = TestResult;
+
+    fn next(&mut self) -> Option<TestResult> {
+        let case = self.cases.next()?;
+        self.number += 1;
+
+        let calls: Vec<String> = case.names().into_iter().map(str::to_owned).collect();
+        let filter = self.filters.iter().find(|&(prefix, _)| calls.starts_with(prefix));
+
+        let (results, verdict) = match filter {
+            Some(&(_, n)) => (Vec::new(), Verdict::Filtered(n)),
+            None => self.execute(&case),
+        };
+
+        Some(TestResult { number: self.number, calls, results, verdict })
+    }
+}
+
+/// Runs a call with contract violations returned as errors, and panics
+/// caught, so that the test case can be judged by them.
+fn run_call<S>(call: &Call<S>, state: &mut S) -> Result<String, (String, Verdict)> {
+    if let Some((ref desc, ref pre)) = call.pre {
+        if !pre(state) {
+            return Err((VdmError::PreconditionViolated(desc.to_string()).to_string(), Verdict::Inconclusive));
+        }
+    }
+
+    let saved = contracts::thread_policy();
+    contracts::set_thread_policy(Some(Policy::Error));
+    let outcome = quietly(|| panic::catch_unwind(AssertUnwindSafe(|| (call.action)(state))));
+    contracts::set_thread_policy(saved);
+
+    let message = match outcome {
+        Ok(Ok(result)) => return Ok(result),
+        Ok(Err(err)) => err.to_string(),
+        Err(payload) => match payload.downcast::<String>() {
+            Ok(message) => *message,
+            Err(payload) => payload.downcast_ref::<&str>().map_or("panic", |m| *m).to_owned(),
+        },
+    };
+
+    Err((message, Verdict::Failed))
+}
+
+thread_local! {
+    static QUIET: Cell<bool> = const { Cell::new(false) };
+}
+
+static QUIET_HOOK: Once = Once::new();
+
+/// Runs `f` without the panic hook printing its panics, which are caught
+/// and reported as results. The hook of other threads is unaffected.
+fn quietly<T, F: FnOnce() -> T>(f: F) -> T {
+    QUIET_HOOK.call_once(|| {
+        let hook = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            if !QUIET.with(Cell::get) {
+                hook(info);
+            }
+        }));
+    });
+
+    let saved = QUIET.with(|q| q.replace(true));
+    let result = f();
+    QUIET.with(|q| q.set(saved));
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use contracts::{check, Condition};
+    use F64;
+    use MATH;
+
+    fn op(name: &'static str) -> Trace<Vec<&'static str>> {
+        Trace::call_void(name, move |s: &mut Vec<&'static str>| {
+            s.push(name);
+            Ok(())
+        })
+    }
+
+    fn names<S: 'static>(t: &Trace<S>) -> Vec<String> {
+        t.cases().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn expansion() {
+        let t = op("a").then(op("b").or(op("c")));
+        assert_eq!(vec!["a; b", "a; c"], names(&t));
+
+        assert_eq!(vec!["", "a", "a; a", "a; a; a"], names(&op("a").star()));
+        assert_eq!(3, op("a").plus().cases().count());
+        assert_eq!(vec!["a; a"], names(&op("a").times(2)));
+        assert_eq!(vec!["", "a"], names(&op("a").optional()));
+
+        let t = op("a").or(op("b")).repeat(1, 2);
+        assert_eq!(vec!["a", "b", "a; a", "a; b", "b; a", "b; b"], names(&t));
+
+        let t = Trace::perm(vec![op("a"), op("b"), op("c")]);
+        assert_eq!(vec!["a; b; c", "a; c; b", "b; a; c", "b; c; a", "c; a; b", "c; b; a"], names(&t));
+    }
+
+    #[test]
+    fn bindings() {
+        let push = |x: i64| Trace::call_void(format!("push({})", x), move |_: &mut Vec<i64>| Ok(()));
+
+        let t = Trace::let_be_st(&set!{3, 1, 4, 2}, |x| x % 2 == 1, push);
+        assert_eq!(vec!["push(1)", "push(3)"], names(&t));
+        assert_eq!(0, Trace::let_be_st(&set!{1, 2}, |x| x > 2, push).cases().count());
+
+        let t = Trace::let_in(&set!{1, 2}.powersets(), move |s: Set<i64>| {
+            Trace::call(format!("sum({})", s.to_vdm_string()), move |_: &mut Vec<i64>| {
+                Ok(s.iter().sum::<i64>())
+            })
+        });
+        assert_eq!(vec!["sum({1, 2})", "sum({1})", "sum({2})", "sum({})"], names(&t));
+
+        let (xs, ys) = (set!{1, 2}, set!{3, 4, 5});
+        let inner = ys.clone();
+        let t = Trace::let_in(&xs, move |x| {
+            Trace::let_in(&inner, move |y| {
+                Trace::call(format!("pair({}, {})", x, y), move |_: &mut Vec<i64>| Ok(x * y))
+            })
+        });
+        let pairs: Set<String> = t.cases().map(|c| c.to_string()).collect();
+        let expected: Set<String> = cartesian_set!(xs, ys).iter()
+            .map(|t| format!("pair({}, {})", tuple_field!(t, 1), tuple_field!(t, 2)))
+            .collect();
+        assert_eq!(expected, pairs);
+        assert_eq!("pair(1, 3)", names(&t)[0]);
+    }
+
+    #[test]
+    fn verdicts_and_filtering() {
+        let push = Trace::call_void("push(1)", |s: &mut Vec<i64>| {
+            s.push(1);
+            Ok(())
+        });
+        let pop = Trace::call("pop()", |s: &mut Vec<i64>| Ok(s.pop().unwrap()))
+            .pre("s <> []", |s| !s.is_empty());
+        let top = Trace::call("top()", |s: &mut Vec<i64>| Ok(s[0]));
+        let check_inv = Trace::call_void("check()", |s: &mut Vec<i64>| {
+            check(Condition::Inv, s.is_empty(), "s = []")
+        });
+
+        let t = Trace::alt(vec![pop.clone(), top, push.clone(), check_inv.clone()])
+            .then(push.or(pop).or(check_inv));
+        let results: Vec<TestResult> = t.run(Vec::new).collect();
+        let verdicts: Vec<Verdict> = results.iter().map(|r| r.verdict).collect();
+
+        assert_eq!(vec![Verdict::Inconclusive, Verdict::Filtered(1), Verdict::Filtered(1),
+                        Verdict::Failed, Verdict::Filtered(4), Verdict::Filtered(4),
+                        Verdict::Passed, Verdict::Passed, Verdict::Failed,
+                        Verdict::Passed, Verdict::Inconclusive, Verdict::Passed],
+                   verdicts);
+        assert_eq!("1: pop() = Precondition violated: s <> []; push(1) -> INCONCLUSIVE", results[0].to_string());
+        assert_eq!("2: pop(); pop() -> FILTERED by test 1", results[1].to_string());
+        assert_eq!("9: push(1) = (); check() = Invariant violated: s = [] -> FAILED", results[8].to_string());
+        assert!(results[3].results[0].contains("index out of bounds"));
+        assert_eq!(None, contracts::thread_policy());
+    }
+
+    #[test]
+    fn nested_preconditions_fail() {
+        let root = Trace::call("root()", |_: &mut ()| MATH::try_sqrt(F64(-1.0)));
+        let twice = Trace::call("twice()", |_: &mut ()| {
+            check(Condition::Pre, false, "false")?;
+            Ok(2)
+        });
+        let panics = Trace::call_void("panics()", |_: &mut ()| {
+            MATH::sqrt(F64(-1.0));
+            Ok(())
+        });
+        let outer = Trace::call_void("outer()", |_: &mut ()| Ok(())).pre("false", |_| false);
+
+        let results: Vec<TestResult> = Trace::alt(vec![root, twice, panics, outer]).run(|| ()).collect();
+        let verdicts: Vec<Verdict> = results.iter().map(|r| r.verdict).collect();
+
+        assert_eq!(vec![Verdict::Failed, Verdict::Failed, Verdict::Failed, Verdict::Inconclusive], verdicts);
+        assert_eq!("3: panics() = Precondition violated: pre_sqrt -> FAILED", results[2].to_string());
+        assert_eq!("4: outer() = Precondition violated: false -> INCONCLUSIVE", results[3].to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "only a call")]
+    fn pre_of_a_trace() {
+        op("a").then(op("b")).pre("true", |_| true);
+    }
+}
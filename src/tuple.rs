@@ -0,0 +1,152 @@
+use lib::fmt;
+
+/// VDM tuple of any arity, represented as a cons list terminated by `()`:
+/// `mk_(1, true)` is `Tuple(1, Tuple(true, ()))`. Build tuples with
+/// `mk_tuple!`, name their types with `tuple_t!` and select fields
+/// (`t.#n`) with `tuple_field!`, which handles fields 1 to 20.
+/// `cartesian_set!` yields sets of `Tuple`s.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Tuple<H, T>(pub H, pub T);
+
+/// Formats the fields of a tuple with `Display`, separated by commas.
+pub trait DisplayFields {
+    fn fmt_fields(&self, f: &mut fmt::Formatter) -> fmt::Result;
+}
+
+/// Formats the fields of a tuple with `Debug`, separated by commas.
+pub trait DebugFields {
+    fn fmt_fields(&self, f: &mut fmt::Formatter) -> fmt::Result;
+}
+
+impl DisplayFields for () {
+    fn fmt_fields(&self, _: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl DebugFields for () {
+    fn fmt_fields(&self, _: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+}
+
+impl<H: fmt::Display, T: DisplayFields> DisplayFields for Tuple<H, T> {
+    fn fmt_fields(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, ", {}", self.0)?;
+        self.1.fmt_fields(f)
+    }
+}
+
+impl<H: fmt::Debug, T: DebugFields> DebugFields for Tuple<H, T> {
+    fn fmt_fields(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, ", {:?}", self.0)?;
+        self.1.fmt_fields(f)
+    }
+}
+
+impl<H: fmt::Display, T: DisplayFields> fmt::Display for Tuple<H, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "mk_({}", self.0)?;
+        self.1.fmt_fields(f)?;
+        write!(f, ")")
+    }
+}
+
+impl<H: fmt::Debug, T: DebugFields> fmt::Debug for Tuple<H, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "mk_({:?}", self.0)?;
+        self.1.fmt_fields(f)?;
+        write!(f, ")")
+    }
+}
+
+macro_rules! from_impl {
+    ($( ($($t:ident $v:ident),+) )*) => ($(
+        impl<$($t),+> From<($($t,)+)> for tuple_t!($($t),+) {
+            fn from(($($v,)+): ($($t,)+)) -> tuple_t!($($t),+) {
+                mk_tuple!($($v),+)
+            }
+        }
+
+        impl<$($t),+> From<tuple_t!($($t),+)> for ($($t,)+) {
+            fn from(tuple: tuple_t!($($t),+)) -> ($($t,)+) {
+                let tuple_pat!($($v),+) = tuple;
+                ($($v,)+)
+            }
+        }
+    )*)
+}
+
+macro_rules! tuple_pat {
+    () => ( () );
+    ($v:ident $(, $rest:ident)*) => ( Tuple($v, tuple_pat!($($rest),*)) );
+}
+
+from_impl! {
+    (A a, B b)
+    (A a, B b, C c)
+    (A a, B b, C c, D d)
+    (A a, B b, C c, D d, E e)
+    (A a, B b, C c, D d, E e, F f)
+    (A a, B b, C c, D d, E e, F f, G g)
+    (A a, B b, C c, D d, E e, F f, G g, H h)
+}
+
+#[cfg(test)]
+mod tests {
+    use lib::ToString;
+    use Set;
+    use Map;
+
+    #[test]
+    fn equality() {
+        assert_eq!(mk_tuple!(1, 'a', true), mk_tuple!(1, 'a', true));
+        assert!(mk_tuple!(1, 'a') != mk_tuple!(1, 'b'));
+    }
+
+    #[test]
+    fn ordering() {
+        assert!(mk_tuple!(1, 3) < mk_tuple!(2, 0));
+        assert!(mk_tuple!(1, 3) < mk_tuple!(1, 4));
+    }
+
+    #[test]
+    fn field_selection() {
+        let t = mk_tuple!(1, "two", 3.0, 'f');
+
+        assert_eq!(1, tuple_field!(t, 1));
+        assert_eq!("two", tuple_field!(t, 2));
+        assert_eq!('f', tuple_field!(t, 4));
+    }
+
+    #[test]
+    fn large_arity() {
+        let t = mk_tuple!(1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14);
+        let s = set!{t, t};
+
+        assert_eq!(1, s.card());
+        assert_eq!(14, tuple_field!(t, 14));
+    }
+
+    #[test]
+    fn map_key() {
+        let m = map!{mk_tuple!(1, 'a') => "foo"};
+
+        assert_eq!("foo", m.get(mk_tuple!(1, 'a')));
+    }
+
+    #[test]
+    fn from_rust_tuple() {
+        let t: tuple_t!(i32, char) = (1, 'a').into();
+        let (n, c) = t.into();
+
+        assert_eq!(mk_tuple!(1, 'a'), t);
+        assert_eq!((1, 'a'), (n, c));
+    }
+
+    #[test]
+    fn display_formatting() {
+        assert_eq!("mk_(1, 3)", mk_tuple!(1, 3).to_string());
+        assert_eq!("mk_(\"a\", 'b')", format!("{:?}", mk_tuple!("a", 'b')));
+    }
+}
@@ -1,7 +1,60 @@
-use std::hash::{Hash, Hasher, SipHasher};
+/// Declares `$name`, an iterator over a collection that wraps the
+/// iterator `$inner` of its backing, so that the public type does not
+/// change with the `persistent` feature.
+macro_rules! wrap_iter {
+    ($(#[$attr:meta])* $name:ident<$($lt:lifetime,)* $($p:ident),+>($inner:ty) -> $item:ty
+     where $($bound:tt)+) => {
+        $(#[$attr])*
+        pub struct $name<$($lt,)* $($p),+>($inner) where $($bound)+;
+
+        impl<$($lt,)* $($p),+> Iterator for $name<$($lt,)* $($p),+> where $($bound)+ {
+            type Item = $item;
+
+            fn next(&mut self) -> Option<$item> {
+                self.0.next()
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.0.size_hint()
+            }
+        }
+
+        impl<$($lt,)* $($p),+> ExactSizeIterator for $name<$($lt,)* $($p),+> where $($bound)+ {}
+    };
+}
+
+#[cfg(feature = "std")]
+use std::collections::hash_map::DefaultHasher;
+use lib::hash::{Hash, Hasher};
 
 pub fn get_hash<T: Hash>(val: &T) -> u64 {
-    let mut state = SipHasher::new();
+    let mut state = DefaultHasher::new();
     val.hash(&mut state);
     state.finish()
 }
+
+/// 64-bit FNV-1a, as `core` has no hasher of its own. Like `std`'s
+/// `DefaultHasher::new()`, it is unkeyed, so equal values hash equally
+/// across set and map instances.
+#[cfg(not(feature = "std"))]
+struct DefaultHasher(u64);
+
+#[cfg(not(feature = "std"))]
+impl DefaultHasher {
+    fn new() -> DefaultHasher {
+        DefaultHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl Hasher for DefaultHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(0x100_0000_01b3);
+        }
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
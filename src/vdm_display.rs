@@ -0,0 +1,475 @@
+use lib::any::Any;
+use lib::cmp::Ordering;
+use lib::fmt;
+use lib::hash::Hash;
+use lib::{String, Vec};
+use num_traits::Float;
+use BigInt;
+use F64;
+use Int;
+use Nat;
+use Nat1;
+use Rat;
+use Opt;
+use Token;
+use Tuple;
+use Fixed;
+use Decimal;
+use real::Real;
+use Seq;
+use Set;
+use Map;
+use OrdSet;
+use OrdMap;
+
+/// Formats values exactly as the Overture interpreter prints them:
+/// strings and characters are quoted, sets and maps are sorted, the empty
+/// map is `{|->}` and integral reals have no fractional part.
+///
+/// `impl_quote!`, `def_record!`, `impl_record!` and `impl_union!` implement
+/// it for the generated types whenever all their fields or variants do.
+pub trait VdmDisplay {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result;
+
+    fn to_vdm_string(&self) -> String {
+        format!("{}", Vdm(self))
+    }
+
+    /// Order of the printed elements of sets and maps. Numbers and
+    /// characters compare by value; sequences, tuples and records element
+    /// by element; other values by their printed form.
+    fn vdm_cmp(&self, other: &Self) -> Ordering
+        where Self: Sized
+    {
+        self.to_vdm_string().cmp(&other.to_vdm_string())
+    }
+}
+
+/// Adapter to use `VdmDisplay` with the `format!` family of macros.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate codegen_runtime;
+/// use codegen_runtime::*;
+///
+/// fn main() {
+///     let m = map!{strseq!("b") => 2, strseq!("a") => 1};
+///
+///     assert_eq!("{\"a\" |-> 1, \"b\" |-> 2}", format!("{}", Vdm(&m)));
+/// }
+/// ```
+pub struct Vdm<'a, T: ?Sized + 'a>(pub &'a T);
+
+impl<'a, T: VdmDisplay + ?Sized> fmt::Display for Vdm<'a, T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt_vdm(f)
+    }
+}
+
+/// Prints a real like Java's `Double.toString`, except that integral
+/// values have no fractional part.
+fn fmt_real(val: f64, f: &mut fmt::Formatter) -> fmt::Result {
+    let abs = Float::abs(val);
+
+    if val.is_nan() {
+        write!(f, "NaN")
+    } else if val.is_infinite() {
+        write!(f, "{}Infinity", if val < 0.0 { "-" } else { "" })
+    } else if val == Float::trunc(val) && abs < 1e15 {
+        write!(f, "{}", val as i64)
+    } else if (1e-3..1e7).contains(&abs) {
+        write!(f, "{}", val)
+    } else {
+        let sci = format!("{:E}", val);
+
+        if sci.contains('.') {
+            write!(f, "{}", sci)
+        } else {
+            write!(f, "{}", sci.replace("E", ".0E"))
+        }
+    }
+}
+
+fn fmt_char(c: char, quote: char, f: &mut fmt::Formatter) -> fmt::Result {
+    match c {
+        '\n' => write!(f, "\\n"),
+        '\t' => write!(f, "\\t"),
+        '\r' => write!(f, "\\r"),
+        '\\' => write!(f, "\\\\"),
+        c if c == quote => write!(f, "\\{}", c),
+        c => write!(f, "{}", c),
+    }
+}
+
+fn fmt_string(text: &str, f: &mut fmt::Formatter) -> fmt::Result {
+    write!(f, "\"")?;
+
+    for c in text.chars() {
+        fmt_char(c, '"', f)?;
+    }
+
+    write!(f, "\"")
+}
+
+/// Prints `[a, b]`, or a string literal if the elements are characters.
+fn fmt_seq<'a, T, I>(items: I, f: &mut fmt::Formatter) -> fmt::Result
+    where T: VdmDisplay + Any,
+          I: Iterator<Item = &'a T>
+{
+    let items: Vec<&T> = items.collect();
+
+    if !items.is_empty() && <dyn Any>::is::<char>(items[0]) {
+        let text: String = items.iter()
+                                .filter_map(|c| <dyn Any>::downcast_ref::<char>(*c))
+                                .collect();
+        return fmt_string(&text, f);
+    }
+
+    let item_string = items.iter()
+                           .map(|i| i.to_vdm_string())
+                           .collect::<Vec<_>>()
+                           .join(", ");
+
+    write!(f, "[{}]", item_string)
+}
+
+fn fmt_set<'a, T, I>(items: I, f: &mut fmt::Formatter) -> fmt::Result
+    where T: VdmDisplay + 'a,
+          I: Iterator<Item = &'a T>
+{
+    let mut items: Vec<&T> = items.collect();
+    items.sort_by(|a, b| T::vdm_cmp(a, b));
+
+    let item_string = items.iter()
+                           .map(|i| i.to_vdm_string())
+                           .collect::<Vec<_>>()
+                           .join(", ");
+
+    write!(f, "{{{}}}", item_string)
+}
+
+fn fmt_map<'a, K, V, I>(maplets: I, f: &mut fmt::Formatter) -> fmt::Result
+    where K: VdmDisplay + 'a,
+          V: VdmDisplay + 'a,
+          I: Iterator<Item = (&'a K, &'a V)>
+{
+    let mut maplets: Vec<(&K, &V)> = maplets.collect();
+
+    if maplets.is_empty() {
+        return write!(f, "{{|->}}");
+    }
+
+    maplets.sort_by(|a, b| K::vdm_cmp(a.0, b.0));
+
+    let item_string = maplets.iter()
+                             .map(|&(k, v)| format!("{} |-> {}", Vdm(k), Vdm(v)))
+                             .collect::<Vec<_>>()
+                             .join(", ");
+
+    write!(f, "{{{}}}", item_string)
+}
+
+impl VdmDisplay for bool {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl VdmDisplay for char {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "'")?;
+        fmt_char(*self, '\'', f)?;
+        write!(f, "'")
+    }
+
+    fn vdm_cmp(&self, other: &char) -> Ordering {
+        self.cmp(other)
+    }
+}
+
+impl VdmDisplay for str {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_string(self, f)
+    }
+}
+
+impl VdmDisplay for String {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_string(self, f)
+    }
+}
+
+impl<T: VdmDisplay + ?Sized> VdmDisplay for &T {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        (**self).fmt_vdm(f)
+    }
+}
+
+macro_rules! int_impl {
+    ($($t:ty)*) => ($(
+        impl VdmDisplay for $t {
+            fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "{}", self)
+            }
+
+            fn vdm_cmp(&self, other: &$t) -> Ordering {
+                self.cmp(other)
+            }
+        }
+    )*)
+}
+
+int_impl! { i8 i16 i32 i64 isize u8 u16 u32 u64 usize Int Nat Nat1 BigInt }
+
+impl VdmDisplay for f64 {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_real(*self, f)
+    }
+
+    fn vdm_cmp(&self, other: &f64) -> Ordering {
+        self.partial_cmp(other).unwrap_or(Ordering::Equal)
+    }
+}
+
+impl VdmDisplay for F64 {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_real(self.0, f)
+    }
+
+    fn vdm_cmp(&self, other: &F64) -> Ordering {
+        self.cmp(other)
+    }
+}
+
+impl<const FRAC: u32> VdmDisplay for Fixed<FRAC> {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_real(self.to_f64(), f)
+    }
+
+    fn vdm_cmp(&self, other: &Fixed<FRAC>) -> Ordering {
+        self.cmp(other)
+    }
+}
+
+impl<const PLACES: u32> VdmDisplay for Decimal<PLACES> {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_real(self.to_f64(), f)
+    }
+
+    fn vdm_cmp(&self, other: &Decimal<PLACES>) -> Ordering {
+        self.cmp(other)
+    }
+}
+
+impl VdmDisplay for Rat {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_real(F64::from(self.clone()).0, f)
+    }
+
+    fn vdm_cmp(&self, other: &Rat) -> Ordering {
+        self.cmp(other)
+    }
+}
+
+impl VdmDisplay for Token {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+impl<T: VdmDisplay> VdmDisplay for Opt<T> {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(ref val) => val.fmt_vdm(f),
+            None => write!(f, "nil"),
+        }
+    }
+
+    fn vdm_cmp(&self, other: &Opt<T>) -> Ordering {
+        match (&self.0, &other.0) {
+            (Some(a), Some(b)) => a.vdm_cmp(b),
+            _ => self.to_vdm_string().cmp(&other.to_vdm_string()),
+        }
+    }
+}
+
+/// Prints the fields after the first one of a `mk_(...)` tuple, and
+/// orders them with `vdm_cmp`.
+pub trait VdmDisplayFields {
+    fn fmt_vdm_fields(&self, f: &mut fmt::Formatter) -> fmt::Result;
+
+    fn vdm_cmp_fields(&self, other: &Self) -> Ordering;
+}
+
+impl VdmDisplayFields for () {
+    fn fmt_vdm_fields(&self, _: &mut fmt::Formatter) -> fmt::Result {
+        Ok(())
+    }
+
+    fn vdm_cmp_fields(&self, _: &()) -> Ordering {
+        Ordering::Equal
+    }
+}
+
+impl<H: VdmDisplay, T: VdmDisplayFields> VdmDisplayFields for Tuple<H, T> {
+    fn fmt_vdm_fields(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, ", ")?;
+        self.0.fmt_vdm(f)?;
+        self.1.fmt_vdm_fields(f)
+    }
+
+    fn vdm_cmp_fields(&self, other: &Tuple<H, T>) -> Ordering {
+        self.0.vdm_cmp(&other.0).then_with(|| self.1.vdm_cmp_fields(&other.1))
+    }
+}
+
+impl<H: VdmDisplay, T: VdmDisplayFields> VdmDisplay for Tuple<H, T> {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "mk_(")?;
+        self.0.fmt_vdm(f)?;
+        self.1.fmt_vdm_fields(f)?;
+        write!(f, ")")
+    }
+
+    fn vdm_cmp(&self, other: &Tuple<H, T>) -> Ordering {
+        self.vdm_cmp_fields(other)
+    }
+}
+
+/// Sequences compare element by element, and a sequence before longer
+/// ones it starts.
+impl<T: VdmDisplay + Hash + Clone + Any> VdmDisplay for Seq<T> {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_seq(self.iter(), f)
+    }
+
+    fn vdm_cmp(&self, other: &Seq<T>) -> Ordering {
+        self.iter()
+            .zip(other.iter())
+            .map(|(a, b)| a.vdm_cmp(b))
+            .find(|&ord| ord != Ordering::Equal)
+            .unwrap_or_else(|| self.len().cmp(&other.len()))
+    }
+}
+
+/// Prints a value by its `Debug` form, for values without a `VdmDisplay`
+/// impl that are passed to the `IO` functions or `val2seq_of_char`, which
+/// took `Debug` values before.
+///
+/// # Examples
+///
+/// ```
+/// use codegen_runtime::{AsDebug, VdmDisplay};
+///
+/// assert_eq!("Some(1)", AsDebug(Some(1)).to_vdm_string());
+/// ```
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct AsDebug<T>(pub T);
+
+impl<T: fmt::Debug> VdmDisplay for AsDebug<T> {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.0)
+    }
+}
+
+impl<T: VdmDisplay + Hash + Eq + Clone> VdmDisplay for Set<T> {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_set(self.iter(), f)
+    }
+}
+
+impl<T: VdmDisplay + Ord + Clone> VdmDisplay for OrdSet<T> {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_set(self.iter(), f)
+    }
+}
+
+impl<K: VdmDisplay + Hash + Eq, V: VdmDisplay> VdmDisplay for Map<K, V> {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_map(self.iter(), f)
+    }
+}
+
+impl<K: VdmDisplay + Ord, V: VdmDisplay> VdmDisplay for OrdMap<K, V> {
+    fn fmt_vdm(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt_map(self.iter(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use contracts::{self, Policy};
+    use Seq;
+    use Set;
+    use Map;
+    use Opt;
+    use F64;
+
+    impl_quote! { Red }
+
+    def_record! {
+        Pixel { pos: tuple_t!(i64, i64), color: Opt<Red>, name :- Seq<char> }
+    }
+
+    enum Value {
+        N(i64),
+        S(Seq<char>),
+    }
+
+    impl_union!{ Value: i64 as Value::N, Seq<char> as Value::S; derive(Clone, PartialEq, Eq, Hash) }
+
+    #[test]
+    fn basic_values() {
+        assert_eq!("'a'", 'a'.to_vdm_string());
+        assert_eq!("\"a\\\"b\\n\"", strseq!("a\"b\n").to_vdm_string());
+        assert_eq!("[]", Seq::<char>::new().to_vdm_string());
+        assert_eq!("nil", Opt::<i32>::nil().to_vdm_string());
+    }
+
+    #[test]
+    fn reals() {
+        assert_eq!("1", F64(1.0).to_vdm_string());
+        assert_eq!("-2.5", F64(-2.5).to_vdm_string());
+        assert_eq!("0.001", F64(0.001).to_vdm_string());
+        assert_eq!("1.5E-4", F64(0.00015).to_vdm_string());
+        assert_eq!("1.0E20", F64(1e20).to_vdm_string());
+        assert_eq!("0.3333333333333333", (Rat::from(1i64) / Rat::from(3i64)).to_vdm_string());
+    }
+
+    #[test]
+    fn sorted_collections() {
+        assert_eq!("{2, 10, 100}", set!{100, 2, 10}.to_vdm_string());
+        assert_eq!("{\"a\", \"b\"}", set!{strseq!("b"), strseq!("a")}.to_vdm_string());
+        assert_eq!("{|->}", Map::<i32, i32>::new().to_vdm_string());
+        assert_eq!("{1 |-> {|->}, 3 |-> {2 |-> 'x'}}",
+                   map!{3 => map!{2 => 'x'}, 1 => map!{}}.to_vdm_string());
+        assert_eq!("{1.5, 2, 10}", set!{F64(10.0), F64(1.5), F64(2.0)}.to_vdm_string());
+    }
+
+    #[test]
+    fn generated_types() {
+        contracts::set_thread_policy(Some(Policy::Error));
+        let p = Pixel::new(mk_tuple!(1, -2), Opt::some(Red), strseq!("p"));
+
+        assert_eq!("mk_Pixel(mk_(1, -2), <Red>, \"p\")", p.to_vdm_string());
+        assert_eq!("[1, \"one\"]",
+                   seq![Value::from(1i64), Value::from(strseq!("one"))].to_vdm_string());
+    }
+
+    #[test]
+    fn element_wise_order() {
+        assert_eq!("{mk_(9, \"b\"), mk_(10, \"a\")}", set!{mk_tuple!(10, strseq!("a")), mk_tuple!(9, strseq!("b"))}.to_vdm_string());
+        assert_eq!("{[2], [2, 1], [10]}", set!{seq![10], seq![2, 1], seq![2]}.to_vdm_string());
+
+        let pixel = |x| Pixel::new(mk_tuple!(x, 0), Opt::nil(), strseq!("p"));
+        assert_eq!("{mk_Pixel(mk_(9, 0), nil, \"p\"), mk_Pixel(mk_(10, 0), nil, \"p\")}",
+                   set!{pixel(10), pixel(9)}.to_vdm_string());
+    }
+
+    #[test]
+    fn vdm_adapter() {
+        assert_eq!("[\"x\"]", format!("{}", Vdm(&seq![strseq!("x")])));
+    }
+}